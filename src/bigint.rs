@@ -0,0 +1,782 @@
+//!-------------------------------------------------------------------------------
+//!
+//! Arbitrary-precision integers for the Calculator.
+//!
+//! BigInt is a sign-magnitude integer with base 2^32 digits. It never
+//! overflows, so a Calculator<BigInt> always returns the exact answer.
+//! AutoInt stays an i64 and only promotes to a BigInt when the i64
+//! operation would overflow.
+//!
+//!-------------------------------------------------------------------------------
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+use std::str::FromStr;
+
+use crate::calculator::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    // Little-endian base 2^32 digits without leading zeros; empty for zero
+    magnitude: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid integer literal")
+    }
+}
+
+impl std::error::Error for ParseBigIntError {}
+
+// Drop leading zero digits so every value has exactly one representation
+fn trim(mut digits: Vec<u32>) -> Vec<u32> {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    digits
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (index, digit) in long.iter().enumerate() {
+        let total = *digit as u64 + *short.get(index).unwrap_or(&0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+// a - b for |a| >= |b|
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (index, digit) in a.iter().enumerate() {
+        let mut total = *digit as i64 - *b.get(index).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if total < 0 {
+            total += 1 << 32;
+            borrow = 1;
+        }
+        difference.push(total as u32);
+    }
+    trim(difference)
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let total = *x as u64 * *y as u64 + product[i + j] as u64 + carry;
+            product[i + j] = total as u32;
+            carry = total >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    trim(product)
+}
+
+// Divide by a single digit, returning the quotient and remainder
+fn div_rem_digit(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+    for (index, digit) in a.iter().enumerate().rev() {
+        let current = (remainder << 32) | *digit as u64;
+        quotient[index] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    (trim(quotient), remainder as u32)
+}
+
+// Shift-and-subtract long division; b must not be zero
+fn div_rem_magnitude(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, remainder) = div_rem_digit(a, b[0]);
+        return (quotient, trim(vec![remainder]));
+    }
+
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::with_capacity(b.len() + 1);
+    for bit in (0..a.len() * 32).rev() {
+        // remainder = remainder * 2 + next bit of a
+        let mut carry = (a[bit / 32] >> (bit % 32)) & 1;
+        for digit in remainder.iter_mut() {
+            let shifted = (*digit >> 31) & 1;
+            *digit = (*digit << 1) | carry;
+            carry = shifted;
+        }
+        if carry > 0 {
+            remainder.push(carry);
+        }
+        if cmp_magnitude(&remainder, b) != Ordering::Less {
+            remainder = sub_magnitude(&remainder, b);
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    (trim(quotient), remainder)
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, magnitude: Vec<u32>) -> Self {
+        let magnitude = trim(magnitude);
+        BigInt { negative: negative && !magnitude.is_empty(), magnitude }
+    }
+
+    fn from_u128_with_sign(negative: bool, mut value: u128) -> Self {
+        let mut magnitude = Vec::new();
+        while value > 0 {
+            magnitude.push(value as u32);
+            value >>= 32;
+        }
+        BigInt::from_parts(negative, magnitude)
+    }
+
+    pub fn zero() -> Self {
+        BigInt::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::from_parts(false, self.magnitude.clone())
+    }
+
+    // Magnitude as a u128, or None if it needs more than 128 bits
+    fn magnitude_u128(&self) -> Option<u128> {
+        if self.magnitude.len() > 4 {
+            return None;
+        }
+        Some(self.magnitude.iter().rev().fold(0u128, |value, digit| (value << 32) | *digit as u128))
+    }
+
+    pub fn to_i128(&self) -> Option<i128> {
+        let magnitude = self.magnitude_u128()?;
+        if self.negative {
+            0i128.checked_sub_unsigned(magnitude)
+        } else {
+            i128::try_from(magnitude).ok()
+        }
+    }
+
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.negative {
+            return None;
+        }
+        self.magnitude_u128()
+    }
+
+    // Truncating division and remainder, like the primitive integer types.
+    // Returns None when dividing by zero.
+    pub fn checked_div_rem(&self, divisor: &BigInt) -> Option<(BigInt, BigInt)> {
+        if divisor.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = div_rem_magnitude(&self.magnitude, &divisor.magnitude);
+        Some((
+            BigInt::from_parts(self.negative != divisor.negative, quotient),
+            BigInt::from_parts(self.negative, remainder),
+        ))
+    }
+
+    pub fn checked_div(&self, divisor: &BigInt) -> Option<BigInt> {
+        self.checked_div_rem(divisor).map(|(quotient, _)| quotient)
+    }
+
+    pub fn checked_rem(&self, divisor: &BigInt) -> Option<BigInt> {
+        self.checked_div_rem(divisor).map(|(_, remainder)| remainder)
+    }
+
+    // Division rounded as requested instead of truncated. Returns None when dividing by zero.
+    pub fn checked_div_rounded(&self, divisor: &BigInt, rounding: Rounding) -> Option<BigInt> {
+        let (quotient, remainder) = self.checked_div_rem(divisor)?;
+        if remainder.is_zero() {
+            return Some(quotient);
+        }
+        // The exact quotient lies strictly between quotient and quotient + step
+        let negative = self.negative != divisor.negative;
+        let step = BigInt::from(if negative { -1 } else { 1 });
+        let away_from_zero = match rounding {
+            Rounding::Floor => negative,
+            Rounding::Ceil => !negative,
+            Rounding::HalfEven => match cmp_magnitude(&add_magnitude(&remainder.magnitude, &remainder.magnitude), &divisor.magnitude) {
+                Ordering::Less => false,
+                Ordering::Greater => true,
+                Ordering::Equal => quotient.magnitude.first().is_some_and(|digit| digit & 1 == 1),
+            },
+        };
+        Some(if away_from_zero { quotient + step } else { quotient })
+    }
+
+    // Remainder that is never negative, matching i64::rem_euclid
+    pub fn checked_rem_euclid(&self, divisor: &BigInt) -> Option<BigInt> {
+        let remainder = self.checked_rem(divisor)?;
+        if remainder.is_negative() {
+            Some(remainder + divisor.abs())
+        } else {
+            Some(remainder)
+        }
+    }
+
+    // Number of significant bits in the magnitude; 0 for zero
+    pub fn bits(&self) -> u64 {
+        match self.magnitude.last() {
+            Some(top) => 32 * self.magnitude.len() as u64 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    // Exponentiation by squaring
+    pub fn pow(&self, exponent: u32) -> BigInt {
+        let (mut result, mut base, mut exponent) = (BigInt::from(1), self.clone(), exponent);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    // The degree-th root rounded toward zero.
+    // Returns None for degree 0 and for an even root of a negative number.
+    pub fn checked_root(&self, degree: u32) -> Option<BigInt> {
+        if degree == 0 || (self.negative && degree.is_multiple_of(2)) {
+            return None;
+        }
+        if self.is_zero() {
+            return Some(BigInt::zero());
+        }
+        let magnitude = self.abs();
+        let degree_big = BigInt::from(degree);
+        // Newton's method from a power of two above the root decreases monotonically to it
+        let mut root = BigInt::from(2).pow(self.bits().div_ceil(degree as u64) as u32);
+        loop {
+            let sum = &(&degree_big - &BigInt::from(1)) * &root + &magnitude / &root.pow(degree - 1);
+            let next = &sum / &degree_big;
+            if next >= root {
+                return Some(if self.negative { -root } else { root });
+            }
+            root = next;
+        }
+    }
+
+    // Greatest common divisor of the magnitudes; gcd(0, 0) is 0
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let remainder = &a % &b;
+            (a, b) = (b, remainder);
+        }
+        a
+    }
+
+    // Least common multiple of the magnitudes; 0 if either is 0
+    pub fn lcm(&self, other: &BigInt) -> BigInt {
+        if self.is_zero() || other.is_zero() {
+            return BigInt::zero();
+        }
+        &(&self.abs() / &self.gcd(other)) * &other.abs()
+    }
+
+    // The inverse in [0, modulus) by the extended Euclidean algorithm.
+    // Returns None unless modulus is positive and coprime to self.
+    pub fn checked_mod_inverse(&self, modulus: &BigInt) -> Option<BigInt> {
+        if modulus.negative || modulus.is_zero() {
+            return None;
+        }
+        let (mut old_r, mut r) = (self.checked_rem_euclid(modulus)?, modulus.clone());
+        let (mut old_x, mut x) = (BigInt::from(1), BigInt::zero());
+        while !r.is_zero() {
+            let (quotient, remainder) = old_r.checked_div_rem(&r)?;
+            (old_r, r) = (r, remainder);
+            let next = &old_x - &(&quotient * &x);
+            (old_x, x) = (x, next);
+        }
+        // old_r is now the gcd
+        if old_r != BigInt::from(1) {
+            return None;
+        }
+        old_x.checked_rem_euclid(modulus)
+    }
+
+    // self^exponent mod modulus in [0, modulus), by square-and-multiply over the exponent bits.
+    // Returns None for a negative exponent or a modulus that is not positive.
+    pub fn checked_mod_pow(&self, exponent: &BigInt, modulus: &BigInt) -> Option<BigInt> {
+        if exponent.negative || modulus.negative || modulus.is_zero() {
+            return None;
+        }
+        let mut base = self.checked_rem_euclid(modulus)?;
+        let mut result = BigInt::from(1).checked_rem_euclid(modulus)?;
+        for digit in &exponent.magnitude {
+            for bit in 0..32 {
+                if digit >> bit & 1 == 1 {
+                    result = (&result * &base).checked_rem_euclid(modulus)?;
+                }
+                base = (&base * &base).checked_rem_euclid(modulus)?;
+            }
+        }
+        Some(result)
+    }
+
+    // Logarithm rounded down. Returns None unless self > 0 and base >= 2.
+    pub fn checked_ilog(&self, base: &BigInt) -> Option<u32> {
+        if self.negative || self.is_zero() || *base < BigInt::from(2) {
+            return None;
+        }
+        let (mut value, mut log) = (self.clone(), 0);
+        while value >= *base {
+            value = &value / base;
+            log += 1;
+        }
+        Some(log)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => cmp_magnitude(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: &BigInt) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::from_parts(self.negative, add_magnitude(&self.magnitude, &rhs.magnitude));
+        }
+        match cmp_magnitude(&self.magnitude, &rhs.magnitude) {
+            Ordering::Less => BigInt::from_parts(rhs.negative, sub_magnitude(&rhs.magnitude, &self.magnitude)),
+            _ => BigInt::from_parts(self.negative, sub_magnitude(&self.magnitude, &rhs.magnitude)),
+        }
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.magnitude.clone())
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.magnitude)
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: &BigInt) -> BigInt {
+        self + &-rhs
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: &BigInt) -> BigInt {
+        BigInt::from_parts(self.negative != rhs.negative, mul_magnitude(&self.magnitude, &rhs.magnitude))
+    }
+}
+
+// Panics when dividing by zero, like the primitive integer types
+impl Div for &BigInt {
+    type Output = BigInt;
+
+    fn div(self, rhs: &BigInt) -> BigInt {
+        self.checked_div(rhs).expect("attempt to divide by zero")
+    }
+}
+
+impl Rem for &BigInt {
+    type Output = BigInt;
+
+    fn rem(self, rhs: &BigInt) -> BigInt {
+        self.checked_rem(rhs).expect("attempt to calculate the remainder with a divisor of zero")
+    }
+}
+
+// Owned operands forward to the reference implementations
+macro_rules! forward_owned_ops {
+    ($($trait:ident $method:ident),*) => {$(
+        impl $trait for BigInt {
+            type Output = BigInt;
+
+            fn $method(self, rhs: BigInt) -> BigInt {
+                (&self).$method(&rhs)
+            }
+        }
+    )*};
+}
+
+forward_owned_ops!(Add add, Sub sub, Mul mul, Div div, Rem rem);
+
+macro_rules! from_signed {
+    ($($t:ty),*) => {$(
+        impl From<$t> for BigInt {
+            fn from(value: $t) -> Self {
+                BigInt::from_u128_with_sign(value < 0, (value as i128).unsigned_abs())
+            }
+        }
+
+        impl TryFrom<&BigInt> for $t {
+            type Error = BigInt;
+
+            fn try_from(value: &BigInt) -> Result<Self, BigInt> {
+                value.to_i128().and_then(|v| <$t>::try_from(v).ok()).ok_or_else(|| value.clone())
+            }
+        }
+    )*};
+}
+
+macro_rules! from_unsigned {
+    ($($t:ty),*) => {$(
+        impl From<$t> for BigInt {
+            fn from(value: $t) -> Self {
+                BigInt::from_u128_with_sign(false, value as u128)
+            }
+        }
+
+        impl TryFrom<&BigInt> for $t {
+            type Error = BigInt;
+
+            fn try_from(value: &BigInt) -> Result<Self, BigInt> {
+                value.to_u128().and_then(|v| <$t>::try_from(v).ok()).ok_or_else(|| value.clone())
+            }
+        }
+    )*};
+}
+
+from_signed!(i8, i16, i32, i64, i128, isize);
+from_unsigned!(u8, u16, u32, u64, u128, usize);
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    // Parse an optionally signed decimal integer
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        BigInt::parse_radix(text, 10).ok_or(ParseBigIntError)
+    }
+}
+
+impl BigInt {
+    // Parse an optionally signed integer from digits in the given base, without a prefix
+    fn parse_radix(text: &str, base: u32) -> Option<Self> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(base)) {
+            return None;
+        }
+
+        let mut magnitude: Vec<u32> = Vec::new();
+        // Consume as many digits at a time as fit in a u32: value = value * base^n + chunk
+        for chunk in digits.as_bytes().chunks(u32::MAX.ilog(base) as usize) {
+            let chunk_value = u32::from_str_radix(std::str::from_utf8(chunk).unwrap(), base).unwrap();
+            let scale = base.pow(chunk.len() as u32);
+            let mut carry = chunk_value as u64;
+            for digit in magnitude.iter_mut() {
+                let total = *digit as u64 * scale as u64 + carry;
+                *digit = total as u32;
+                carry = total >> 32;
+            }
+            if carry > 0 {
+                magnitude.push(carry as u32);
+            }
+        }
+        Some(BigInt::from_parts(negative, magnitude))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return f.pad_integral(true, "", "0");
+        }
+        // Peel off nine decimal digits at a time, least significant first
+        let mut chunks = Vec::new();
+        let mut rest = self.magnitude.clone();
+        while !rest.is_empty() {
+            let (quotient, remainder) = div_rem_digit(&rest, 1_000_000_000);
+            chunks.push(remainder);
+            rest = quotient;
+        }
+        let mut digits = chunks.pop().unwrap().to_string();
+        for chunk in chunks.iter().rev() {
+            digits.push_str(&format!("{:09}", chunk));
+        }
+        f.pad_integral(!self.negative, "", &digits)
+    }
+}
+
+// Exact arithmetic on BigInt; every overflow policy gives the same answer
+fn big_operation(operation_type: OperationType, x: &BigInt, y: &BigInt) -> Option<BigInt> {
+    match operation_type {
+        OperationType::Addition => Some(x + y),
+        OperationType::Subtraction => Some(x - y),
+        OperationType::Multiplication => Some(x * y),
+        OperationType::Division => x.checked_div(y),
+        OperationType::Remainder => x.checked_rem(y),
+        OperationType::EuclidMod => x.checked_rem_euclid(y),
+        OperationType::Power => Some(x.pow(u32::try_from(y).ok()?)),
+        OperationType::Isqrt => x.checked_root(2),
+        OperationType::Icbrt => x.checked_root(3),
+        OperationType::Ilog2 => x.checked_ilog(&BigInt::from(2)).map(BigInt::from),
+        OperationType::Ilog10 => x.checked_ilog(&BigInt::from(10)).map(BigInt::from),
+        OperationType::Ilog => x.checked_ilog(y).map(BigInt::from),
+        OperationType::Gcd => Some(x.gcd(y)),
+        OperationType::Lcm => Some(x.lcm(y)),
+        OperationType::ModInverse => x.checked_mod_inverse(y),
+        OperationType::Assign => Some(x.clone()),
+        // Built from other operations by OperationType::compute
+        OperationType::Neg
+        | OperationType::Abs
+        | OperationType::Signum
+        | OperationType::Square
+        | OperationType::Factorial => None,
+        // Bit operations need a fixed width and are rejected by supports
+        OperationType::And
+        | OperationType::Or
+        | OperationType::Xor
+        | OperationType::Not
+        | OperationType::Shl
+        | OperationType::Shr
+        | OperationType::LogicalShr
+        | OperationType::RotateLeft
+        | OperationType::RotateRight => None,
+    }
+}
+
+impl CheckedInt for BigInt {
+    const NAME: &'static str = "BigInt";
+
+    fn zero() -> Self {
+        BigInt::zero()
+    }
+
+    fn apply(operation_type: OperationType, x: &Self, y: &Self, _policy: OverflowPolicy) -> Option<(Self, bool)> {
+        big_operation(operation_type, x, y).map(|value| (value, false))
+    }
+
+    fn mul_div(x: &Self, y: &Self, z: &Self, rounding: Rounding) -> Option<Self> {
+        (x * y).checked_div_rounded(z, rounding)
+    }
+
+    fn mean(values: &[Self], rounding: Rounding) -> Option<Self> {
+        let sum = values.iter().fold(BigInt::zero(), |sum, value| &sum + value);
+        sum.checked_div_rounded(&BigInt::from(values.len()), rounding)
+    }
+
+    fn to_u32(&self) -> Option<u32> {
+        u32::try_from(self).ok()
+    }
+
+    fn from_str_radix(text: &str, radix: Radix) -> Option<Self> {
+        BigInt::parse_radix(text, radix.base())
+    }
+
+    fn supports_modular() -> bool {
+        true
+    }
+
+    fn apply_modular(operation_type: OperationType, x: &Self, y: &Self, modulus: &Self) -> Option<Self> {
+        if operation_type == OperationType::Power {
+            return x.checked_mod_pow(y, modulus);
+        }
+        let (a, b) = (x.checked_rem_euclid(modulus)?, y.checked_rem_euclid(modulus)?);
+        let value = match operation_type {
+            OperationType::Addition => &a + &b,
+            OperationType::Subtraction => &a - &b,
+            OperationType::Multiplication => &a * &b,
+            OperationType::Division => &a * &b.checked_mod_inverse(modulus)?,
+            _ => return None,
+        };
+        value.checked_rem_euclid(modulus)
+    }
+
+    fn supports(operation_type: OperationType) -> bool {
+        !operation_type.is_bitwise()
+    }
+
+    // Bounds from the operand sizes, so nothing is computed before refusing
+    fn within_size_limit(operation_type: OperationType, x: &Self, y: &Self) -> bool {
+        match operation_type {
+            // |x| ** y has at most bits(x) * y bits, and 0, 1 and -1 stay small
+            OperationType::Power => {
+                x.bits() <= 1 || y.to_u32().is_none_or(|exponent| x.bits() * exponent as u64 <= MAX_RESULT_BITS)
+            }
+            // Each factor adds at most bits(n) bits
+            OperationType::Factorial => {
+                x.to_u32().is_none_or(|n| n as u64 * (32 - n.leading_zeros()) as u64 <= MAX_RESULT_BITS)
+            }
+            _ => true,
+        }
+    }
+}
+
+// An integer that is an i64 until an operation overflows, then a BigInt.
+// Every value has one representation, so equality and hashing agree with Ord.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AutoInt(Repr);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Small(i64),
+    // Only used for values outside the i64 range
+    Big(BigInt),
+}
+
+impl AutoInt {
+    pub fn is_promoted(&self) -> bool {
+        matches!(self.0, Repr::Big(_))
+    }
+
+    pub fn to_bigint(&self) -> BigInt {
+        match &self.0 {
+            Repr::Small(value) => BigInt::from(*value),
+            Repr::Big(value) => value.clone(),
+        }
+    }
+}
+
+impl From<i64> for AutoInt {
+    fn from(value: i64) -> Self {
+        AutoInt(Repr::Small(value))
+    }
+}
+
+impl From<BigInt> for AutoInt {
+    fn from(value: BigInt) -> Self {
+        match i64::try_from(&value) {
+            Ok(small) => AutoInt(Repr::Small(small)),
+            Err(big) => AutoInt(Repr::Big(big)),
+        }
+    }
+}
+
+impl Ord for AutoInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (&self.0, &other.0) {
+            (Repr::Small(x), Repr::Small(y)) => x.cmp(y),
+            _ => self.to_bigint().cmp(&other.to_bigint()),
+        }
+    }
+}
+
+impl PartialOrd for AutoInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for AutoInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        text.parse::<BigInt>().map(AutoInt::from)
+    }
+}
+
+impl fmt::Display for AutoInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Repr::Small(value) => fmt::Display::fmt(value, f),
+            Repr::Big(value) => fmt::Display::fmt(value, f),
+        }
+    }
+}
+
+impl CheckedInt for AutoInt {
+    const NAME: &'static str = "AutoInt";
+
+    fn zero() -> Self {
+        AutoInt(Repr::Small(0))
+    }
+
+    // Try the checked i64 operation first and only fall back to BigInt when it overflows
+    fn apply(operation_type: OperationType, x: &Self, y: &Self, _policy: OverflowPolicy) -> Option<(Self, bool)> {
+        if let (Repr::Small(a), Repr::Small(b)) = (&x.0, &y.0) {
+            if let Ok(value) = operation_type.perform(*a, *b) {
+                return Some((AutoInt::from(value), false));
+            }
+        }
+        big_operation(operation_type, &x.to_bigint(), &y.to_bigint()).map(|value| (AutoInt::from(value), false))
+    }
+
+    fn mul_div(x: &Self, y: &Self, z: &Self, rounding: Rounding) -> Option<Self> {
+        BigInt::mul_div(&x.to_bigint(), &y.to_bigint(), &z.to_bigint(), rounding).map(AutoInt::from)
+    }
+
+    fn mean(values: &[Self], rounding: Rounding) -> Option<Self> {
+        let values: Vec<BigInt> = values.iter().map(AutoInt::to_bigint).collect();
+        BigInt::mean(&values, rounding).map(AutoInt::from)
+    }
+
+    fn from_str_radix(text: &str, radix: Radix) -> Option<Self> {
+        BigInt::parse_radix(text, radix.base()).map(AutoInt::from)
+    }
+
+    fn supports_modular() -> bool {
+        true
+    }
+
+    fn apply_modular(operation_type: OperationType, x: &Self, y: &Self, modulus: &Self) -> Option<Self> {
+        BigInt::apply_modular(operation_type, &x.to_bigint(), &y.to_bigint(), &modulus.to_bigint()).map(AutoInt::from)
+    }
+
+    // A promoted value has no fixed width, so bit operations are not offered at all
+    fn supports(operation_type: OperationType) -> bool {
+        !operation_type.is_bitwise()
+    }
+
+    fn within_size_limit(operation_type: OperationType, x: &Self, y: &Self) -> bool {
+        BigInt::within_size_limit(operation_type, &x.to_bigint(), &y.to_bigint())
+    }
+}
@@ -1,27 +1,314 @@
-///-------------------------------------------------------------------------------
-///
-/// This is your calculator implementation task 
-/// to practice enums, structs, and methods.
-/// 
-/// Complete the implementation of the Calculator struct and its methods.
-/// 
-/// The calculator should support basic arithmetic 
-/// operations (addition, subtraction, multiplication)
-/// with overflow protection and maintain a history 
-/// of operations.
-/// 
-/// Tasks:
-/// 1. Implement the OperationType enum methods
-/// 2. Implement the Operation struct constructor
-/// 3. Implement all Calculator methods
-/// 
-///-------------------------------------------------------------------------------
-
-#[derive(Clone)]
+//!-------------------------------------------------------------------------------
+//!
+//! The Calculator and the operations it records.
+//!
+//! Calculator<T> computes binary, unary, ternary and aggregate operations
+//! on any CheckedInt type, following its overflow policy, rounding and
+//! optional modulus, and records each one in history. Entries can refer
+//! to earlier results and variables, be repeated, undone and redone, and
+//! a transaction keeps a batch of them only if all succeed.
+//!
+//!-------------------------------------------------------------------------------
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bigint::BigInt;
+use crate::expression::is_variable_name;
+use crate::number_theory::{gcd, lcm, mod_add, mod_inverse, mod_mul, mod_pow, mod_sub};
+use crate::operations::{BinaryOperation, OperationRegistry, RegisteredOperation};
+
+// How an operation behaves when its exact result does not fit in the integer type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    // Fail with CalcError::Overflow/Underflow (checked_*)
+    #[default]
+    Checked,
+    // Wrap around at the boundary of the type (wrapping_*)
+    Wrapping,
+    // Clamp to the MIN or MAX of the type (saturating_*)
+    Saturating,
+    // Wrap around and record that an overflow happened (overflowing_*)
+    Overflowing,
+}
+
+impl OverflowPolicy {
+    // Return the marker appended to the operation sign in show_history
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            OverflowPolicy::Checked => "",
+            OverflowPolicy::Wrapping => "wrap",
+            OverflowPolicy::Saturating => "sat",
+            OverflowPolicy::Overflowing => "ovf",
+        }
+    }
+    
+    // Return the stable name used when history is saved to a file
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverflowPolicy::Checked => "checked",
+            OverflowPolicy::Wrapping => "wrapping",
+            OverflowPolicy::Saturating => "saturating",
+            OverflowPolicy::Overflowing => "overflowing",
+        }
+    }
+    
+    // Look up a policy by the name returned from OverflowPolicy::name
+    pub fn from_name(name: &str) -> Option<OverflowPolicy> {
+        match name {
+            "checked" => Some(OverflowPolicy::Checked),
+            "wrapping" => Some(OverflowPolicy::Wrapping),
+            "saturating" => Some(OverflowPolicy::Saturating),
+            "overflowing" => Some(OverflowPolicy::Overflowing),
+            _ => None,
+        }
+    }
+}
+
+// How to round a result that falls between two representable values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    // Down, toward negative infinity
+    Floor,
+    // Up, toward positive infinity
+    Ceil,
+    // To the nearest value, ties to the one with an even last digit
+    #[default]
+    HalfEven,
+}
+
+impl Rounding {
+    // Return the stable name used in operation names, e.g. "ceil"
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rounding::Floor => "floor",
+            Rounding::Ceil => "ceil",
+            Rounding::HalfEven => "half_even",
+        }
+    }
+    
+    // Look up a rounding mode by the name returned from Rounding::name
+    pub fn from_name(name: &str) -> Option<Rounding> {
+        [Rounding::Floor, Rounding::Ceil, Rounding::HalfEven].into_iter().find(|rounding| rounding.name() == name)
+    }
+}
+
+// The base numbers are printed and parsed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Radix {
+    #[default]
+    Decimal,
+    Hex,
+    Octal,
+    Binary,
+}
+
+impl Radix {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Radix::Decimal => "dec",
+            Radix::Hex => "hex",
+            Radix::Octal => "oct",
+            Radix::Binary => "bin",
+        }
+    }
+    
+    // Look up a radix by the name returned from Radix::name
+    pub fn from_name(name: &str) -> Option<Radix> {
+        match name {
+            "dec" => Some(Radix::Decimal),
+            "hex" => Some(Radix::Hex),
+            "oct" => Some(Radix::Octal),
+            "bin" => Some(Radix::Binary),
+            _ => None,
+        }
+    }
+    
+    pub fn base(&self) -> u32 {
+        match self {
+            Radix::Decimal => 10,
+            Radix::Hex => 16,
+            Radix::Octal => 8,
+            Radix::Binary => 2,
+        }
+    }
+    
+    // The literal prefix, e.g. "0x"; decimal literals have none
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Radix::Decimal => "",
+            Radix::Hex => "0x",
+            Radix::Octal => "0o",
+            Radix::Binary => "0b",
+        }
+    }
+    
+    // Split a literal such as "0xff" into its radix and digits; unprefixed text is decimal
+    pub fn split_prefix(text: &str) -> (Radix, &str) {
+        [Radix::Hex, Radix::Octal, Radix::Binary]
+            .into_iter()
+            .find_map(|radix| text.strip_prefix(radix.prefix()).map(|digits| (radix, digits)))
+            .unwrap_or((Radix::Decimal, text))
+    }
+    
+    // Prefix and digits of value, zero-padded to at least min_digits digits
+    fn format_digits(&self, value: u128, min_digits: usize) -> String {
+        let digits = match self {
+            Radix::Decimal => value.to_string(),
+            Radix::Hex => format!("{:x}", value),
+            Radix::Octal => format!("{:o}", value),
+            Radix::Binary => format!("{:b}", value),
+        };
+        format!("{}{:0>width$}", self.prefix(), digits, width = min_digits)
+    }
+}
+
+// Widths at which negative numbers can be shown in two's complement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitWidth {
+    Bits8,
+    Bits16,
+    Bits32,
+    Bits64,
+}
+
+impl BitWidth {
+    pub fn bits(&self) -> u32 {
+        match self {
+            BitWidth::Bits8 => 8,
+            BitWidth::Bits16 => 16,
+            BitWidth::Bits32 => 32,
+            BitWidth::Bits64 => 64,
+        }
+    }
+    
+    pub fn from_bits(bits: u32) -> Option<BitWidth> {
+        match bits {
+            8 => Some(BitWidth::Bits8),
+            16 => Some(BitWidth::Bits16),
+            32 => Some(BitWidth::Bits32),
+            64 => Some(BitWidth::Bits64),
+            _ => None,
+        }
+    }
+    
+    // Digits needed for every bit in the radix; decimal is never padded
+    fn digits(&self, radix: Radix) -> usize {
+        let bits = self.bits() as usize;
+        match radix {
+            Radix::Decimal => 0,
+            Radix::Hex => bits / 4,
+            Radix::Octal => bits.div_ceil(3),
+            Radix::Binary => bits,
+        }
+    }
+}
+
+// How show_history prints numbers. Negative values are written with a sign, e.g. -0x10,
+// unless twos_complement is set: then they show their bits at that width, e.g. 0xf0 at 8 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NumberFormat {
+    pub radix: Radix,
+    pub twos_complement: Option<BitWidth>,
+}
+
+impl From<Radix> for NumberFormat {
+    fn from(radix: Radix) -> Self {
+        NumberFormat { radix, twos_complement: None }
+    }
+}
+
+impl NumberFormat {
+    // Format an integer from its sign and its bits sign-extended to 128.
+    // Two's complement is zero-padded to the full width; values outside the width keep their sign.
+    pub fn format_integer(&self, negative: bool, bits: u128) -> String {
+        if let Some(width) = self.twos_complement {
+            let fits = if negative {
+                bits as i128 >= -(1i128 << (width.bits() - 1))
+            } else {
+                bits < 1u128 << width.bits()
+            };
+            if fits {
+                let mask = (1u128 << width.bits()) - 1;
+                return self.radix.format_digits(bits & mask, width.digits(self.radix));
+            }
+        }
+        if negative {
+            format!("-{}", self.radix.format_digits(bits.wrapping_neg(), 0))
+        } else {
+            self.radix.format_digits(bits, 0)
+        }
+    }
+}
+
+// Parse a literal such as "-42", "0xFFFF_FFFF", "0o755" or "0b1010_0001".
+// Underscores only separate digits and are ignored.
+pub fn parse_literal<T: CheckedInt>(text: &str) -> Option<T> {
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text),
+    };
+    let (radix, digits) = Radix::split_prefix(unsigned);
+    let digits: String = digits.chars().filter(|&c| c != '_').collect();
+    // from_str_radix would accept a second sign after the prefix
+    if digits.is_empty() || unsigned.starts_with('_') || digits.starts_with(['+', '-']) {
+        return None;
+    }
+    T::from_str_radix(&format!("{}{}", sign, digits), radix)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OperationType {
     Addition,
     Subtraction,
-    Multiplication
+    Multiplication,
+    Division,
+    Remainder,
+    EuclidMod,
+    And,
+    Or,
+    Xor,
+    // Shift left; the amount must be below the bit width
+    Shl,
+    // Arithmetic shift right, which copies the sign bit for signed types
+    Shr,
+    // Logical shift right, which always shifts in zeros
+    LogicalShr,
+    RotateLeft,
+    RotateRight,
+    // Bitwise complement of the first operand; the second operand is unused
+    Not,
+    // The first operand raised to the second, which must be a u32
+    Power,
+    // Integer square root, rounded down; fails for negative numbers
+    Isqrt,
+    // Integer cube root, rounded toward zero
+    Icbrt,
+    // Base-2 logarithm rounded down; fails for non-positive numbers
+    Ilog2,
+    Ilog10,
+    // Logarithm in the base given by the second operand, which must be at least 2
+    Ilog,
+    // Greatest common divisor of the magnitudes, never negative
+    Gcd,
+    // Least common multiple of the magnitudes, never negative
+    Lcm,
+    // Inverse of the first operand modulo the second, which must be positive
+    ModInverse,
+    // Store the first operand in the variable named by the Operation; the result is the operand
+    Assign,
+    // Negation; fails for MIN, and for any positive value of an unsigned type
+    Neg,
+    // Absolute value; fails for MIN
+    Abs,
+    // -1, 0 or 1 depending on the sign of the first operand
+    Signum,
+    // The first operand multiplied by itself
+    Square,
+    // n!, for n from 0 up to u32::MAX
+    Factorial,
 }
 
 impl OperationType {
@@ -31,113 +318,2160 @@ impl OperationType {
             OperationType::Addition => "+",
             OperationType::Subtraction => "-",
             OperationType::Multiplication => "*",
+            OperationType::Division => "/",
+            OperationType::Remainder => "%",
+            OperationType::EuclidMod => "mod",
+            OperationType::And => "&",
+            OperationType::Or => "|",
+            OperationType::Xor => "^",
+            OperationType::Shl => "<<",
+            OperationType::Shr => ">>",
+            OperationType::LogicalShr => ">>>",
+            OperationType::RotateLeft => "rotl",
+            OperationType::RotateRight => "rotr",
+            OperationType::Not => "!",
+            OperationType::Power => "**",
+            OperationType::Isqrt => "isqrt",
+            OperationType::Icbrt => "icbrt",
+            OperationType::Ilog2 => "ilog2",
+            OperationType::Ilog10 => "ilog10",
+            OperationType::Ilog => "ilog",
+            OperationType::Gcd => "gcd",
+            OperationType::Lcm => "lcm",
+            OperationType::ModInverse => "mod_inverse",
+            OperationType::Assign => "=",
+            OperationType::Neg => "neg",
+            OperationType::Abs => "abs",
+            OperationType::Signum => "signum",
+            OperationType::Square => "square",
+            OperationType::Factorial => "!",
+        }
+    }
+    
+    // Return the stable name used when history is saved to a file
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperationType::Addition => "addition",
+            OperationType::Subtraction => "subtraction",
+            OperationType::Multiplication => "multiplication",
+            OperationType::Division => "division",
+            OperationType::Remainder => "remainder",
+            OperationType::EuclidMod => "euclid_mod",
+            OperationType::And => "and",
+            OperationType::Or => "or",
+            OperationType::Xor => "xor",
+            OperationType::Shl => "shl",
+            OperationType::Shr => "shr",
+            OperationType::LogicalShr => "logical_shr",
+            OperationType::RotateLeft => "rotate_left",
+            OperationType::RotateRight => "rotate_right",
+            OperationType::Not => "not",
+            OperationType::Power => "pow",
+            OperationType::Isqrt => "isqrt",
+            OperationType::Icbrt => "icbrt",
+            OperationType::Ilog2 => "ilog2",
+            OperationType::Ilog10 => "ilog10",
+            OperationType::Ilog => "ilog",
+            OperationType::Gcd => "gcd",
+            OperationType::Lcm => "lcm",
+            OperationType::ModInverse => "mod_inverse",
+            OperationType::Assign => "assign",
+            OperationType::Neg => "neg",
+            OperationType::Abs => "abs",
+            OperationType::Signum => "signum",
+            OperationType::Square => "square",
+            OperationType::Factorial => "factorial",
+        }
+    }
+    
+    // Look up an operation by the name returned from OperationType::name.
+    // Registered operations are looked up in an OperationRegistry instead.
+    pub fn from_name(name: &str) -> Option<OperationType> {
+        match name {
+            "addition" => Some(OperationType::Addition),
+            "subtraction" => Some(OperationType::Subtraction),
+            "multiplication" => Some(OperationType::Multiplication),
+            "division" => Some(OperationType::Division),
+            "remainder" => Some(OperationType::Remainder),
+            "euclid_mod" => Some(OperationType::EuclidMod),
+            "and" => Some(OperationType::And),
+            "or" => Some(OperationType::Or),
+            "xor" => Some(OperationType::Xor),
+            "shl" => Some(OperationType::Shl),
+            "shr" => Some(OperationType::Shr),
+            "logical_shr" => Some(OperationType::LogicalShr),
+            "rotate_left" => Some(OperationType::RotateLeft),
+            "rotate_right" => Some(OperationType::RotateRight),
+            "not" => Some(OperationType::Not),
+            "pow" => Some(OperationType::Power),
+            "isqrt" => Some(OperationType::Isqrt),
+            "icbrt" => Some(OperationType::Icbrt),
+            "ilog2" => Some(OperationType::Ilog2),
+            "ilog10" => Some(OperationType::Ilog10),
+            "ilog" => Some(OperationType::Ilog),
+            "gcd" => Some(OperationType::Gcd),
+            "lcm" => Some(OperationType::Lcm),
+            "mod_inverse" => Some(OperationType::ModInverse),
+            "assign" => Some(OperationType::Assign),
+            "neg" => Some(OperationType::Neg),
+            "abs" => Some(OperationType::Abs),
+            "signum" => Some(OperationType::Signum),
+            "square" => Some(OperationType::Square),
+            "factorial" => Some(OperationType::Factorial),
+            _ => None,
+        }
+    }
+    
+    // Look up an operation by the sign returned from get_sign; "~" is Not as well.
+    // "!" is Not rather than Factorial, which is only written after its operand.
+    pub fn from_sign(sign: &str) -> Option<OperationType> {
+        match sign {
+            "+" => Some(OperationType::Addition),
+            "-" => Some(OperationType::Subtraction),
+            "*" => Some(OperationType::Multiplication),
+            "/" => Some(OperationType::Division),
+            "%" => Some(OperationType::Remainder),
+            "mod" => Some(OperationType::EuclidMod),
+            "&" => Some(OperationType::And),
+            "|" => Some(OperationType::Or),
+            "^" => Some(OperationType::Xor),
+            "<<" => Some(OperationType::Shl),
+            ">>" => Some(OperationType::Shr),
+            ">>>" => Some(OperationType::LogicalShr),
+            "rotl" => Some(OperationType::RotateLeft),
+            "rotr" => Some(OperationType::RotateRight),
+            "!" | "~" => Some(OperationType::Not),
+            "**" => Some(OperationType::Power),
+            "isqrt" => Some(OperationType::Isqrt),
+            "icbrt" => Some(OperationType::Icbrt),
+            "ilog2" => Some(OperationType::Ilog2),
+            "ilog10" => Some(OperationType::Ilog10),
+            "ilog" => Some(OperationType::Ilog),
+            "gcd" => Some(OperationType::Gcd),
+            "lcm" => Some(OperationType::Lcm),
+            "mod_inverse" => Some(OperationType::ModInverse),
+            "=" => Some(OperationType::Assign),
+            "neg" => Some(OperationType::Neg),
+            "abs" => Some(OperationType::Abs),
+            "signum" => Some(OperationType::Signum),
+            "square" => Some(OperationType::Square),
+            _ => None,
+        }
+    }
+    
+    // Bit operations only make sense for fixed-width integers
+    pub fn is_bitwise(&self) -> bool {
+        matches!(
+            self,
+            OperationType::And
+                | OperationType::Or
+                | OperationType::Xor
+                | OperationType::Not
+                | OperationType::Shl
+                | OperationType::Shr
+                | OperationType::LogicalShr
+                | OperationType::RotateLeft
+                | OperationType::RotateRight
+        )
+    }
+    
+    // Shifts and rotations take a bit count as their second operand
+    pub fn is_shift(&self) -> bool {
+        matches!(
+            self,
+            OperationType::Shl
+                | OperationType::Shr
+                | OperationType::LogicalShr
+                | OperationType::RotateLeft
+                | OperationType::RotateRight
+        )
+    }
+    
+    // Unary operations only use the first operand
+    pub fn is_unary(&self) -> bool {
+        matches!(
+            self,
+            OperationType::Not
+                | OperationType::Isqrt
+                | OperationType::Icbrt
+                | OperationType::Ilog2
+                | OperationType::Ilog10
+                | OperationType::Assign
+                | OperationType::Neg
+                | OperationType::Abs
+                | OperationType::Signum
+                | OperationType::Square
+                | OperationType::Factorial
+        )
+    }
+    
+    // Binary operations written like a call, e.g. "ilog(100, 10)", instead of between their operands
+    pub fn is_function(&self) -> bool {
+        matches!(self, OperationType::Ilog | OperationType::Gcd | OperationType::Lcm | OperationType::ModInverse)
+    }
+    
+    // Roots, logarithms and number theory only make sense for integer types
+    pub fn is_integer_only(&self) -> bool {
+        matches!(
+            self,
+            OperationType::Isqrt
+                | OperationType::Icbrt
+                | OperationType::Ilog2
+                | OperationType::Ilog10
+                | OperationType::Ilog
+                | OperationType::Gcd
+                | OperationType::Lcm
+                | OperationType::ModInverse
+                | OperationType::Factorial
+        )
+    }
+    
+    // Return the operation without its result, e.g. "2 ** 10", "!5", "isqrt(16)" or "5!"
+    pub fn describe<T: fmt::Display>(&self, x: &T, y: &T) -> String {
+        self.describe_with_sign(self.get_sign(), x, y)
+    }
+    
+    // Same as describe, with sign written in place of the operation sign
+    fn describe_with_sign<T: fmt::Display>(&self, sign: &str, x: &T, y: &T) -> String {
+        if self.is_function() {
+            format!("{}({}, {})", sign, x, y)
+        } else if *self == OperationType::Factorial {
+            format!("{}{}", x, sign)
+        } else if self.is_unary() && sign.ends_with(char::is_alphanumeric) {
+            format!("{}({})", sign, x)
+        } else if self.is_unary() {
+            format!("{}{}", sign, x)
+        } else {
+            format!("{} {} {}", x, sign, y)
+        }
+    }
+    
+    // Division, remainder and modulo fail on a zero divisor under every policy
+    pub fn is_division(&self) -> bool {
+        matches!(self, OperationType::Division | OperationType::Remainder | OperationType::EuclidMod)
+    }
+    
+    // Perform the operation on two numbers with overflow protection
+    pub fn perform<T: CheckedInt>(&self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.compute(&x, &y, OverflowPolicy::Checked).map(|(value, _)| value)
+    }
+    
+    // Perform the operation following the given overflow policy.
+    // Returns the value and, for OverflowPolicy::Overflowing, whether it wrapped around.
+    // Division by zero is an error under every policy.
+    pub fn perform_with_policy<T: CheckedInt>(&self, x: T, y: T, policy: OverflowPolicy) -> Result<(T, bool), CalcError<T>> {
+        self.compute(&x, &y, policy)
+    }
+    
+    // Same as perform, but discards the reason for a failure
+    pub fn checked_perform<T: CheckedInt>(&self, x: T, y: T) -> Option<T> {
+        self.perform(x, y).ok()
+    }
+    
+    // Perform the operation on any CheckedInt following the given overflow policy
+    pub fn compute<T: CheckedInt>(&self, x: &T, y: &T, policy: OverflowPolicy) -> Result<(T, bool), CalcError<T>> {
+        self.compute_rounded(x, y, policy, Rounding::default())
+    }
+    
+    // Same as compute, rounding results that need it as requested
+    pub fn compute_rounded<T: CheckedInt>(
+        &self,
+        x: &T,
+        y: &T,
+        policy: OverflowPolicy,
+        rounding: Rounding,
+    ) -> Result<(T, bool), CalcError<T>> {
+        if !T::supports(*self) {
+            return Err(CalcError::Unsupported { operation: self.name().to_string() });
+        }
+        if self.is_division() && *y == T::zero() {
+            return Err(CalcError::DivisionByZero { lhs: x.clone(), operation: *self });
+        }
+        if !self.in_domain(x, y) {
+            return Err(CalcError::InvalidArgument { lhs: x.clone(), rhs: y.clone(), operation: *self });
+        }
+        if !T::within_size_limit(*self, x, y) {
+            return Err(CalcError::TooLarge { lhs: x.clone(), rhs: y.clone(), operation: *self });
+        }
+        self.apply(x, y, policy, rounding).ok_or_else(|| self.failure(x, y))
+    }
+    
+    // Apply the operation to operands in its domain. Neg, Abs, Signum, Square and Factorial
+    // are built from Subtraction and Multiplication, so every backend has them under every policy.
+    fn apply<T: CheckedInt>(&self, x: &T, y: &T, policy: OverflowPolicy, rounding: Rounding) -> Option<(T, bool)> {
+        let zero = T::zero();
+        match self {
+            OperationType::Neg => T::apply(OperationType::Subtraction, &zero, x, policy),
+            OperationType::Abs if *x < zero => T::apply(OperationType::Subtraction, &zero, x, policy),
+            OperationType::Abs => Some((x.clone(), false)),
+            OperationType::Signum => match x.cmp(&zero) {
+                Ordering::Less => T::apply(OperationType::Subtraction, &zero, &T::one(), policy),
+                Ordering::Equal => Some((zero, false)),
+                Ordering::Greater => Some((T::one(), false)),
+            },
+            OperationType::Square => T::apply_rounded(OperationType::Multiplication, x, x, policy, rounding),
+            OperationType::Factorial => factorial(x, policy),
+            _ => T::apply_rounded(*self, x, y, policy, rounding),
         }
     }
     
-    // Perform the operation on two i64 numbers with overflow protection
-    pub fn perform(&self, x: i64, y: i64) -> Option<i64> {
+    // Whether the operands are valid for the operation; invalid ones fail under every policy
+    fn in_domain<T: CheckedInt>(&self, x: &T, y: &T) -> bool {
+        let zero = T::zero();
         match self {
-            OperationType::Addition => x.checked_add(y),
-            OperationType::Subtraction => x.checked_sub(y),
-            OperationType::Multiplication => x.checked_mul(y),
+            OperationType::Power => y.to_u32().is_some(),
+            OperationType::Isqrt => *x >= zero,
+            OperationType::Ilog2 | OperationType::Ilog10 => *x > zero,
+            // A base too large for u32 is fine, any base below 2 is not
+            OperationType::Ilog => *x > zero && y.to_u32().map_or(*y > zero, |base| base >= 2),
+            OperationType::ModInverse => *y > zero,
+            OperationType::Factorial => *x >= zero && x.to_u32().is_some(),
+            _ => true,
+        }
+    }
+    
+    // Perform the operation in Z/modulus: addition, subtraction, multiplication, division
+    // by a modular inverse, power with an unreduced exponent, negation and square.
+    // Overflow policies do not apply.
+    pub fn compute_modular<T: CheckedInt>(&self, x: &T, y: &T, modulus: &T) -> Result<T, CalcError<T>> {
+        let zero = T::zero();
+        match self {
+            OperationType::Neg => return OperationType::Subtraction.compute_modular(&zero, x, modulus),
+            OperationType::Square => return OperationType::Multiplication.compute_modular(x, x, modulus),
+            _ => {}
+        }
+        let modular = matches!(
+            self,
+            OperationType::Addition
+                | OperationType::Subtraction
+                | OperationType::Multiplication
+                | OperationType::Division
+                | OperationType::Power
+        );
+        if !modular || !T::supports_modular() {
+            return Err(CalcError::Unsupported { operation: format!("{} in modular mode", self.name()) });
+        }
+        if *modulus <= zero {
+            return Err(CalcError::InvalidModulus { modulus: modulus.clone() });
+        }
+        if *self == OperationType::Power && *y < zero {
+            return Err(CalcError::InvalidArgument { lhs: x.clone(), rhs: y.clone(), operation: *self });
+        }
+        T::apply_modular(*self, x, y, modulus).ok_or_else(|| {
+            // Only division fails: zero and other non-units have no inverse
+            let residue = T::apply_modular(OperationType::Addition, y, &zero, modulus);
+            if residue == Some(zero) {
+                CalcError::DivisionByZero { lhs: x.clone(), operation: *self }
+            } else {
+                CalcError::NotInvertible { value: y.clone(), modulus: modulus.clone() }
+            }
+        })
+    }
+    
+    // Explain why an operation with a non-zero divisor returned None
+    fn failure<T: CheckedInt>(&self, x: &T, y: &T) -> CalcError<T> {
+        let operation = *self;
+        let zero = T::zero();
+        let (lhs, rhs) = (x.clone(), y.clone());
+        let too_large = match self {
+            OperationType::Addition => *y > zero,
+            OperationType::Subtraction => *y < zero,
+            // For integers the only failing division is MIN / -1, whose quotient is MAX + 1
+            OperationType::Multiplication | OperationType::Division => (*x < zero) == (*y < zero),
+            // Only an odd power of a negative number is too small
+            OperationType::Power => *x > zero || y.to_u32().is_some_and(|exponent| exponent % 2 == 0),
+            OperationType::Remainder | OperationType::EuclidMod => true,
+            // Only MIN fails, and for unsigned types any positive value
+            OperationType::Neg => *x < zero,
+            OperationType::Abs | OperationType::Square | OperationType::Factorial => true,
+            OperationType::Shl
+            | OperationType::Shr
+            | OperationType::LogicalShr
+            | OperationType::RotateLeft
+            | OperationType::RotateRight => return CalcError::ShiftOutOfRange { lhs, rhs, operation },
+            OperationType::ModInverse => return CalcError::NotInvertible { value: lhs, modulus: rhs },
+            // Only a magnitude just above MAX, such as gcd(i64::MIN, 0), fails
+            OperationType::Gcd | OperationType::Lcm => true,
+            // These never fail on operands in their domain
+            OperationType::And
+            | OperationType::Or
+            | OperationType::Xor
+            | OperationType::Not
+            | OperationType::Isqrt
+            | OperationType::Icbrt
+            | OperationType::Ilog2
+            | OperationType::Ilog10
+            | OperationType::Ilog
+            | OperationType::Assign
+            | OperationType::Signum => true,
+        };
+        if too_large {
+            CalcError::Overflow { lhs, rhs, operation }
+        } else {
+            CalcError::Underflow { lhs, rhs, operation }
         }
     }
 }
 
-#[derive(Clone)]
-pub struct Operation {
-    pub first_num: i64,
-    pub second_num: i64,
-    pub operation_type: OperationType
+// Operations of the form x * y / z whose product is computed without overflowing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TernaryOperationType {
+    MulDiv(Rounding),
+    // amount * bps / 10_000
+    ApplyBps(Rounding),
+    // amount * percent / 100
+    Percent(Rounding),
 }
 
-impl Operation {
-    // Create a new Operation with the given parameters
-    pub fn new(first_num: i64, second_num: i64, operation_type: OperationType) -> Self {
-        Operation {
-            first_num,
-            second_num,
-            operation_type,
+impl TernaryOperationType {
+    // Return the stable name used in history, e.g. "mul_div" or "apply_bps_ceil".
+    // Floor is the usual rounding for fee math, so it has no suffix.
+    pub fn name(&self) -> String {
+        let (base, rounding) = match self {
+            TernaryOperationType::MulDiv(rounding) => ("mul_div", rounding),
+            TernaryOperationType::ApplyBps(rounding) => ("apply_bps", rounding),
+            TernaryOperationType::Percent(rounding) => ("percent", rounding),
+        };
+        match rounding {
+            Rounding::Floor => base.to_string(),
+            _ => format!("{}_{}", base, rounding.name()),
+        }
+    }
+    
+    // Look up an operation by the name returned from TernaryOperationType::name
+    pub fn from_name(name: &str) -> Option<TernaryOperationType> {
+        [Rounding::Floor, Rounding::Ceil, Rounding::HalfEven]
+            .into_iter()
+            .flat_map(|rounding| {
+                [
+                    TernaryOperationType::MulDiv(rounding),
+                    TernaryOperationType::ApplyBps(rounding),
+                    TernaryOperationType::Percent(rounding),
+                ]
+            })
+            .find(|operation_type| operation_type.name() == name)
+    }
+    
+    pub fn rounding(&self) -> Rounding {
+        match self {
+            TernaryOperationType::MulDiv(rounding)
+            | TernaryOperationType::ApplyBps(rounding)
+            | TernaryOperationType::Percent(rounding) => *rounding,
+        }
+    }
+    
+    // The divisor implied by the operation, if it is not given explicitly
+    pub fn fixed_divisor(&self) -> Option<&'static str> {
+        match self {
+            TernaryOperationType::MulDiv(_) => None,
+            TernaryOperationType::ApplyBps(_) => Some("10000"),
+            TernaryOperationType::Percent(_) => Some("100"),
+        }
+    }
+    
+    // Format the operation as a call, e.g. "mul_div(1000, 3, 7)" or "apply_bps(1000, 30)"
+    pub fn describe<T: fmt::Display>(&self, x: &T, y: &T, z: &T) -> String {
+        match self.fixed_divisor() {
+            Some(_) => format!("{}({}, {})", self.name(), x, y),
+            None => format!("{}({}, {}, {})", self.name(), x, y, z),
+        }
+    }
+    
+    // Compute x * y / z with the operation's rounding
+    pub fn compute<T: CheckedInt>(&self, x: &T, y: &T, z: &T) -> Result<T, CalcError<T>> {
+        let operands = [x.clone(), y.clone(), z.clone()];
+        if *z == T::zero() {
+            return Err(CalcError::TernaryDivisionByZero { operands, operation: *self });
         }
+        T::mul_div(x, y, z, self.rounding()).ok_or(CalcError::TernaryOverflow { operands, operation: *self })
     }
 }
 
-pub struct Calculator {
-    pub history: Vec<Operation>
+// Operations over a list of any length, recorded as a single history entry.
+// They always use checked arithmetic, like the ternary operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateOperationType {
+    Sum,
+    Product,
+    Min,
+    Max,
+    // The sum divided by the number of values
+    Mean(Rounding),
+    // The middle value, or the mean of the two middle values of an even-length list
+    Median(Rounding),
 }
 
-impl Calculator {
-    // Create a new Calculator with empty history
-    pub fn new() -> Self {
-        Calculator {
-            history: Vec::new(),
+impl AggregateOperationType {
+    // Return the stable name used in history, e.g. "sum" or "mean_ceil".
+    // Floor rounding has no suffix, as for ternary operations.
+    pub fn name(&self) -> String {
+        let (base, rounding) = match self {
+            AggregateOperationType::Sum => return "sum".to_string(),
+            AggregateOperationType::Product => return "product".to_string(),
+            AggregateOperationType::Min => return "min".to_string(),
+            AggregateOperationType::Max => return "max".to_string(),
+            AggregateOperationType::Mean(rounding) => ("mean", rounding),
+            AggregateOperationType::Median(rounding) => ("median", rounding),
+        };
+        match rounding {
+            Rounding::Floor => base.to_string(),
+            _ => format!("{}_{}", base, rounding.name()),
         }
     }
     
-    // Perform addition and store successful operations in history
-    pub fn addition(&mut self, x: i64, y: i64) -> Option<i64> {
-        let result = OperationType::Addition.perform(x, y);
-        if result.is_some() {
-            let operation = Operation::new(x, y, OperationType::Addition);
-            self.history.push(operation);
-        }
-        result
+    // Look up an operation by the name returned from AggregateOperationType::name
+    pub fn from_name(name: &str) -> Option<AggregateOperationType> {
+        [Rounding::Floor, Rounding::Ceil, Rounding::HalfEven]
+            .into_iter()
+            .flat_map(|rounding| [AggregateOperationType::Mean(rounding), AggregateOperationType::Median(rounding)])
+            .chain([
+                AggregateOperationType::Sum,
+                AggregateOperationType::Product,
+                AggregateOperationType::Min,
+                AggregateOperationType::Max,
+            ])
+            .find(|operation_type| operation_type.name() == name)
     }
     
-    // Perform subtraction and store successful operations in history
-    pub fn subtraction(&mut self, x: i64, y: i64) -> Option<i64> {
-        let result = OperationType::Subtraction.perform(x, y);
-        if result.is_some() {
-            let operation = Operation::new(x, y, OperationType::Subtraction);
-            self.history.push(operation);
+    // Format the operation as a call. Lists longer than eight values are shortened to
+    // their first three and last two, e.g. "sum(1, 2, 3, ... 95 more, 99, 100)".
+    pub fn describe<T: fmt::Display>(&self, values: &[T]) -> String {
+        let mut shown: Vec<String> = values.iter().map(ToString::to_string).collect();
+        if shown.len() > 8 {
+            let hidden = shown.len() - 5;
+            shown.splice(3..shown.len() - 2, [format!("... {} more", hidden)]);
         }
-        result
+        format!("{}({})", self.name(), shown.join(", "))
     }
     
-    // Perform multiplication and store successful operations in history
-    pub fn multiplication(&mut self, x: i64, y: i64) -> Option<i64> {
-        let result = OperationType::Multiplication.perform(x, y);
-        if result.is_some() {
-            let operation = Operation::new(x, y, OperationType::Multiplication);
-            self.history.push(operation);
+    // Compute the operation over the values. A sum or product fails at the first
+    // operand that takes it out of range, even if later operands would bring it back.
+    pub fn compute<T: CheckedInt>(&self, values: &[T]) -> Result<T, CalcError<T>> {
+        let overflow = |position| CalcError::AggregateOverflow { operands: values.to_vec(), position, operation: *self };
+        let too_large = || overflow(None);
+        let fold = |operation_type, start: T| {
+            values.iter().enumerate().try_fold(start, |total, (position, value)| {
+                T::apply(operation_type, &total, value, OverflowPolicy::Checked)
+                    .map(|(total, _)| total)
+                    .ok_or_else(|| overflow(Some(position)))
+            })
+        };
+        match self {
+            AggregateOperationType::Sum => return fold(OperationType::Addition, T::zero()),
+            AggregateOperationType::Product => return fold(OperationType::Multiplication, T::one()),
+            _ if values.is_empty() => return Err(CalcError::NoOperands { operation: *self }),
+            _ => {}
         }
-        result
+        match self {
+            AggregateOperationType::Min => Ok(values.iter().min().cloned().expect("values is not empty")),
+            AggregateOperationType::Max => Ok(values.iter().max().cloned().expect("values is not empty")),
+            // The sum may not fit in T even though the mean does
+            AggregateOperationType::Mean(rounding) => T::mean(values, *rounding).ok_or_else(too_large),
+            AggregateOperationType::Median(rounding) => {
+                let mut sorted = values.to_vec();
+                sorted.sort();
+                let middle = sorted.len() / 2;
+                if sorted.len() % 2 == 1 {
+                    return Ok(sorted.swap_remove(middle));
+                }
+                midpoint(&sorted[middle - 1], &sorted[middle], *rounding).ok_or_else(too_large)
+            }
+            AggregateOperationType::Sum | AggregateOperationType::Product => unreachable!("computed above"),
+        }
+    }
+}
+
+// (a + b) / 2 for a <= b, rounded as requested. If a + b does not fit, a and b have
+// the same sign, so b - a does and a + (b - a) / 2 rounded down is the floor of the
+// midpoint; it is a tie, to round up or to even, exactly when b - a is odd.
+fn midpoint<T: CheckedInt>(a: &T, b: &T, rounding: Rounding) -> Option<T> {
+    let one = T::one();
+    let add = |x: &T, y: &T| T::apply(OperationType::Addition, x, y, OverflowPolicy::Checked).map(|(value, _)| value);
+    let two = add(&one, &one)?;
+    if let Some(sum) = add(a, b) {
+        return T::mul_div(&sum, &one, &two, rounding);
     }
+    let (difference, _) = T::apply(OperationType::Subtraction, b, a, OverflowPolicy::Checked)?;
+    let floor = add(a, &T::mul_div(&difference, &one, &two, Rounding::Floor)?)?;
+    let odd = |value: &T| {
+        T::apply(OperationType::Remainder, value, &two, OverflowPolicy::Checked).is_some_and(|(rest, _)| rest != T::zero())
+    };
+    let up = match rounding {
+        Rounding::Floor => false,
+        Rounding::Ceil => odd(&difference),
+        Rounding::HalfEven => odd(&difference) && odd(&floor),
+    };
+    if up {
+        add(&floor, &one)
+    } else {
+        Some(floor)
+    }
+}
+
+// Types without a fixed width refuse powers and factorials that could need more bits than
+// this, about 79,000 decimal digits, instead of running out of time or memory
+pub const MAX_RESULT_BITS: u64 = 1 << 18;
+
+// A number type the Calculator can compute with: a primitive integer, BigInt or Rational
+pub trait CheckedInt: Clone + fmt::Debug + fmt::Display + Ord + FromStr {
+    // Type name used in error messages, e.g. "i64"
+    const NAME: &'static str;
     
-    // Generate a formatted string showing all operations in history
-    pub fn show_history(&self) -> String {
-        let mut result = String::new();
-        for (index, operation) in self.history.iter().enumerate() {
-            let op_result = operation.operation_type.perform(operation.first_num, operation.second_num);
-            if let Some(value) = op_result {
-                result.push_str(&format!("{}: {} {} {} = {}\n", 
-                    index, 
-                    operation.first_num, 
-                    operation.operation_type.get_sign(), 
-                    operation.second_num, 
-                    value
-                ));
-            }
+    fn zero() -> Self;
+    
+    // The multiplicative identity, used by signum and factorial
+    fn one() -> Self {
+        "1".parse().ok().expect("every CheckedInt parses 1")
+    }
+    
+    // Apply an operation under an overflow policy, returning the value and whether it
+    // wrapped around, or None if the Checked policy finds the result out of range.
+    // Division-like operations are never called with a zero divisor.
+    fn apply(operation_type: OperationType, x: &Self, y: &Self, policy: OverflowPolicy) -> Option<(Self, bool)>;
+    
+    // Same as apply, rounding results that fall between two values as requested.
+    // Only types for which rounds returns true need to override it.
+    fn apply_rounded(
+        operation_type: OperationType,
+        x: &Self,
+        y: &Self,
+        policy: OverflowPolicy,
+        rounding: Rounding,
+    ) -> Option<(Self, bool)> {
+        let _ = rounding;
+        Self::apply(operation_type, x, y, policy)
+    }
+    
+    // Whether results of the operation can need rounding, which Calculator::set_rounding controls
+    fn rounds(operation_type: OperationType) -> bool {
+        let _ = operation_type;
+        false
+    }
+    
+    // x * y / z with an intermediate wide enough for the exact product, rounded as requested.
+    // Returns None if the quotient does not fit; z is never zero.
+    fn mul_div(x: &Self, y: &Self, z: &Self, rounding: Rounding) -> Option<Self>;
+    
+    // The sum of the values divided by their count, with an intermediate wide enough for the
+    // exact sum, rounded as requested. Returns None if the mean does not fit; values is not empty.
+    fn mean(values: &[Self], rounding: Rounding) -> Option<Self>;
+    
+    // Whether apply is defined for the operation; unsupported ones fail with CalcError::Unsupported
+    fn supports(operation_type: OperationType) -> bool {
+        let _ = operation_type;
+        true
+    }
+    
+    // Whether the result of a Power or Factorial stays within MAX_RESULT_BITS; larger ones fail
+    // with CalcError::TooLarge. Fixed-width types overflow long before, so only unbounded ones check.
+    fn within_size_limit(operation_type: OperationType, x: &Self, y: &Self) -> bool {
+        let _ = (operation_type, x, y);
+        true
+    }
+    
+    // Whether apply_modular is implemented, which Calculator::with_modulus requires
+    fn supports_modular() -> bool {
+        false
+    }
+    
+    // Addition, Subtraction, Multiplication, Division or Power in Z/modulus, on x and y
+    // reduced to 0..modulus. Power gets a non-negative exponent, which is not reduced.
+    // Returns None when dividing by a value without an inverse.
+    fn apply_modular(operation_type: OperationType, x: &Self, y: &Self, modulus: &Self) -> Option<Self> {
+        let _ = (operation_type, x, y, modulus);
+        None
+    }
+    
+    // The value as a u32 exponent or logarithm base, if it is a whole number in range
+    fn to_u32(&self) -> Option<u32> {
+        self.to_string().parse().ok()
+    }
+    
+    // Parse optionally signed digits in the given radix, without a prefix.
+    // Types that do not override it only accept decimal, in their FromStr syntax.
+    fn from_str_radix(text: &str, radix: Radix) -> Option<Self> {
+        match radix {
+            Radix::Decimal => text.parse().ok(),
+            _ => None,
         }
-        result
     }
     
-    // Repeat an operation from history by index
-    pub fn repeat(&mut self, operation_index: usize) -> Option<i64> {
-        if operation_index >= self.history.len() {
-            return None;
+    // Format the value as NumberFormat describes. Types without a fixed width always print in decimal.
+    fn to_radix_string(&self, format: NumberFormat) -> String {
+        let _ = format;
+        self.to_string()
+    }
+}
+
+// n! under an overflow policy. Wrapping products of fixed-width types reach zero and
+// saturating ones MAX after a few factors and then never change, which ends the loop early.
+fn factorial<T: CheckedInt>(n: &T, policy: OverflowPolicy) -> Option<(T, bool)> {
+    let one = T::one();
+    let (mut product, mut factor, mut overflowed) = (one.clone(), one.clone(), false);
+    while factor < *n {
+        factor = T::apply(OperationType::Addition, &factor, &one, OverflowPolicy::Checked)?.0;
+        let (next, wrapped) = T::apply(OperationType::Multiplication, &product, &factor, policy)?;
+        overflowed |= wrapped;
+        let settled = next == T::zero() || (policy == OverflowPolicy::Saturating && next == product);
+        product = next;
+        if settled {
+            break;
         }
-        
-        let operation = self.history[operation_index].clone();
-        match operation.operation_type {
-            OperationType::Addition => self.addition(operation.first_num, operation.second_num),
-            OperationType::Subtraction => self.subtraction(operation.first_num, operation.second_num),
-            OperationType::Multiplication => self.multiplication(operation.first_num, operation.second_num),
+    }
+    Some((product, overflowed))
+}
+
+// Integer cube root, rounded down
+fn icbrt(n: u128) -> u128 {
+    if n == 0 {
+        return 0;
+    }
+    // Newton's method from an overestimate decreases monotonically to the root
+    let mut root = 1u128 << (128 - n.leading_zeros()).div_ceil(3);
+    loop {
+        let next = (2 * root + n / (root * root)) / 3;
+        if next >= root {
+            return root;
         }
+        root = next;
     }
-    
-    // Clear all operations from history
-    pub fn clear_history(&mut self) {
-        self.history.clear();
+}
+
+// Every primitive integer maps the policies onto its checked_*, wrapping_*,
+// saturating_* and overflowing_* methods. $unsigned is the type of the same
+// width that logical shifts go through.
+macro_rules! impl_checked_int {
+    ($($int:ty: $unsigned:ty),*) => {$(
+        impl CheckedInt for $int {
+            const NAME: &'static str = stringify!($int);
+            
+            fn zero() -> Self {
+                0
+            }
+            
+            fn apply(operation_type: OperationType, x: &Self, y: &Self, policy: OverflowPolicy) -> Option<(Self, bool)> {
+                let (x, y) = (*x, *y);
+                // Negative shift amounts are always an error
+                let amount = u32::try_from(y).ok();
+                let result = match (operation_type, policy) {
+                    (OperationType::Addition, OverflowPolicy::Checked) => (x.checked_add(y)?, false),
+                    (OperationType::Addition, OverflowPolicy::Wrapping) => (x.wrapping_add(y), false),
+                    (OperationType::Addition, OverflowPolicy::Saturating) => (x.saturating_add(y), false),
+                    (OperationType::Addition, OverflowPolicy::Overflowing) => x.overflowing_add(y),
+                    (OperationType::Subtraction, OverflowPolicy::Checked) => (x.checked_sub(y)?, false),
+                    (OperationType::Subtraction, OverflowPolicy::Wrapping) => (x.wrapping_sub(y), false),
+                    (OperationType::Subtraction, OverflowPolicy::Saturating) => (x.saturating_sub(y), false),
+                    (OperationType::Subtraction, OverflowPolicy::Overflowing) => x.overflowing_sub(y),
+                    (OperationType::Multiplication, OverflowPolicy::Checked) => (x.checked_mul(y)?, false),
+                    (OperationType::Multiplication, OverflowPolicy::Wrapping) => (x.wrapping_mul(y), false),
+                    (OperationType::Multiplication, OverflowPolicy::Saturating) => (x.saturating_mul(y), false),
+                    (OperationType::Multiplication, OverflowPolicy::Overflowing) => x.overflowing_mul(y),
+                    (OperationType::Division, OverflowPolicy::Checked) => (x.checked_div(y)?, false),
+                    (OperationType::Division, OverflowPolicy::Wrapping) => (x.wrapping_div(y), false),
+                    (OperationType::Division, OverflowPolicy::Saturating) => (x.saturating_div(y), false),
+                    (OperationType::Division, OverflowPolicy::Overflowing) => x.overflowing_div(y),
+                    (OperationType::Remainder, OverflowPolicy::Checked) => (x.checked_rem(y)?, false),
+                    // MIN % -1 is exactly 0, so there is nothing to clamp
+                    (OperationType::Remainder, OverflowPolicy::Wrapping | OverflowPolicy::Saturating) => {
+                        (x.wrapping_rem(y), false)
+                    }
+                    (OperationType::Remainder, OverflowPolicy::Overflowing) => x.overflowing_rem(y),
+                    (OperationType::EuclidMod, OverflowPolicy::Checked) => (x.checked_rem_euclid(y)?, false),
+                    (OperationType::EuclidMod, OverflowPolicy::Wrapping | OverflowPolicy::Saturating) => {
+                        (x.wrapping_rem_euclid(y), false)
+                    }
+                    (OperationType::EuclidMod, OverflowPolicy::Overflowing) => x.overflowing_rem_euclid(y),
+                    (OperationType::And, _) => (x & y, false),
+                    (OperationType::Or, _) => (x | y, false),
+                    (OperationType::Xor, _) => (x ^ y, false),
+                    (OperationType::Not, _) => (!x, false),
+                    // Amounts of at least the bit width only wrap around under Wrapping and Overflowing
+                    (OperationType::Shl, OverflowPolicy::Wrapping) => (x.wrapping_shl(amount?), false),
+                    (OperationType::Shl, OverflowPolicy::Overflowing) => x.overflowing_shl(amount?),
+                    (OperationType::Shl, _) => (x.checked_shl(amount?)?, false),
+                    (OperationType::Shr, OverflowPolicy::Wrapping) => (x.wrapping_shr(amount?), false),
+                    (OperationType::Shr, OverflowPolicy::Overflowing) => x.overflowing_shr(amount?),
+                    (OperationType::Shr, _) => (x.checked_shr(amount?)?, false),
+                    (OperationType::LogicalShr, OverflowPolicy::Wrapping) => {
+                        ((x as $unsigned).wrapping_shr(amount?) as $int, false)
+                    }
+                    (OperationType::LogicalShr, OverflowPolicy::Overflowing) => {
+                        let (value, overflowed) = (x as $unsigned).overflowing_shr(amount?);
+                        (value as $int, overflowed)
+                    }
+                    (OperationType::LogicalShr, _) => ((x as $unsigned).checked_shr(amount?)? as $int, false),
+                    // Rotations never lose bits, so any amount is taken modulo the bit width
+                    (OperationType::RotateLeft, _) => (x.rotate_left(amount?), false),
+                    (OperationType::RotateRight, _) => (x.rotate_right(amount?), false),
+                    (OperationType::Power, OverflowPolicy::Checked) => (x.checked_pow(amount?)?, false),
+                    (OperationType::Power, OverflowPolicy::Wrapping) => (x.wrapping_pow(amount?), false),
+                    (OperationType::Power, OverflowPolicy::Saturating) => (x.saturating_pow(amount?), false),
+                    (OperationType::Power, OverflowPolicy::Overflowing) => x.overflowing_pow(amount?),
+                    // Roots of magnitudes up to 2^127 always fit back into the type
+                    (OperationType::Isqrt, _) => ((x as u128).isqrt() as $int, false),
+                    (OperationType::Icbrt, _) => {
+                        let negative = x < Self::zero();
+                        let magnitude = if negative { (x as $unsigned).wrapping_neg() } else { x as $unsigned };
+                        let root = icbrt(magnitude as u128) as $int;
+                        (if negative { root.wrapping_neg() } else { root }, false)
+                    }
+                    (OperationType::Ilog2, _) => (x.checked_ilog2()? as $int, false),
+                    (OperationType::Ilog10, _) => (x.checked_ilog10()? as $int, false),
+                    (OperationType::Ilog, _) => (x.checked_ilog(y)? as $int, false),
+                    // Magnitudes of types up to 64 bits fit in u64; only a gcd or lcm above MAX does not convert back
+                    (OperationType::Gcd | OperationType::Lcm | OperationType::ModInverse, _) if <$int>::BITS <= 64 => {
+                        let magnitude = |value: $int| (value as i128).unsigned_abs() as u64;
+                        let value = match operation_type {
+                            OperationType::Gcd => gcd(magnitude(x), magnitude(y)),
+                            OperationType::Lcm => lcm(magnitude(x), magnitude(y))?,
+                            // The modulus y is positive
+                            _ => mod_inverse((x as i128).rem_euclid(y as i128) as u64, y as u64)?,
+                        };
+                        (<$int>::try_from(value).ok()?, false)
+                    }
+                    // Wider types are computed exactly on BigInt
+                    (OperationType::Gcd | OperationType::Lcm | OperationType::ModInverse, _) => {
+                        let (value, _) = BigInt::apply(operation_type, &BigInt::from(x), &BigInt::from(y), policy)?;
+                        (<$int>::try_from(&value).ok()?, false)
+                    }
+                    (OperationType::Assign, _) => (x, false),
+                    // Built from other operations by OperationType::compute
+                    (
+                        OperationType::Neg
+                        | OperationType::Abs
+                        | OperationType::Signum
+                        | OperationType::Square
+                        | OperationType::Factorial,
+                        _,
+                    ) => return None,
+                    // Rejected by OperationType::compute
+                };
+                Some(result)
+            }
+            
+            fn to_u32(&self) -> Option<u32> {
+                u32::try_from(*self).ok()
+            }
+            
+            fn supports_modular() -> bool {
+                true
+            }
+            
+            // Residues of types up to 64 bits fit in u64, where number_theory uses u128 intermediates
+            fn apply_modular(operation_type: OperationType, x: &Self, y: &Self, modulus: &Self) -> Option<Self> {
+                if <$int>::BITS > 64 {
+                    let (x, y, modulus) = (BigInt::from(*x), BigInt::from(*y), BigInt::from(*modulus));
+                    return <$int>::try_from(&BigInt::apply_modular(operation_type, &x, &y, &modulus)?).ok();
+                }
+                let modulus = *modulus as u64;
+                let residue = |value: $int| (value as i128).rem_euclid(modulus as i128) as u64;
+                let (a, b) = (residue(*x), residue(*y));
+                let value = match operation_type {
+                    OperationType::Addition => mod_add(a, b, modulus),
+                    OperationType::Subtraction => mod_sub(a, b, modulus),
+                    OperationType::Multiplication => mod_mul(a, b, modulus),
+                    OperationType::Division => mod_mul(a, mod_inverse(b, modulus)?, modulus),
+                    OperationType::Power => mod_pow(a, *y as u64, modulus)?,
+                    _ => return None,
+                };
+                Some(value as $int)
+            }
+            
+            fn mul_div(x: &Self, y: &Self, z: &Self, rounding: Rounding) -> Option<Self> {
+                let product = BigInt::from(*x) * BigInt::from(*y);
+                let quotient = product.checked_div_rounded(&BigInt::from(*z), rounding)?;
+                <$int>::try_from(&quotient).ok()
+            }
+            
+            fn mean(values: &[Self], rounding: Rounding) -> Option<Self> {
+                let sum = values.iter().fold(BigInt::zero(), |sum, value| sum + BigInt::from(*value));
+                let quotient = sum.checked_div_rounded(&BigInt::from(values.len()), rounding)?;
+                <$int>::try_from(&quotient).ok()
+            }
+            
+            fn from_str_radix(text: &str, radix: Radix) -> Option<Self> {
+                <$int>::from_str_radix(text, radix.base()).ok()
+            }
+            
+            fn to_radix_string(&self, format: NumberFormat) -> String {
+                let negative = *self < Self::zero();
+                // Only negative values are sign-extended, so u128 keeps all of its bits
+                let bits = if negative { *self as i128 as u128 } else { *self as u128 };
+                format.format_integer(negative, bits)
+            }
+        }
+    )*};
+}
+
+impl_checked_int!(
+    i8: u8, i16: u16, i32: u32, i64: u64, i128: u128, isize: usize,
+    u8: u8, u16: u16, u32: u32, u64: u64, u128: u128, usize: usize
+);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CalcError<T = i64> {
+    // The exact result is greater than the largest value of T
+    Overflow { lhs: T, rhs: T, operation: OperationType },
+    // The exact result is less than the smallest value of T
+    Underflow { lhs: T, rhs: T, operation: OperationType },
+    DivisionByZero { lhs: T, operation: OperationType },
+    // A shift or rotation amount is negative, or at least the bit width under the Checked policy
+    ShiftOutOfRange { lhs: T, rhs: T, operation: OperationType },
+    // The operands are outside the domain of the operation, e.g. a negative exponent or ilog2(0)
+    InvalidArgument { lhs: T, rhs: T, operation: OperationType },
+    // value and modulus have a common factor, so mod_inverse has no answer
+    NotInvertible { value: T, modulus: T },
+    // The result of a type without a fixed width could need more than MAX_RESULT_BITS bits
+    TooLarge { lhs: T, rhs: T, operation: OperationType },
+    // A modular Calculator needs a positive modulus
+    InvalidModulus { modulus: T },
+    // The quotient of a ternary operation does not fit in T
+    TernaryOverflow { operands: [T; 3], operation: TernaryOperationType },
+    TernaryDivisionByZero { operands: [T; 3], operation: TernaryOperationType },
+    // A sum or product left the range of T at operands[position], or with no position,
+    // the mean or median itself does not fit
+    AggregateOverflow { operands: Vec<T>, position: Option<usize>, operation: AggregateOperationType },
+    // Min, max, mean and median of an empty list
+    NoOperands { operation: AggregateOperationType },
+    // The operation has no meaning for T, e.g. apply_bps on i8 which cannot hold 10000
+    Unsupported { operation: String },
+    InvalidHistoryIndex { index: usize, len: usize },
+    // An RPN command needs more values than the stack holds
+    StackUnderflow { command: String, needed: usize, available: usize },
+    // No assignment to the variable is in history
+    UndefinedVariable { name: String },
+    // Variables are identifiers that are not keywords or function names
+    InvalidVariableName { name: String },
+    // A registered operation returned None from BinaryOperation::perform
+    NoResult { lhs: T, rhs: T, operation: RegisteredOperation<T> },
+    // No operation with this name is registered
+    UnknownOperation { name: String },
+    // OperationRegistry::register refused the operation
+    InvalidRegistration { name: String, reason: String },
+    NothingToUndo,
+    NothingToRedo,
+    // A step of Calculator::transaction failed, so none of its operations were kept.
+    // Steps count the operations and loaded histories the transaction recorded, from 0.
+    TransactionFailed { step: usize, error: Box<CalcError<T>> },
+    // An expression could not be parsed; column is 1-based
+    Parse { column: usize, message: String },
+}
+
+impl<T: CheckedInt> fmt::Display for CalcError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcError::Overflow { lhs, rhs, operation } => {
+                write!(f, "overflow: {} exceeds {}::MAX", operation.describe(lhs, rhs), T::NAME)
+            }
+            CalcError::Underflow { lhs, rhs, operation } => {
+                write!(f, "underflow: {} is below {}::MIN", operation.describe(lhs, rhs), T::NAME)
+            }
+            CalcError::DivisionByZero { lhs, operation } => {
+                write!(f, "division by zero: {} {} 0", lhs, operation.get_sign())
+            }
+            CalcError::ShiftOutOfRange { lhs, rhs, operation } => {
+                write!(f, "shift amount out of range: {} {} {} for {}", lhs, operation.get_sign(), rhs, T::NAME)
+            }
+            CalcError::InvalidArgument { lhs, rhs, operation } => {
+                write!(f, "invalid argument: {}", operation.describe(lhs, rhs))
+            }
+            CalcError::NotInvertible { value, modulus } => {
+                write!(f, "no inverse: {} is not invertible modulo {}", value, modulus)
+            }
+            CalcError::TooLarge { lhs, rhs, operation } => write!(
+                f,
+                "too large: {} could need more than {} bits as a {}",
+                operation.describe(lhs, rhs),
+                MAX_RESULT_BITS,
+                T::NAME
+            ),
+            CalcError::InvalidModulus { modulus } => write!(f, "invalid modulus {}: it must be positive", modulus),
+            CalcError::TernaryOverflow { operands: [x, y, z], operation } => {
+                write!(f, "overflow: {} does not fit in {}", operation.describe(x, y, z), T::NAME)
+            }
+            CalcError::TernaryDivisionByZero { operands: [x, y, z], operation } => {
+                write!(f, "division by zero: {}", operation.describe(x, y, z))
+            }
+            CalcError::AggregateOverflow { operands, position, operation } => {
+                write!(f, "overflow: {} does not fit in {}", operation.describe(operands), T::NAME)?;
+                match position {
+                    Some(position) => write!(f, " at operand {} ({})", position, operands[*position]),
+                    None => Ok(()),
+                }
+            }
+            CalcError::NoOperands { operation } => write!(f, "{} needs at least one operand", operation.name()),
+            CalcError::Unsupported { operation } => write!(f, "{} is not supported for {}", operation, T::NAME),
+            CalcError::InvalidHistoryIndex { index, len } => {
+                write!(f, "invalid history index {} (history has {} entries)", index, len)
+            }
+            CalcError::StackUnderflow { command, needed, available } => {
+                write!(f, "stack underflow: {} needs {} values, the stack has {}", command, needed, available)
+            }
+            CalcError::UndefinedVariable { name } => write!(f, "undefined variable '{}'", name),
+            CalcError::InvalidVariableName { name } => write!(f, "'{}' cannot be used as a variable name", name),
+            CalcError::NoResult { lhs, rhs, operation } => {
+                write!(f, "no result: {} has no {} value", operation.describe(lhs, rhs), T::NAME)
+            }
+            CalcError::UnknownOperation { name } => write!(f, "unknown operation '{}'", name),
+            CalcError::InvalidRegistration { name, reason } => write!(f, "cannot register '{}': {}", name, reason),
+            CalcError::NothingToUndo => write!(f, "nothing to undo"),
+            CalcError::NothingToRedo => write!(f, "nothing to redo"),
+            CalcError::TransactionFailed { step, error } => {
+                write!(f, "transaction rolled back at step {}: {}", step, error)
+            }
+            CalcError::Parse { column, message } => {
+                write!(f, "parse error at column {}: {}", column, message)
+            }
+        }
+    }
+}
+
+impl<T: CheckedInt> std::error::Error for CalcError<T> {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation<T = i64> {
+    // What the operation performs, with its operands
+    pub kind: OperationKind<T>,
+    // The value computed when the operation was recorded
+    pub result: T,
+    // Sequence number assigned by the Calculator that recorded the operation
+    pub id: Option<u64>,
+    pub policy: OverflowPolicy,
+    // How the result was rounded, for operations whose type rounds, see CheckedInt::rounds
+    pub rounding: Rounding,
+    // Set when the Overflowing policy reported that the result wrapped around
+    pub overflowed: bool,
+    // Set for operations computed in Z/modulus by a modular Calculator
+    pub modulus: Option<T>,
+    // The variable the result was assigned to, e.g. fee for "let fee = 25"
+    pub variable: Option<String>,
+}
+
+// The kinds of operation history can hold, each with its own operands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperationKind<T = i64> {
+    // x op y, e.g. 4 + 9 or gcd(12, 18)
+    Binary { operation_type: OperationType, operands: [Argument<T>; 2] },
+    // An operation on one value such as isqrt(16), or an assignment
+    Unary { operation_type: OperationType, operand: Argument<T> },
+    // x * y / z, e.g. mul_div(1000, 3, 7)
+    Ternary { operation_type: TernaryOperationType, operands: [Argument<T>; 3] },
+    // A list of values, e.g. sum(1, 2, 3)
+    Aggregate { operation_type: AggregateOperationType, operands: Vec<Argument<T>> },
+    // An operation added with Calculator::register_operation
+    Registered { operation: RegisteredOperation<T>, operands: [Argument<T>; 2] },
+}
+
+// An operand as recorded in history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Argument<T = i64> {
+    pub value: T,
+    // Where the value was read from, shown instead of it
+    pub reference: Option<Reference>,
+}
+
+// An operand given directly, as the result of an earlier history entry or as a variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand<T = i64> {
+    Value(T),
+    // The result of history entry n, written $n
+    HistoryRef(usize),
+    Variable(String),
+}
+
+// Where an operand was read from. Recomputing an entry reads it again from the entries before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reference {
+    History(usize),
+    // The result of the last assignment to the variable
+    Variable(String),
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reference::History(index) => write!(f, "${}", index),
+            Reference::Variable(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl<T> From<T> for Operand<T> {
+    fn from(value: T) -> Self {
+        Operand::Value(value)
+    }
+}
+
+impl<T> From<T> for Argument<T> {
+    fn from(value: T) -> Self {
+        Argument { value, reference: None }
+    }
+}
+
+impl<T: CheckedInt> Argument<T> {
+    // A reference is recorded with a zero value until Operation::resolve reads it
+    pub fn from_operand(operand: Operand<T>) -> Self {
+        match operand {
+            Operand::Value(value) => Argument::from(value),
+            Operand::HistoryRef(index) => Argument { value: T::zero(), reference: Some(Reference::History(index)) },
+            Operand::Variable(name) => Argument { value: T::zero(), reference: Some(Reference::Variable(name)) },
+        }
+    }
+    
+    fn convert<U: CheckedInt + From<T>>(self) -> Argument<U> {
+        Argument { value: U::from(self.value), reference: self.reference }
+    }
+    
+    // The reference if there is one, otherwise the value in the given format
+    fn describe_in(&self, format: NumberFormat) -> String {
+        match &self.reference {
+            Some(reference) => reference.to_string(),
+            None => self.value.to_radix_string(format),
+        }
+    }
+}
+
+impl<T: CheckedInt> OperationKind<T> {
+    // x op y, or just x for a unary operation type
+    pub fn binary(operation_type: OperationType, x: Argument<T>, y: Argument<T>) -> Self {
+        if operation_type.is_unary() {
+            OperationKind::Unary { operation_type, operand: x }
+        } else {
+            OperationKind::Binary { operation_type, operands: [x, y] }
+        }
+    }
+    
+    // The built-in operation type of binary and unary operations
+    pub fn operation_type(&self) -> Option<OperationType> {
+        match self {
+            OperationKind::Binary { operation_type, .. } | OperationKind::Unary { operation_type, .. } => Some(*operation_type),
+            _ => None,
+        }
+    }
+    
+    // The operands in the order they are written
+    pub fn arguments(&self) -> &[Argument<T>] {
+        match self {
+            OperationKind::Binary { operands, .. } | OperationKind::Registered { operands, .. } => operands,
+            OperationKind::Unary { operand, .. } => std::slice::from_ref(operand),
+            OperationKind::Ternary { operands, .. } => operands,
+            OperationKind::Aggregate { operands, .. } => operands,
+        }
+    }
+    
+    fn arguments_mut(&mut self) -> &mut [Argument<T>] {
+        match self {
+            OperationKind::Binary { operands, .. } | OperationKind::Registered { operands, .. } => operands,
+            OperationKind::Unary { operand, .. } => std::slice::from_mut(operand),
+            OperationKind::Ternary { operands, .. } => operands,
+            OperationKind::Aggregate { operands, .. } => operands,
+        }
+    }
+    
+    // The stable name used when history is saved to a file
+    pub fn name(&self) -> String {
+        match self {
+            OperationKind::Binary { operation_type, .. } | OperationKind::Unary { operation_type, .. } => {
+                operation_type.name().to_string()
+            }
+            OperationKind::Ternary { operation_type, .. } => operation_type.name(),
+            OperationKind::Aggregate { operation_type, .. } => operation_type.name(),
+            OperationKind::Registered { operation, .. } => operation.name().to_string(),
+        }
+    }
+}
+
+impl<T: CheckedInt> Operation<T> {
+    // Create a new Operation with the given parameters; a unary operation type ignores second_num
+    pub fn new(first_num: T, second_num: T, operation_type: OperationType, result: T) -> Self {
+        Operation::from_kind(OperationKind::binary(operation_type, first_num.into(), second_num.into()), result)
+    }
+    
+    // Create an Operation of any kind with the default policy and no modulus or variable
+    pub fn from_kind(kind: OperationKind<T>, result: T) -> Self {
+        Operation {
+            kind,
+            result,
+            id: None,
+            policy: OverflowPolicy::Checked,
+            rounding: Rounding::default(),
+            overflowed: false,
+            modulus: None,
+            variable: None,
+        }
+    }
+    
+    // Create an Operation whose operands may refer to history; references get their values from resolve
+    pub fn from_operands(x: Operand<T>, y: Operand<T>, operation_type: OperationType) -> Self {
+        let kind = OperationKind::binary(operation_type, Argument::from_operand(x), Argument::from_operand(y));
+        Operation::from_kind(kind, T::zero())
+    }
+    
+    // Create an Operation that performs a registered operation; built-in ones are recorded as their OperationType
+    pub fn from_registered(x: Operand<T>, y: Operand<T>, operation: &RegisteredOperation<T>) -> Self {
+        match operation.operation_type() {
+            Some(operation_type) => Operation::from_operands(x, y, operation_type),
+            None => {
+                let operands = [Argument::from_operand(x), Argument::from_operand(y)];
+                Operation::from_kind(OperationKind::Registered { operation: operation.clone(), operands }, T::zero())
+            }
+        }
+    }
+    
+    // Create a new Operation for x * y / z
+    pub fn new_ternary(first_num: T, second_num: T, operation_type: TernaryOperationType, third_num: T, result: T) -> Self {
+        let operands = [first_num.into(), second_num.into(), third_num.into()];
+        Operation::from_kind(OperationKind::Ternary { operation_type, operands }, result)
+    }
+    
+    // Create a new Operation over a list of values
+    pub fn new_aggregate(operation_type: AggregateOperationType, operands: Vec<T>, result: T) -> Self {
+        let operands = operands.into_iter().map(Argument::from).collect();
+        Operation::from_kind(OperationKind::Aggregate { operation_type, operands }, result)
+    }
+    
+    // The built-in operation type of binary and unary operations
+    pub fn operation_type(&self) -> Option<OperationType> {
+        self.kind.operation_type()
+    }
+    
+    // Run the operation again on its operands under the policy and modulus it was recorded with.
+    // Also returns whether the Overflowing policy saw it wrap around.
+    // Registered operations perform their own checks, so no policy applies to them.
+    pub fn evaluate(&self) -> Result<(T, bool), CalcError<T>> {
+        match (&self.kind, &self.modulus) {
+            (OperationKind::Binary { operation_type, operands: [x, y] }, _) => self.compute(*operation_type, &x.value, &y.value),
+            (OperationKind::Unary { operation_type, operand }, _) => self.compute(*operation_type, &operand.value, &T::zero()),
+            (OperationKind::Ternary { operation_type, operands: [x, y, z] }, None) => {
+                operation_type.compute(&x.value, &y.value, &z.value).map(|value| (value, false))
+            }
+            (OperationKind::Aggregate { operation_type, operands }, None) => {
+                let values: Vec<T> = operands.iter().map(|argument| argument.value.clone()).collect();
+                operation_type.compute(&values).map(|value| (value, false))
+            }
+            (OperationKind::Registered { operation, operands: [x, y] }, None) => {
+                let (lhs, rhs) = (&x.value, &y.value);
+                operation.perform(lhs, rhs).map(|value| (value, false)).ok_or_else(|| CalcError::NoResult {
+                    lhs: lhs.clone(),
+                    rhs: rhs.clone(),
+                    operation: operation.clone(),
+                })
+            }
+            (kind, Some(_)) => Err(CalcError::Unsupported { operation: format!("{} in modular mode", kind.name()) }),
+        }
+    }
+    
+    // Compute a binary or unary operation type in Z/modulus or under the policy and rounding
+    fn compute(&self, operation_type: OperationType, x: &T, y: &T) -> Result<(T, bool), CalcError<T>> {
+        match &self.modulus {
+            Some(modulus) => operation_type.compute_modular(x, y, modulus).map(|value| (value, false)),
+            None => operation_type.compute_rounded(x, y, self.policy, self.rounding),
+        }
+    }
+    
+    pub fn recompute(&self) -> Result<T, CalcError<T>> {
+        self.evaluate().map(|(value, _)| value)
+    }
+    
+    // Copy the values of referenced entries and variables into the operands.
+    // history holds the entries before this one, so an operation can only refer back.
+    pub fn resolve(&mut self, history: &[Operation<T>]) -> Result<(), CalcError<T>> {
+        for argument in self.kind.arguments_mut() {
+            if let Some(reference) = &argument.reference {
+                argument.value = lookup(history, reference)?;
+            }
+        }
+        Ok(())
+    }
+    
+    // Convert the operands and result to another integer type.
+    // A registered operation only works on T, so its entries cannot be converted.
+    pub fn convert<U: CheckedInt + From<T>>(self) -> Result<Operation<U>, CalcError<T>> {
+        let kind = match self.kind {
+            OperationKind::Binary { operation_type, operands } => {
+                OperationKind::Binary { operation_type, operands: operands.map(Argument::convert) }
+            }
+            OperationKind::Unary { operation_type, operand } => OperationKind::Unary { operation_type, operand: operand.convert() },
+            OperationKind::Ternary { operation_type, operands } => {
+                OperationKind::Ternary { operation_type, operands: operands.map(Argument::convert) }
+            }
+            OperationKind::Aggregate { operation_type, operands } => {
+                OperationKind::Aggregate { operation_type, operands: operands.into_iter().map(Argument::convert).collect() }
+            }
+            OperationKind::Registered { operation, .. } => {
+                return Err(CalcError::Unsupported { operation: format!("moving {} to another backend", operation.name()) });
+            }
+        };
+        Ok(Operation {
+            kind,
+            result: U::from(self.result),
+            id: self.id,
+            policy: self.policy,
+            rounding: self.rounding,
+            overflowed: self.overflowed,
+            modulus: self.modulus.map(U::from),
+            variable: self.variable,
+        })
+    }
+    
+    // Return the sign shown in history, e.g. "+" or "+sat"; ternary operations and aggregates show their name
+    pub fn symbol(&self) -> String {
+        let sign = match &self.kind {
+            OperationKind::Binary { operation_type, .. } | OperationKind::Unary { operation_type, .. } => {
+                operation_type.get_sign().to_string()
+            }
+            OperationKind::Registered { operation, .. } => operation.symbol().to_string(),
+            kind => return kind.name(),
+        };
+        let suffix = self.policy.suffix();
+        if !suffix.is_empty() && sign.ends_with(char::is_alphabetic) {
+            format!("{}_{}", sign, suffix)
+        } else {
+            format!("{}{}", sign, suffix)
+        }
+    }
+    
+    // Return the operation without its result, e.g. "-4 + 9", "$1 * 7", "mul_div(1000, 3, 7)" or "sum(1, 2, 3)"
+    pub fn describe(&self) -> String {
+        self.describe_in(Radix::Decimal)
+    }
+    
+    // Same as describe, with the operands printed in the given radix or NumberFormat
+    pub fn describe_in(&self, format: impl Into<NumberFormat>) -> String {
+        let format = format.into();
+        let operands: Vec<String> = self.kind.arguments().iter().map(|argument| argument.describe_in(format)).collect();
+        let described = match &self.kind {
+            OperationKind::Aggregate { operation_type, .. } => operation_type.describe(&operands),
+            OperationKind::Ternary { operation_type, .. } => operation_type.describe(&operands[0], &operands[1], &operands[2]),
+            OperationKind::Registered { operation, .. } => operation.describe(&operands[0], &operands[1]),
+            OperationKind::Unary { operation_type: OperationType::Assign, .. } => operands[0].clone(),
+            OperationKind::Unary { operation_type, .. } => {
+                operation_type.describe_with_sign(&self.symbol(), &operands[0], &String::new())
+            }
+            OperationKind::Binary { operation_type, .. } => {
+                operation_type.describe_with_sign(&self.symbol(), &operands[0], &operands[1])
+            }
+        };
+        match &self.variable {
+            Some(name) => format!("{} = {}", name, described),
+            None => described,
+        }
+    }
+}
+
+// The value a reference reads from the given entries
+fn lookup<T: CheckedInt>(history: &[Operation<T>], reference: &Reference) -> Result<T, CalcError<T>> {
+    match reference {
+        Reference::History(index) => history
+            .get(*index)
+            .map(|operation| operation.result.clone())
+            .ok_or(CalcError::InvalidHistoryIndex { index: *index, len: history.len() }),
+        Reference::Variable(name) => history
+            .iter()
+            .rev()
+            .find(|operation| operation.variable.as_ref() == Some(name))
+            .map(|operation| operation.result.clone())
+            .ok_or_else(|| CalcError::UndefinedVariable { name: name.clone() }),
+    }
+}
+
+// A history entry whose stored result disagrees with recomputation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryMismatch<T = i64> {
+    pub index: usize,
+    pub operation: Operation<T>,
+    pub recomputed: Result<T, CalcError<T>>,
+}
+
+// A reversible change to history, kept on the undo and redo stacks
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HistoryEdit<T> {
+    // An operation was appended to history
+    Push(Operation<T>),
+    // History was replaced wholesale; holds the entries it replaced
+    Replace(Vec<Operation<T>>),
+    // A transaction or expression made these edits, oldest first
+    Batch(Vec<HistoryEdit<T>>),
+}
+
+impl<T: CheckedInt> HistoryEdit<T> {
+    fn convert<U: CheckedInt + From<T>>(self) -> Result<HistoryEdit<U>, CalcError<T>> {
+        let convert = |entries: Vec<Operation<T>>| -> Result<Vec<Operation<U>>, _> {
+            entries.into_iter().map(Operation::convert).collect()
+        };
+        Ok(match self {
+            HistoryEdit::Push(operation) => HistoryEdit::Push(operation.convert()?),
+            HistoryEdit::Replace(entries) => HistoryEdit::Replace(convert(entries)?),
+            HistoryEdit::Batch(edits) => {
+                HistoryEdit::Batch(edits.into_iter().map(HistoryEdit::convert).collect::<Result<_, _>>()?)
+            }
+        })
+    }
+}
+
+pub struct Calculator<T = i64> {
+    pub history: Vec<Operation<T>>,
+    undo_stack: Vec<HistoryEdit<T>>,
+    redo_stack: Vec<HistoryEdit<T>>,
+    // Edits recorded so far; a transaction reports the step that failed from it
+    steps: usize,
+    next_id: u64,
+    policy: OverflowPolicy,
+    rounding: Rounding,
+    format: NumberFormat,
+    // Set in modular mode, see Calculator::with_modulus
+    modulus: Option<T>,
+    operations: OperationRegistry<T>,
+}
+
+impl Calculator {
+    // Create a new i64 Calculator with empty history
+    pub fn new() -> Self {
+        Calculator::default()
+    }
+}
+
+// Calculator::<T>::default() creates an empty Calculator for any integer type
+impl<T: CheckedInt> Default for Calculator<T> {
+    fn default() -> Self {
+        Calculator {
+            history: Vec::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            steps: 0,
+            next_id: 0,
+            policy: OverflowPolicy::Checked,
+            rounding: Rounding::default(),
+            format: NumberFormat::default(),
+            modulus: None,
+            operations: OperationRegistry::default(),
+        }
+    }
+}
+
+impl<T: CheckedInt> Calculator<T> {
+    // The variable that holds the memory register used by M+, M-, MR and MC
+    pub const MEMORY: &'static str = "M";
+    
+    // Create a new Calculator that uses the given overflow policy
+    pub fn with_overflow_policy(policy: OverflowPolicy) -> Self {
+        Calculator { policy, ..Calculator::default() }
+    }
+    
+    // Switch to another integer backend, e.g. from i64 to BigInt, keeping history and undo state.
+    // Registered operations work on T only, so the new Calculator has just the built-in ones,
+    // and the switch is refused while history, undo or redo hold an entry of a registered operation.
+    pub fn into_backend<U: CheckedInt + From<T>>(self) -> Result<Calculator<U>, CalcError<T>> {
+        Ok(Calculator {
+            history: self.history.into_iter().map(Operation::convert).collect::<Result<_, _>>()?,
+            undo_stack: self.undo_stack.into_iter().map(HistoryEdit::convert).collect::<Result<_, _>>()?,
+            redo_stack: self.redo_stack.into_iter().map(HistoryEdit::convert).collect::<Result<_, _>>()?,
+            steps: self.steps,
+            next_id: self.next_id,
+            policy: self.policy,
+            rounding: self.rounding,
+            format: self.format,
+            modulus: self.modulus.map(U::from),
+            operations: OperationRegistry::default(),
+        })
+    }
+    
+    // Create a Calculator for arithmetic in Z/modulus. Addition, subtraction,
+    // multiplication, division and power results are reduced to 0..modulus,
+    // division multiplies by the modular inverse, and other operations are unsupported.
+    pub fn with_modulus(modulus: T) -> Result<Self, CalcError<T>> {
+        let mut calculator = Calculator::default();
+        calculator.set_modulus(Some(modulus))?;
+        Ok(calculator)
+    }
+    
+    pub fn modulus(&self) -> Option<&T> {
+        self.modulus.as_ref()
+    }
+    
+    // Enter modular mode with a positive modulus, or leave it with None.
+    // Operations already in history keep the modulus they were computed with.
+    pub fn set_modulus(&mut self, modulus: Option<T>) -> Result<(), CalcError<T>> {
+        if let Some(modulus) = &modulus {
+            if !T::supports_modular() {
+                return Err(CalcError::Unsupported { operation: "modular arithmetic".to_string() });
+            }
+            if *modulus <= T::zero() {
+                return Err(CalcError::InvalidModulus { modulus: modulus.clone() });
+            }
+        }
+        self.modulus = modulus;
+        Ok(())
+    }
+    
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+    
+    // Change the policy used by subsequent operations
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+    
+    pub fn rounding(&self) -> Rounding {
+        self.rounding
+    }
+    
+    // Change how subsequent results between two values are rounded, e.g. a FixedDecimal
+    // product with more decimals than its operands. Ternary operations name their own rounding.
+    pub fn set_rounding(&mut self, rounding: Rounding) {
+        self.rounding = rounding;
+    }
+    
+    // Perform addition and store successful operations in history
+    pub fn addition(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Addition, x, y)
+    }
+    
+    // Perform subtraction and store successful operations in history
+    pub fn subtraction(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Subtraction, x, y)
+    }
+    
+    // Perform multiplication and store successful operations in history
+    pub fn multiplication(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Multiplication, x, y)
+    }
+    
+    // Perform truncating division and store successful operations in history
+    pub fn division(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Division, x, y)
+    }
+    
+    // Compute the truncating remainder and store successful operations in history
+    pub fn remainder(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Remainder, x, y)
+    }
+    
+    // Compute the non-negative Euclidean modulo and store successful operations in history
+    pub fn euclid_mod(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::EuclidMod, x, y)
+    }
+    
+    // Option-returning wrappers kept for callers that only care about success
+    pub fn checked_addition(&mut self, x: T, y: T) -> Option<T> {
+        self.addition(x, y).ok()
+    }
+    
+    pub fn checked_subtraction(&mut self, x: T, y: T) -> Option<T> {
+        self.subtraction(x, y).ok()
+    }
+    
+    pub fn checked_multiplication(&mut self, x: T, y: T) -> Option<T> {
+        self.multiplication(x, y).ok()
+    }
+    
+    pub fn checked_division(&mut self, x: T, y: T) -> Option<T> {
+        self.division(x, y).ok()
+    }
+    
+    pub fn checked_remainder(&mut self, x: T, y: T) -> Option<T> {
+        self.remainder(x, y).ok()
+    }
+    
+    pub fn checked_euclid_mod(&mut self, x: T, y: T) -> Option<T> {
+        self.euclid_mod(x, y).ok()
+    }
+    
+    pub fn bit_and(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::And, x, y)
+    }
+    
+    pub fn bit_or(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Or, x, y)
+    }
+    
+    pub fn bit_xor(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Xor, x, y)
+    }
+    
+    pub fn bit_not(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Not, x)
+    }
+    
+    // x raised to exponent; negative exponents and exponents above u32::MAX are invalid
+    pub fn power(&mut self, x: T, exponent: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Power, x, exponent)
+    }
+    
+    pub fn isqrt(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Isqrt, x)
+    }
+    
+    pub fn icbrt(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Icbrt, x)
+    }
+    
+    pub fn ilog2(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Ilog2, x)
+    }
+    
+    pub fn ilog10(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Ilog10, x)
+    }
+    
+    pub fn ilog(&mut self, x: T, base: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Ilog, x, base)
+    }
+    
+    pub fn gcd(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Gcd, x, y)
+    }
+    
+    // Overflow if the least common multiple does not fit in T, under every policy
+    pub fn lcm(&mut self, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Lcm, x, y)
+    }
+    
+    // The inverse of x modulo a positive modulus, in 0..modulus
+    pub fn mod_inverse(&mut self, x: T, modulus: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::ModInverse, x, modulus)
+    }
+    
+    // -x under the calculator's policy; overflows for MIN under the Checked policy
+    pub fn neg(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Neg, x)
+    }
+    
+    pub fn abs(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Abs, x)
+    }
+    
+    pub fn signum(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Signum, x)
+    }
+    
+    pub fn square(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Square, x)
+    }
+    
+    // x! for non-negative x; 21! already overflows i64
+    pub fn factorial(&mut self, x: T) -> Result<T, CalcError<T>> {
+        self.calculate_unary(OperationType::Factorial, x)
+    }
+    
+    // Shift left by amount bits; amounts of at least the bit width fail under the Checked policy
+    pub fn shl(&mut self, x: T, amount: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Shl, x, amount)
+    }
+    
+    // Arithmetic shift right, keeping the sign of signed values
+    pub fn shr(&mut self, x: T, amount: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::Shr, x, amount)
+    }
+    
+    // Logical shift right, filling with zeros even for negative values
+    pub fn logical_shr(&mut self, x: T, amount: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::LogicalShr, x, amount)
+    }
+    
+    pub fn rotate_left(&mut self, x: T, amount: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::RotateLeft, x, amount)
+    }
+    
+    pub fn rotate_right(&mut self, x: T, amount: T) -> Result<T, CalcError<T>> {
+        self.calculate(OperationType::RotateRight, x, amount)
+    }
+    
+    // Compute x * y / z rounded down, without overflow in the product, and store it in history.
+    // Like the other ternary operations and aggregates it takes values or Operand references,
+    // e.g. mul_div(Operand::Variable("fee".to_string()), 3, 7).
+    pub fn mul_div(
+        &mut self,
+        x: impl Into<Operand<T>>,
+        y: impl Into<Operand<T>>,
+        z: impl Into<Operand<T>>,
+    ) -> Result<T, CalcError<T>> {
+        self.calculate_ternary(TernaryOperationType::MulDiv(Rounding::Floor), x, y, z)
+    }
+    
+    // Same as mul_div, but rounded up
+    pub fn mul_div_ceil(
+        &mut self,
+        x: impl Into<Operand<T>>,
+        y: impl Into<Operand<T>>,
+        z: impl Into<Operand<T>>,
+    ) -> Result<T, CalcError<T>> {
+        self.calculate_ternary(TernaryOperationType::MulDiv(Rounding::Ceil), x, y, z)
+    }
+    
+    // Take bps basis points (1/100 of a percent) of an amount, rounded down
+    pub fn apply_bps(&mut self, amount: impl Into<Operand<T>>, bps: impl Into<Operand<T>>) -> Result<T, CalcError<T>> {
+        self.calculate_scaled(TernaryOperationType::ApplyBps(Rounding::Floor), amount, bps)
+    }
+    
+    // Same as apply_bps, but rounded up, e.g. for fees charged to the user
+    pub fn apply_bps_ceil(&mut self, amount: impl Into<Operand<T>>, bps: impl Into<Operand<T>>) -> Result<T, CalcError<T>> {
+        self.calculate_scaled(TernaryOperationType::ApplyBps(Rounding::Ceil), amount, bps)
+    }
+    
+    // Take a percentage of an amount, rounded down
+    pub fn percent(&mut self, amount: impl Into<Operand<T>>, percent: impl Into<Operand<T>>) -> Result<T, CalcError<T>> {
+        self.calculate_scaled(TernaryOperationType::Percent(Rounding::Floor), amount, percent)
+    }
+    
+    // Same as percent, but rounded up
+    pub fn percent_ceil(&mut self, amount: impl Into<Operand<T>>, percent: impl Into<Operand<T>>) -> Result<T, CalcError<T>> {
+        self.calculate_scaled(TernaryOperationType::Percent(Rounding::Ceil), amount, percent)
+    }
+    
+    // The total of values as one history entry, e.g. "sum(100, 250, 75) = 425"
+    pub fn sum(&mut self, values: &[impl Into<Operand<T>> + Clone]) -> Result<T, CalcError<T>> {
+        self.calculate_aggregate(AggregateOperationType::Sum, values)
+    }
+    
+    pub fn product(&mut self, values: &[impl Into<Operand<T>> + Clone]) -> Result<T, CalcError<T>> {
+        self.calculate_aggregate(AggregateOperationType::Product, values)
+    }
+    
+    pub fn min(&mut self, values: &[impl Into<Operand<T>> + Clone]) -> Result<T, CalcError<T>> {
+        self.calculate_aggregate(AggregateOperationType::Min, values)
+    }
+    
+    pub fn max(&mut self, values: &[impl Into<Operand<T>> + Clone]) -> Result<T, CalcError<T>> {
+        self.calculate_aggregate(AggregateOperationType::Max, values)
+    }
+    
+    // The mean rounded down
+    pub fn mean(&mut self, values: &[impl Into<Operand<T>> + Clone]) -> Result<T, CalcError<T>> {
+        self.calculate_aggregate(AggregateOperationType::Mean(Rounding::Floor), values)
+    }
+    
+    pub fn mean_ceil(&mut self, values: &[impl Into<Operand<T>> + Clone]) -> Result<T, CalcError<T>> {
+        self.calculate_aggregate(AggregateOperationType::Mean(Rounding::Ceil), values)
+    }
+    
+    // The median; for an even number of values, the mean of the middle two rounded down
+    pub fn median(&mut self, values: &[impl Into<Operand<T>> + Clone]) -> Result<T, CalcError<T>> {
+        self.calculate_aggregate(AggregateOperationType::Median(Rounding::Floor), values)
+    }
+    
+    pub fn median_ceil(&mut self, values: &[impl Into<Operand<T>> + Clone]) -> Result<T, CalcError<T>> {
+        self.calculate_aggregate(AggregateOperationType::Median(Rounding::Ceil), values)
+    }
+    
+    pub fn checked_repeat(&mut self, operation_index: usize) -> Option<T> {
+        self.repeat(operation_index).ok()
+    }
+    
+    // Perform any operation under the calculator's policy and store it in history if it succeeded
+    pub fn calculate(&mut self, operation_type: OperationType, x: T, y: T) -> Result<T, CalcError<T>> {
+        self.calculate_with_policy(operation_type, x, y, self.policy)
+    }
+    
+    // Perform an operation under a one-off policy and store it in history if it succeeded
+    pub fn calculate_with_policy(
+        &mut self,
+        operation_type: OperationType,
+        x: T,
+        y: T,
+        policy: OverflowPolicy,
+    ) -> Result<T, CalcError<T>> {
+        let mut operation = Operation::new(x, y, operation_type, T::zero());
+        operation.policy = policy;
+        operation.rounding = self.rounding_for(&operation);
+        operation.modulus = self.modulus.clone();
+        self.push_evaluated(operation)
+    }
+    
+    // Perform an operation whose operands may refer to earlier results, e.g. $1 * 7.
+    // The references are kept, so history shows them and repeat reads the same entries.
+    pub fn calculate_operands(&mut self, operation_type: OperationType, x: Operand<T>, y: Operand<T>) -> Result<T, CalcError<T>> {
+        let mut operation = Operation::from_operands(x, y, operation_type);
+        operation.policy = self.policy;
+        operation.rounding = self.rounding_for(&operation);
+        operation.modulus = self.modulus.clone();
+        self.push_evaluated(operation)
+    }
+    
+    // Add an operation that expressions, RPN lines and load_history can then use by its symbol or name
+    pub fn register_operation(&mut self, operation: impl BinaryOperation<T> + 'static) -> Result<(), CalcError<T>> {
+        self.operations.register(operation)
+    }
+    
+    pub fn operations(&self) -> &OperationRegistry<T> {
+        &self.operations
+    }
+    
+    // Perform a built-in or registered operation looked up by name, e.g. "addition" or "avg"
+    pub fn calculate_named(&mut self, name: &str, x: Operand<T>, y: Operand<T>) -> Result<T, CalcError<T>> {
+        let operation = self.operations.by_name(name).cloned();
+        let operation = operation.ok_or_else(|| CalcError::UnknownOperation { name: name.to_string() })?;
+        self.calculate_registered(&operation, x, y)
+    }
+    
+    // Perform a registered operation and store it in history if it succeeded.
+    // Built-in operations follow the calculator's policy; every operation follows its modulus.
+    pub fn calculate_registered(&mut self, operation: &RegisteredOperation<T>, x: Operand<T>, y: Operand<T>) -> Result<T, CalcError<T>> {
+        let mut entry = Operation::from_registered(x, y, operation);
+        if !matches!(entry.kind, OperationKind::Registered { .. }) {
+            entry.policy = self.policy;
+            entry.rounding = self.rounding_for(&entry);
+        }
+        entry.modulus = self.modulus.clone();
+        self.push_evaluated(entry)
+    }
+    
+    // The value of an operand without performing anything
+    pub fn resolve(&self, operand: &Operand<T>) -> Result<T, CalcError<T>> {
+        match operand {
+            Operand::Value(value) => Ok(value.clone()),
+            Operand::HistoryRef(index) => lookup(&self.history, &Reference::History(*index)),
+            Operand::Variable(name) => self.variable(name),
+        }
+    }
+    
+    // A reference to the last result, which expressions call ans
+    pub fn ans(&self) -> Result<Operand<T>, CalcError<T>> {
+        match self.history.len() {
+            0 => Err(CalcError::InvalidHistoryIndex { index: 0, len: 0 }),
+            len => Ok(Operand::HistoryRef(len - 1)),
+        }
+    }
+    
+    // Assign a value to a variable. The assignment is recorded in history, so undo reverts it
+    // and replaying the history reproduces every variable.
+    pub fn assign(&mut self, name: &str, value: Operand<T>) -> Result<T, CalcError<T>> {
+        if !is_variable_name(name) || self.operations.by_symbol(name).is_some() {
+            return Err(CalcError::InvalidVariableName { name: name.to_string() });
+        }
+        let mut operation = Operation::from_operands(value, Operand::Value(T::zero()), OperationType::Assign);
+        operation.variable = Some(name.to_string());
+        self.push_evaluated(operation)
+    }
+    
+    // The current value of a variable, from its last assignment in history
+    pub fn variable(&self, name: &str) -> Result<T, CalcError<T>> {
+        lookup(&self.history, &Reference::Variable(name.to_string()))
+    }
+    
+    // Every assigned variable with its current value, including the memory register M
+    pub fn variables(&self) -> BTreeMap<String, T> {
+        self.history
+            .iter()
+            .filter_map(|operation| Some((operation.variable.clone()?, operation.result.clone())))
+            .collect()
+    }
+    
+    // M+: add a value to the memory register
+    pub fn memory_add(&mut self, value: Operand<T>) -> Result<T, CalcError<T>> {
+        self.update_memory(OperationType::Addition, value)
+    }
+    
+    // M-: subtract a value from the memory register
+    pub fn memory_subtract(&mut self, value: Operand<T>) -> Result<T, CalcError<T>> {
+        self.update_memory(OperationType::Subtraction, value)
+    }
+    
+    // MR: the memory register, which is zero until something is stored in it
+    pub fn memory_recall(&self) -> T {
+        self.variable(Self::MEMORY).unwrap_or_else(|_| T::zero())
+    }
+    
+    // MC: set the memory register back to zero
+    pub fn memory_clear(&mut self) -> Result<T, CalcError<T>> {
+        self.assign(Self::MEMORY, Operand::Value(T::zero()))
+    }
+    
+    // Record "M = M + value" or "M = M - value" under the calculator's policy and modulus
+    fn update_memory(&mut self, operation_type: OperationType, value: Operand<T>) -> Result<T, CalcError<T>> {
+        let memory = match self.variable(Self::MEMORY) {
+            Ok(_) => Operand::Variable(Self::MEMORY.to_string()),
+            Err(_) => Operand::Value(T::zero()),
+        };
+        let mut operation = Operation::from_operands(memory, value, operation_type);
+        operation.policy = self.policy;
+        operation.rounding = self.rounding_for(&operation);
+        operation.modulus = self.modulus.clone();
+        operation.variable = Some(Self::MEMORY.to_string());
+        self.push_evaluated(operation)
+    }
+    
+    // The calculator's rounding for an operation whose type rounds, otherwise the default
+    fn rounding_for(&self, operation: &Operation<T>) -> Rounding {
+        match operation.operation_type() {
+            Some(operation_type) if T::rounds(operation_type) => self.rounding,
+            _ => Rounding::default(),
+        }
+    }
+    
+    // Compute an operation from its operands and store it in history if it succeeded
+    fn push_evaluated(&mut self, mut operation: Operation<T>) -> Result<T, CalcError<T>> {
+        operation.resolve(&self.history)?;
+        let (result, overflowed) = operation.evaluate()?;
+        operation.result = result.clone();
+        operation.overflowed = overflowed;
+        operation.id = Some(self.assign_id());
+        self.history.push(operation.clone());
+        self.record(HistoryEdit::Push(operation));
+        Ok(result)
+    }
+    
+    // Perform a unary operation such as Not or Isqrt; it is stored with just its operand
+    pub fn calculate_unary(&mut self, operation_type: OperationType, x: T) -> Result<T, CalcError<T>> {
+        self.calculate(operation_type, x, T::zero())
+    }
+    
+    // Perform a ternary operation and store it in history if it succeeded. References are kept like
+    // those of calculate_operands. Overflow policies do not apply: the result is exact up to rounding or an error.
+    pub fn calculate_ternary(
+        &mut self,
+        operation_type: TernaryOperationType,
+        x: impl Into<Operand<T>>,
+        y: impl Into<Operand<T>>,
+        z: impl Into<Operand<T>>,
+    ) -> Result<T, CalcError<T>> {
+        let operands = [x.into(), y.into(), z.into()].map(Argument::from_operand);
+        let mut operation = Operation::from_kind(OperationKind::Ternary { operation_type, operands }, T::zero());
+        operation.modulus = self.modulus.clone();
+        self.push_evaluated(operation)
+    }
+    
+    // Perform an aggregate over a list of values or references and store it in history as a single
+    // entry if it succeeded. Like ternary operations, aggregates always use checked arithmetic.
+    pub fn calculate_aggregate(
+        &mut self,
+        operation_type: AggregateOperationType,
+        values: &[impl Into<Operand<T>> + Clone],
+    ) -> Result<T, CalcError<T>> {
+        let operands = values.iter().map(|value| Argument::from_operand(value.clone().into())).collect();
+        let mut operation = Operation::from_kind(OperationKind::Aggregate { operation_type, operands }, T::zero());
+        operation.modulus = self.modulus.clone();
+        self.push_evaluated(operation)
+    }
+    
+    // Perform a ternary operation whose divisor is fixed, such as apply_bps
+    pub(crate) fn calculate_scaled(
+        &mut self,
+        operation_type: TernaryOperationType,
+        x: impl Into<Operand<T>>,
+        y: impl Into<Operand<T>>,
+    ) -> Result<T, CalcError<T>> {
+        let divisor = operation_type.fixed_divisor().and_then(|text| text.parse::<T>().ok());
+        let divisor = divisor.ok_or_else(|| CalcError::Unsupported { operation: operation_type.name() })?;
+        self.calculate_ternary(operation_type, x, y, divisor)
+    }
+    
+    fn assign_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+    
+    // Replace the whole history as a single undoable step.
+    // Entries get fresh sequence ids from this Calculator.
+    pub(crate) fn replace_history(&mut self, mut history: Vec<Operation<T>>) {
+        for operation in &mut history {
+            operation.id = Some(self.assign_id());
+        }
+        let previous = std::mem::replace(&mut self.history, history);
+        self.record(HistoryEdit::Replace(previous));
+    }
+    
+    // Run a batch of operations and keep its history, policy, modulus and registered
+    // operations only if the batch succeeds, as one undoable step. On failure nothing
+    // changes, variables included, and the error holds the step that failed: the number
+    // of edits, such as operations or loaded histories, the batch had recorded before it.
+    pub fn transaction<R>(
+        &mut self,
+        batch: impl FnOnce(&mut Calculator<T>) -> Result<R, CalcError<T>>,
+    ) -> Result<R, CalcError<T>> {
+        self.atomically(batch)
+            .map_err(|(step, error)| CalcError::TransactionFailed { step, error: Box::new(error) })
+    }
+    
+    // Make edits and keep them as one undoable step if they succeed. On failure the edits are
+    // reverted and the settings restored, and the error comes with the number of edits recorded
+    // before it. History is never copied: the edits start on empty undo and redo stacks, so they
+    // can only undo each other, and those stacks say how to take them back.
+    pub(crate) fn atomically<R>(
+        &mut self,
+        edits: impl FnOnce(&mut Calculator<T>) -> Result<R, CalcError<T>>,
+    ) -> Result<R, (usize, CalcError<T>)> {
+        let undo_stack = std::mem::take(&mut self.undo_stack);
+        let redo_stack = std::mem::take(&mut self.redo_stack);
+        let steps = std::mem::replace(&mut self.steps, 0);
+        let (next_id, policy, rounding, format) = (self.next_id, self.policy, self.rounding, self.format);
+        let modulus = self.modulus.clone();
+        let registered = self.operations.len();
+        
+        let result = edits(self);
+        let batch = std::mem::replace(&mut self.undo_stack, undo_stack);
+        let step = std::mem::replace(&mut self.steps, steps);
+        match result {
+            Ok(value) if batch.is_empty() => {
+                self.redo_stack = redo_stack;
+                Ok(value)
+            }
+            Ok(value) => {
+                self.record(HistoryEdit::Batch(batch));
+                Ok(value)
+            }
+            Err(error) => {
+                for edit in batch.into_iter().rev() {
+                    self.revert(edit);
+                }
+                self.redo_stack = redo_stack;
+                self.next_id = next_id;
+                self.policy = policy;
+                self.rounding = rounding;
+                self.format = format;
+                self.modulus = modulus;
+                self.operations.truncate(registered);
+                Err((step, error))
+            }
+        }
+    }
+    
+    // Remember a new edit; any new edit invalidates what could be redone
+    fn record(&mut self, edit: HistoryEdit<T>) {
+        self.steps += 1;
+        self.undo_stack.push(edit);
+        self.redo_stack.clear();
+    }
+    
+    // Apply the reverse of an edit and return the edit that reverses it back
+    fn revert(&mut self, edit: HistoryEdit<T>) -> HistoryEdit<T> {
+        match edit {
+            HistoryEdit::Push(operation) => {
+                self.history.pop();
+                HistoryEdit::Push(operation)
+            }
+            HistoryEdit::Replace(entries) => HistoryEdit::Replace(std::mem::replace(&mut self.history, entries)),
+            // Newest edit first; the reversed edits are kept oldest first again
+            HistoryEdit::Batch(edits) => {
+                let mut reversed: Vec<_> = edits.into_iter().rev().map(|edit| self.revert(edit)).collect();
+                reversed.reverse();
+                HistoryEdit::Batch(reversed)
+            }
+        }
+    }
+    
+    // Apply an edit again after it was undone and return the edit that undoes it
+    fn reapply(&mut self, edit: HistoryEdit<T>) -> HistoryEdit<T> {
+        match edit {
+            HistoryEdit::Push(operation) => {
+                self.history.push(operation.clone());
+                HistoryEdit::Push(operation)
+            }
+            HistoryEdit::Replace(entries) => HistoryEdit::Replace(std::mem::replace(&mut self.history, entries)),
+            HistoryEdit::Batch(edits) => HistoryEdit::Batch(edits.into_iter().map(|edit| self.reapply(edit)).collect()),
+        }
+    }
+    
+    // Take back the most recent operation, repeat, clear, load or transaction
+    pub fn undo(&mut self) -> Result<(), CalcError<T>> {
+        let edit = self.undo_stack.pop().ok_or(CalcError::NothingToUndo)?;
+        let reverse = self.revert(edit);
+        self.redo_stack.push(reverse);
+        Ok(())
+    }
+    
+    // Re-apply the most recently undone edit
+    pub fn redo(&mut self) -> Result<(), CalcError<T>> {
+        let edit = self.redo_stack.pop().ok_or(CalcError::NothingToRedo)?;
+        let reverse = self.reapply(edit);
+        self.undo_stack.push(reverse);
+        Ok(())
+    }
+    
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+    
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+    
+    pub fn number_format(&self) -> NumberFormat {
+        self.format
+    }
+    
+    // Change how show_history prints operands and results
+    pub fn set_number_format(&mut self, format: NumberFormat) {
+        self.format = format;
+    }
+    
+    pub fn set_radix(&mut self, radix: Radix) {
+        self.format.radix = radix;
+    }
+    
+    // Show negative numbers in two's complement at the given width, or with a sign for None
+    pub fn set_twos_complement(&mut self, width: Option<BitWidth>) {
+        self.format.twos_complement = width;
+    }
+    
+    // Generate a formatted string showing all operations in history, in the Calculator's NumberFormat
+    pub fn show_history(&self) -> String {
+        self.show_history_in(self.format)
+    }
+    
+    // Same as show_history, with operands and results printed in the given radix or NumberFormat
+    pub fn show_history_in(&self, format: impl Into<NumberFormat>) -> String {
+        let format = format.into();
+        let mut result = String::new();
+        for (index, operation) in self.history.iter().enumerate() {
+            result.push_str(&format!("{}: {}", index, operation.describe_in(format)));
+            // An assignment of a plain value such as "fee = 25" already shows the result
+            let plain_assignment = matches!(
+                &operation.kind,
+                OperationKind::Unary { operation_type: OperationType::Assign, operand } if operand.reference.is_none()
+            );
+            if !plain_assignment {
+                result.push_str(&format!(" = {}", operation.result.to_radix_string(format)));
+            }
+            if operation.overflowed {
+                result.push_str(" (overflowed)");
+            }
+            if operation.rounding != Rounding::default() {
+                result.push_str(&format!(" (rounded {})", operation.rounding.name()));
+            }
+            if let Some(modulus) = &operation.modulus {
+                result.push_str(&format!(" (mod {})", modulus.to_radix_string(format)));
+            }
+            result.push('\n');
+        }
+        result
+    }
+    
+    // Report every history entry whose stored result no longer matches recomputation.
+    // Referenced operands are read again from the entries they refer to.
+    pub fn verify_history(&self) -> Vec<HistoryMismatch<T>> {
+        self.history
+            .iter()
+            .enumerate()
+            .filter_map(|(index, operation)| {
+                let mut resolved = operation.clone();
+                let recomputed = resolved.resolve(&self.history[..index]).and_then(|_| resolved.recompute());
+                (recomputed.as_ref() != Ok(&operation.result)).then(|| HistoryMismatch {
+                    index,
+                    operation: operation.clone(),
+                    recomputed,
+                })
+            })
+            .collect()
+    }
+    
+    // Repeat an operation from history by index
+    pub fn repeat(&mut self, operation_index: usize) -> Result<T, CalcError<T>> {
+        let operation = self.history.get(operation_index).cloned().ok_or(CalcError::InvalidHistoryIndex {
+            index: operation_index,
+            len: self.history.len(),
+        })?;
+        // The copy keeps the policy, rounding and modulus of the original entry
+        self.push_evaluated(operation)
+    }
+    
+    // Clear all operations from history. Variables and the memory register live in history
+    // as assignments, so they are deleted too; undo restores the entries and the variables.
+    pub fn clear_history(&mut self) {
+        if !self.history.is_empty() {
+            self.replace_history(Vec::new());
+        }
     }
 }
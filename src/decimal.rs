@@ -0,0 +1,344 @@
+//!-------------------------------------------------------------------------------
+//!
+//! Fixed-point decimals for the Calculator.
+//!
+//! A FixedDecimal is an unsigned base-unit amount together with its number
+//! of decimals, the way token balances are stored on chain: 1.5 SOL is
+//! raw 1_500_000_000 with 9 decimals. Operands with different decimals are
+//! brought to the larger count, and results that cannot be represented
+//! exactly are rounded with an explicit Rounding mode.
+//!
+//!-------------------------------------------------------------------------------
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bigint::BigInt;
+use crate::calculator::*;
+
+// Equality and ordering compare values, so 1.5 and 1.50 are equal
+#[derive(Debug, Clone, Copy)]
+pub struct FixedDecimal {
+    raw: u128,
+    decimals: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError;
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid decimal literal")
+    }
+}
+
+impl std::error::Error for ParseDecimalError {}
+
+fn power_of_ten(exponent: u8) -> Option<u128> {
+    10u128.checked_pow(exponent as u32)
+}
+
+// Intermediate products can need up to 2 * MAX_DECIMALS digits of scale
+fn big_power_of_ten(exponent: u8) -> BigInt {
+    (0..exponent).fold(BigInt::from(1), |value, _| value * BigInt::from(10))
+}
+
+impl FixedDecimal {
+    // u128 holds every value below 10^38, so more decimals could not even represent 1
+    pub const MAX_DECIMALS: u8 = 38;
+
+    // Returns None if decimals exceeds MAX_DECIMALS
+    pub fn new(raw: u128, decimals: u8) -> Option<FixedDecimal> {
+        if decimals > FixedDecimal::MAX_DECIMALS {
+            return None;
+        }
+        Some(FixedDecimal { raw, decimals })
+    }
+
+    // A whole number of units, e.g. from_units(2, 9) is 2.000000000
+    pub fn from_units(units: u128, decimals: u8) -> Option<FixedDecimal> {
+        FixedDecimal::new(units.checked_mul(power_of_ten(decimals)?)?, decimals)
+    }
+
+    pub fn zero() -> FixedDecimal {
+        FixedDecimal { raw: 0, decimals: 0 }
+    }
+
+    pub fn raw(&self) -> u128 {
+        self.raw
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.raw == 0
+    }
+
+    // Convert to another number of decimals, rounding if digits are dropped
+    pub fn rescale(&self, decimals: u8, rounding: Rounding) -> Option<FixedDecimal> {
+        match decimals.cmp(&self.decimals) {
+            Ordering::Equal => Some(*self),
+            Ordering::Greater => {
+                FixedDecimal::new(self.raw.checked_mul(power_of_ten(decimals - self.decimals)?)?, decimals)
+            }
+            Ordering::Less => {
+                let divisor = big_power_of_ten(self.decimals - decimals);
+                let raw = BigInt::from(self.raw).checked_div_rounded(&divisor, rounding)?;
+                FixedDecimal::new(u128::try_from(&raw).ok()?, decimals)
+            }
+        }
+    }
+
+    // Both operands at the larger of their decimal counts; only fails if that overflows
+    fn aligned(&self, other: &FixedDecimal) -> Option<(u128, u128, u8)> {
+        let decimals = self.decimals.max(other.decimals);
+        let x = self.rescale(decimals, Rounding::Floor)?;
+        let y = other.rescale(decimals, Rounding::Floor)?;
+        Some((x.raw, y.raw, decimals))
+    }
+
+    pub fn checked_add(&self, other: &FixedDecimal) -> Option<FixedDecimal> {
+        let (x, y, decimals) = self.aligned(other)?;
+        FixedDecimal::new(x.checked_add(y)?, decimals)
+    }
+
+    pub fn checked_sub(&self, other: &FixedDecimal) -> Option<FixedDecimal> {
+        let (x, y, decimals) = self.aligned(other)?;
+        FixedDecimal::new(x.checked_sub(y)?, decimals)
+    }
+
+    // The product keeps the larger decimal count; the exact product is rounded to fit
+    pub fn checked_mul(&self, other: &FixedDecimal, rounding: Rounding) -> Option<FixedDecimal> {
+        let decimals = self.decimals.max(other.decimals);
+        let product = BigInt::from(self.raw) * BigInt::from(other.raw);
+        let scale = big_power_of_ten(self.decimals + other.decimals - decimals);
+        let raw = product.checked_div_rounded(&scale, rounding)?;
+        FixedDecimal::new(u128::try_from(&raw).ok()?, decimals)
+    }
+
+    // The quotient keeps the larger decimal count. Returns None when dividing by zero.
+    pub fn checked_div(&self, other: &FixedDecimal, rounding: Rounding) -> Option<FixedDecimal> {
+        if other.is_zero() {
+            return None;
+        }
+        let decimals = self.decimals.max(other.decimals);
+        // raw = x.raw / 10^dx / (y.raw / 10^dy) * 10^d
+        let scale = big_power_of_ten(decimals + other.decimals - self.decimals);
+        let numerator = BigInt::from(self.raw) * scale;
+        let raw = numerator.checked_div_rounded(&BigInt::from(other.raw), rounding)?;
+        FixedDecimal::new(u128::try_from(&raw).ok()?, decimals)
+    }
+
+    // x * y / z from a single rounding of the exact value, at the largest decimal count of the three
+    pub fn checked_mul_div(&self, y: &FixedDecimal, z: &FixedDecimal, rounding: Rounding) -> Option<FixedDecimal> {
+        if z.is_zero() {
+            return None;
+        }
+        let decimals = self.decimals.max(y.decimals).max(z.decimals);
+        // raw = x.raw * y.raw * 10^(d + dz - dx - dy) / z.raw, moving a negative exponent to the divisor
+        let (up, down) = (decimals + z.decimals, self.decimals + y.decimals);
+        let numerator = BigInt::from(self.raw) * BigInt::from(y.raw) * big_power_of_ten(up.saturating_sub(down));
+        let denominator = BigInt::from(z.raw) * big_power_of_ten(down.saturating_sub(up));
+        let raw = numerator.checked_div_rounded(&denominator, rounding)?;
+        FixedDecimal::new(u128::try_from(&raw).ok()?, decimals)
+    }
+
+    // Raised to exponent at the same decimal count, from a single rounding of the exact power.
+    // Returns None when the result does not fit or the exact power is too large to compute.
+    pub fn checked_pow(&self, exponent: u32, rounding: Rounding) -> Option<FixedDecimal> {
+        let unit = power_of_ten(self.decimals)?;
+        if exponent == 0 || self.raw == unit {
+            return FixedDecimal::new(unit, self.decimals);
+        }
+        if self.raw == 0 {
+            return Some(*self);
+        }
+        if !self.pow_within_limit(exponent) {
+            return None;
+        }
+        // The exact power has decimals * exponent decimals
+        let power = BigInt::from(self.raw).pow(exponent);
+        let scale = BigInt::from(10).pow(u32::from(self.decimals).checked_mul(exponent - 1)?);
+        let raw = power.checked_div_rounded(&scale, rounding)?;
+        FixedDecimal::new(u128::try_from(&raw).ok()?, self.decimals)
+    }
+
+    // Whether the exact power and its scale, 10^(decimals * (exponent - 1)) with less than
+    // 10/3 bits per digit, both stay within MAX_RESULT_BITS. 0 and 1 need neither.
+    fn pow_within_limit(&self, exponent: u32) -> bool {
+        let bits = (128 - self.raw.leading_zeros()) as u64;
+        let scale_bits = (self.decimals as u64 * exponent.saturating_sub(1) as u64 * 10).div_ceil(3);
+        Some(self.raw) == power_of_ten(self.decimals)
+            || self.raw == 0
+            || (bits * exponent as u64 <= MAX_RESULT_BITS && scale_bits <= MAX_RESULT_BITS)
+    }
+
+    // Exact remainder at the larger decimal count; None when dividing by zero
+    pub fn checked_rem(&self, other: &FixedDecimal) -> Option<FixedDecimal> {
+        let (x, y, decimals) = self.aligned(other)?;
+        FixedDecimal::new(x.checked_rem(y)?, decimals)
+    }
+}
+
+impl PartialEq for FixedDecimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FixedDecimal {}
+
+impl Ord for FixedDecimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let decimals = self.decimals.max(other.decimals);
+        let scaled = |value: &FixedDecimal| BigInt::from(value.raw) * big_power_of_ten(decimals - value.decimals);
+        scaled(self).cmp(&scaled(other))
+    }
+}
+
+impl PartialOrd for FixedDecimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+macro_rules! from_unsigned {
+    ($($t:ty),*) => {$(
+        impl From<$t> for FixedDecimal {
+            fn from(value: $t) -> Self {
+                FixedDecimal { raw: value as u128, decimals: 0 }
+            }
+        }
+    )*};
+}
+
+from_unsigned!(u8, u16, u32, u64, u128);
+
+impl FromStr for FixedDecimal {
+    type Err = ParseDecimalError;
+
+    // Parse "123" or "1.50"; the number of fraction digits becomes the decimal count
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) || text.ends_with('.') {
+            return Err(ParseDecimalError);
+        }
+        let decimals = u8::try_from(fraction.len()).map_err(|_| ParseDecimalError)?;
+        let raw = format!("{}{}", whole, fraction).parse::<u128>().map_err(|_| ParseDecimalError)?;
+        FixedDecimal::new(raw, decimals).ok_or(ParseDecimalError)
+    }
+}
+
+impl fmt::Display for FixedDecimal {
+    // Every decimal is printed, so the text parses back to the same decimal count
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = format!("{:0>width$}", self.raw, width = self.decimals as usize + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - self.decimals as usize);
+        if fraction.is_empty() {
+            write!(f, "{}", whole)
+        } else {
+            write!(f, "{}.{}", whole, fraction)
+        }
+    }
+}
+
+// Decimals never wrap around, so every overflow policy reports overflow as an error.
+// Products, quotients and powers round as the Calculator's rounding setting says.
+impl CheckedInt for FixedDecimal {
+    const NAME: &'static str = "FixedDecimal";
+
+    fn zero() -> Self {
+        FixedDecimal::zero()
+    }
+
+    fn apply(operation_type: OperationType, x: &Self, y: &Self, policy: OverflowPolicy) -> Option<(Self, bool)> {
+        Self::apply_rounded(operation_type, x, y, policy, Rounding::default())
+    }
+
+    fn apply_rounded(
+        operation_type: OperationType,
+        x: &Self,
+        y: &Self,
+        _policy: OverflowPolicy,
+        rounding: Rounding,
+    ) -> Option<(Self, bool)> {
+        let value = match operation_type {
+            OperationType::Addition => x.checked_add(y),
+            OperationType::Subtraction => x.checked_sub(y),
+            OperationType::Multiplication => x.checked_mul(y, rounding),
+            OperationType::Division => x.checked_div(y, rounding),
+            // Values are never negative, so both remainders agree
+            OperationType::Remainder | OperationType::EuclidMod => x.checked_rem(y),
+            OperationType::Assign => Some(*x),
+            // Built from other operations by OperationType::compute
+            OperationType::Neg
+            | OperationType::Abs
+            | OperationType::Signum
+            | OperationType::Square
+            | OperationType::Factorial => None,
+            OperationType::Power => x.checked_pow(y.to_u32()?, rounding),
+            // Bit operations need a fixed width, and roots and logarithms integers;
+            // supports rejects both
+            OperationType::Isqrt
+            | OperationType::Icbrt
+            | OperationType::Ilog2
+            | OperationType::Ilog10
+            | OperationType::Ilog
+            | OperationType::Gcd
+            | OperationType::Lcm
+            | OperationType::ModInverse
+            | OperationType::And
+            | OperationType::Or
+            | OperationType::Xor
+            | OperationType::Not
+            | OperationType::Shl
+            | OperationType::Shr
+            | OperationType::LogicalShr
+            | OperationType::RotateLeft
+            | OperationType::RotateRight => None,
+        };
+        value.map(|value| (value, false))
+    }
+
+    fn rounds(operation_type: OperationType) -> bool {
+        matches!(
+            operation_type,
+            OperationType::Multiplication | OperationType::Division | OperationType::Power | OperationType::Square
+        )
+    }
+
+    fn mul_div(x: &Self, y: &Self, z: &Self, rounding: Rounding) -> Option<Self> {
+        x.checked_mul_div(y, z, rounding)
+    }
+
+    // Summed at the largest decimal count of the values
+    fn mean(values: &[Self], rounding: Rounding) -> Option<Self> {
+        let decimals = values.iter().map(FixedDecimal::decimals).max()?;
+        let sum = values.iter().fold(BigInt::zero(), |sum, value| {
+            sum + BigInt::from(value.raw) * big_power_of_ten(decimals - value.decimals)
+        });
+        let raw = sum.checked_div_rounded(&BigInt::from(values.len()), rounding)?;
+        FixedDecimal::new(u128::try_from(&raw).ok()?, decimals)
+    }
+
+    // Whole numbers such as 3 or 3.00 can be exponents
+    fn to_u32(&self) -> Option<u32> {
+        let unit = power_of_ten(self.decimals)?;
+        if !self.raw.is_multiple_of(unit) {
+            return None;
+        }
+        u32::try_from(self.raw / unit).ok()
+    }
+
+    fn supports(operation_type: OperationType) -> bool {
+        !operation_type.is_bitwise() && !operation_type.is_integer_only()
+    }
+
+    fn within_size_limit(operation_type: OperationType, x: &Self, y: &Self) -> bool {
+        operation_type != OperationType::Power || y.to_u32().is_none_or(|exponent| x.pow_within_limit(exponent))
+    }
+}
@@ -0,0 +1,571 @@
+//!-------------------------------------------------------------------------------
+//!
+//! Infix expression support for the Calculator.
+//!
+//! Expressions such as "(-4 + 9) * 3 - 2" or "(flags >> 4) & 0xf" are
+//! tokenized, parsed into an Expr tree with precedence climbing and then
+//! evaluated through the same checked operations the Calculator methods
+//! use, so every binary step, every "!" and every function call ends up in
+//! history. Operator precedence follows Rust; a "!" after an operand is a
+//! factorial and binds tighter than any prefix operator, and "**" binds
+//! tighter than a sign, so "-2 ** 2" is -4.
+//!
+//! "$n" stands for the result of history entry n and "ans" for the last
+//! result before the expression. "let fee = 25" assigns a variable that
+//! later expressions can use by name. Steps that use a reference or a
+//! variable directly record it, so history shows "$1 * fee" rather than
+//! the values.
+//!
+//! Ternary operations such as "mul_div(fee, 3, 7)" and aggregates such
+//! as "sum(a, b, c)" are called like functions and record their
+//! arguments the same way.
+//!
+//! Operations registered with Calculator::register_operation are parsed
+//! by their symbol, either called like "avg(3, 5)" or, when they have a
+//! precedence, written between their operands like "3 absdiff 5".
+//!
+//!-------------------------------------------------------------------------------
+
+use crate::calculator::*;
+use crate::operations::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    // The text of a numeric literal such as "42", "1.5" or "0xff_ff", converted to a value by the parser
+    Number(String),
+    Operator(OperationType),
+    Minus,
+    // "~", the bitwise complement
+    Not,
+    // "!", the bitwise complement before an operand and factorial after one
+    Bang,
+    // A function name such as "isqrt", a variable, "ans" or "let"
+    Identifier(String),
+    // "$n", the result of history entry n
+    HistoryRef(usize),
+    LeftParen,
+    RightParen,
+    Comma,
+    // "=" after "let name"
+    Equals,
+}
+
+// A token together with the 1-based column it starts at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spanned {
+    pub token: Token,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr<T = i64> {
+    Number(T),
+    HistoryRef(usize),
+    // The last result before the expression was evaluated
+    Ans,
+    Variable(String),
+    // "let name = expression", only at the top level
+    Let(String, Box<Expr<T>>),
+    Negate(Box<Expr<T>>),
+    Unary(OperationType, Box<Expr<T>>),
+    Binary(OperationType, Box<Expr<T>>, Box<Expr<T>>),
+    // A registered operation that is not built in
+    Registered(RegisteredOperation<T>, Box<Expr<T>>, Box<Expr<T>>),
+    // A call such as "mul_div(a, b, c)", or "apply_bps(a, b)" whose divisor is fixed
+    Ternary(TernaryOperationType, Vec<Expr<T>>),
+    // A call such as "sum(a, b, c)" with any number of arguments
+    Aggregate(AggregateOperationType, Vec<Expr<T>>),
+}
+
+// Build a parse error pointing at the given column
+fn parse_error<T>(column: usize, message: impl Into<String>) -> CalcError<T> {
+    CalcError::Parse { column, message: message.into() }
+}
+
+// Split the input into tokens, remembering where each one starts
+pub fn tokenize<T>(input: &str) -> Result<Vec<Spanned>, CalcError<T>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let column = index + 1;
+        let c = chars[index];
+
+        if c.is_whitespace() {
+            index += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_digit() {
+            let start = index;
+            // After 0x, 0o or 0b letters are digits too; invalid ones are reported by the parser
+            let prefixed = c == '0' && matches!(chars.get(index + 1), Some('x' | 'o' | 'b'));
+            if prefixed {
+                index += 2;
+            }
+            let is_digit = |c: char| if prefixed { c.is_ascii_alphanumeric() } else { c.is_ascii_digit() || c == '.' };
+            while index < chars.len() && (is_digit(chars[index]) || chars[index] == '_') {
+                index += 1;
+            }
+            let digits = chars[start..index].iter().collect();
+            tokens.push(Spanned { token: Token::Number(digits), column });
+            continue;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_') {
+                index += 1;
+            }
+            let word: String = chars[start..index].iter().collect();
+            match word.as_str() {
+                "mod" => tokens.push(Spanned { token: Token::Operator(OperationType::EuclidMod), column }),
+                "rotl" => tokens.push(Spanned { token: Token::Operator(OperationType::RotateLeft), column }),
+                "rotr" => tokens.push(Spanned { token: Token::Operator(OperationType::RotateRight), column }),
+                _ => tokens.push(Spanned { token: Token::Identifier(word), column }),
+            }
+            continue;
+        } else if c == '$' {
+            let start = index + 1;
+            index = start;
+            while index < chars.len() && chars[index].is_ascii_digit() {
+                index += 1;
+            }
+            let digits: String = chars[start..index].iter().collect();
+            if digits.is_empty() {
+                return Err(parse_error(column, "expected a history index after '$'"));
+            }
+            let history_index = digits
+                .parse()
+                .map_err(|_| parse_error(column, format!("history index ${} is too large", digits)))?;
+            tokens.push(Spanned { token: Token::HistoryRef(history_index), column });
+            continue;
+        } else if c == '<' || c == '>' {
+            // Shifts are the only operators longer than one character: <<, >> and >>>
+            let operators = [(">>>", OperationType::LogicalShr), ("<<", OperationType::Shl), (">>", OperationType::Shr)];
+            let rest: String = chars[index..].iter().take(3).collect();
+            match operators.iter().find(|(text, _)| rest.starts_with(text)) {
+                Some((text, operation_type)) => {
+                    tokens.push(Spanned { token: Token::Operator(*operation_type), column });
+                    index += text.len();
+                    continue;
+                }
+                None => return Err(parse_error(column, format!("unexpected character '{}'", c))),
+            }
+        } else {
+            match c {
+                '+' => Token::Operator(OperationType::Addition),
+                '-' => Token::Minus,
+                '*' if chars.get(index + 1) == Some(&'*') => {
+                    tokens.push(Spanned { token: Token::Operator(OperationType::Power), column });
+                    index += 2;
+                    continue;
+                }
+                '*' => Token::Operator(OperationType::Multiplication),
+                '/' => Token::Operator(OperationType::Division),
+                '%' => Token::Operator(OperationType::Remainder),
+                '&' => Token::Operator(OperationType::And),
+                '|' => Token::Operator(OperationType::Or),
+                '^' => Token::Operator(OperationType::Xor),
+                '!' => Token::Bang,
+                '~' => Token::Not,
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                ',' => Token::Comma,
+                '=' => Token::Equals,
+                _ => return Err(parse_error(column, format!("unexpected character '{}'", c))),
+            }
+        };
+        tokens.push(Spanned { token, column });
+        index += 1;
+    }
+
+    Ok(tokens)
+}
+
+// Binding power of a binary operator; higher binds tighter
+pub(crate) fn precedence(operation_type: OperationType) -> u8 {
+    match operation_type {
+        OperationType::Or => 1,
+        OperationType::Xor => 2,
+        OperationType::And => 3,
+        OperationType::Shl
+        | OperationType::Shr
+        | OperationType::LogicalShr
+        | OperationType::RotateLeft
+        | OperationType::RotateRight => 4,
+        OperationType::Addition | OperationType::Subtraction => 5,
+        OperationType::Multiplication
+        | OperationType::Division
+        | OperationType::Remainder
+        | OperationType::EuclidMod => 6,
+        // Right-associative, see Parser::expression
+        OperationType::Power => 7,
+        // Unary or called like functions, never produced by peek_operator
+        OperationType::Not
+        | OperationType::Isqrt
+        | OperationType::Icbrt
+        | OperationType::Ilog2
+        | OperationType::Ilog10
+        | OperationType::Ilog
+        | OperationType::Gcd
+        | OperationType::Lcm
+        | OperationType::ModInverse
+        | OperationType::Assign
+        | OperationType::Neg
+        | OperationType::Abs
+        | OperationType::Signum
+        | OperationType::Square
+        | OperationType::Factorial => 8,
+    }
+}
+
+// The operation called by name, e.g. "isqrt" in "isqrt(16)"
+fn function(name: &str) -> Option<OperationType> {
+    OperationType::from_name(name)
+        .filter(|operation_type| operation_type.get_sign() == name)
+        .filter(|operation_type| operation_type.is_unary() || operation_type.is_function())
+}
+
+// Words with a meaning of their own in expressions
+const KEYWORDS: [&str; 5] = ["let", "ans", "mod", "rotl", "rotr"];
+
+// Whether name can be assigned with let: an identifier that is not a keyword or function name
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_like_identifier = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_');
+    starts_like_identifier
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+        && function(name).is_none()
+        && TernaryOperationType::from_name(name).is_none()
+        && AggregateOperationType::from_name(name).is_none()
+}
+
+// A step performing a registered operation; built-in ones are recorded as their OperationType
+fn binary<T>(operation: RegisteredOperation<T>, lhs: Expr<T>, rhs: Expr<T>) -> Expr<T> {
+    match operation.operation_type() {
+        Some(operation_type) => Expr::Binary(operation_type, Box::new(lhs), Box::new(rhs)),
+        None => Expr::Registered(operation, Box::new(lhs), Box::new(rhs)),
+    }
+}
+
+// Convert a literal to a value, failing if it is malformed or does not fit in T
+fn literal<T: CheckedInt>(text: &str, column: usize) -> Result<T, CalcError<T>> {
+    parse_literal(text).ok_or_else(|| {
+        let (radix, digits) = Radix::split_prefix(text.trim_start_matches('-'));
+        let digits: String = digits.chars().filter(|&c| c != '_').collect();
+        if radix != Radix::Decimal && T::from_str_radix("0", radix).is_none() {
+            parse_error(column, format!("{} literals are not supported by {}", radix.name(), T::NAME))
+        } else if !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix.base())) {
+            parse_error(column, format!("integer literal out of range for {}", T::NAME))
+        } else {
+            parse_error(column, format!("'{}' is not a valid {} literal", text, T::NAME))
+        }
+    })
+}
+
+struct Parser<'a, T> {
+    tokens: Vec<Spanned>,
+    position: usize,
+    // Column just past the end of the input, used for "unexpected end" errors
+    end_column: usize,
+    operations: &'a OperationRegistry<T>,
+}
+
+impl<T: CheckedInt> Parser<'_, T> {
+    fn peek(&self) -> Option<&Spanned> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Spanned> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn column(&self) -> usize {
+        self.peek().map_or(self.end_column, |spanned| spanned.column)
+    }
+
+    // Return the binary operator at the current position and its precedence, if any.
+    // Registered operations with a precedence are operators too, e.g. "3 absdiff 5".
+    fn peek_operator(&self) -> Option<(RegisteredOperation<T>, u8)> {
+        let symbol = match &self.peek()?.token {
+            Token::Operator(operation_type) => operation_type.get_sign(),
+            Token::Minus => OperationType::Subtraction.get_sign(),
+            Token::Identifier(word) => word,
+            _ => return None,
+        };
+        let operation = self.operations.by_symbol(symbol)?;
+        Some((operation.clone(), operation.precedence()?))
+    }
+
+    // Whether name is called like a function rather than read as a variable
+    fn is_function(&self, name: &str) -> bool {
+        function(name).is_some()
+            || TernaryOperationType::from_name(name).is_some()
+            || AggregateOperationType::from_name(name).is_some()
+            || self.operations.by_symbol(name).is_some()
+    }
+
+    // Precedence climbing: parse operands joined by operators binding at least min_precedence
+    fn expression(&mut self, min_precedence: u8) -> Result<Expr<T>, CalcError<T>> {
+        let mut lhs = self.unary()?;
+        while let Some((operation, operator_precedence)) = self.peek_operator() {
+            if operator_precedence < min_precedence {
+                break;
+            }
+            self.next();
+            // Power is right-associative like in mathematics, all other operators are left-associative
+            let rhs = if operation.operation_type() == Some(OperationType::Power) {
+                self.expression(operator_precedence)?
+            } else {
+                self.expression(operator_precedence + 1)?
+            };
+            lhs = binary(operation, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr<T>, CalcError<T>> {
+        if matches!(self.peek().map(|spanned| &spanned.token), Some(Token::Minus)) {
+            self.next();
+            // Fold the sign into a literal so that i64::MIN can be written directly,
+            // except before a factorial or a power: -3! is -(3!) and -2 ** 2 is -(2 ** 2)
+            if let Some(Spanned { token: Token::Number(digits), column }) = self.peek().cloned() {
+                let after = self.tokens.get(self.position + 1).map(|spanned| &spanned.token);
+                if !matches!(after, Some(Token::Bang | Token::Operator(OperationType::Power))) {
+                    self.next();
+                    return literal(&format!("-{}", digits), column).map(Expr::Number);
+                }
+            }
+            return Ok(Expr::Negate(Box::new(self.power()?)));
+        }
+        if matches!(self.peek().map(|spanned| &spanned.token), Some(Token::Not | Token::Bang)) {
+            self.next();
+            return Ok(Expr::Unary(OperationType::Not, Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    // The operand of a sign, which binds looser than "**" like in mathematics
+    fn power(&mut self) -> Result<Expr<T>, CalcError<T>> {
+        let base = self.unary()?;
+        match self.peek_operator() {
+            Some((operation, precedence)) if operation.operation_type() == Some(OperationType::Power) => {
+                self.next();
+                let exponent = self.expression(precedence)?;
+                Ok(binary(operation, base, exponent))
+            }
+            _ => Ok(base),
+        }
+    }
+
+    // A primary followed by any number of factorials, e.g. "3!!"
+    fn postfix(&mut self) -> Result<Expr<T>, CalcError<T>> {
+        let mut expr = self.primary()?;
+        while matches!(self.peek().map(|spanned| &spanned.token), Some(Token::Bang)) {
+            self.next();
+            expr = Expr::Unary(OperationType::Factorial, Box::new(expr));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr<T>, CalcError<T>> {
+        let column = self.column();
+        match self.next().map(|spanned| spanned.token) {
+            Some(Token::Number(digits)) => literal(&digits, column).map(Expr::Number),
+            Some(Token::HistoryRef(index)) => Ok(Expr::HistoryRef(index)),
+            Some(Token::Identifier(name)) if name == "ans" => Ok(Expr::Ans),
+            // Functions are always called; any other name without "(" is a variable
+            Some(Token::Identifier(name))
+                if !self.is_function(&name)
+                    && !matches!(self.peek().map(|spanned| &spanned.token), Some(Token::LeftParen)) =>
+            {
+                Ok(Expr::Variable(name))
+            }
+            Some(Token::Identifier(name)) => self.call(&name, column),
+            Some(Token::LeftParen) => {
+                let inner = self.expression(1)?;
+                let close_column = self.column();
+                match self.next().map(|spanned| spanned.token) {
+                    Some(Token::RightParen) => Ok(inner),
+                    _ => Err(parse_error(close_column, "expected ')'")),
+                }
+            }
+            Some(_) => Err(parse_error(column, "expected a number, history reference or '('")),
+            None => Err(parse_error(column, "unexpected end of input")),
+        }
+    }
+
+    // "let name = expression" assigns to a variable, anything else is an expression
+    fn statement(&mut self) -> Result<Expr<T>, CalcError<T>> {
+        if !matches!(self.peek().map(|spanned| &spanned.token), Some(Token::Identifier(word)) if word == "let") {
+            return self.expression(1);
+        }
+        self.next();
+        let column = self.column();
+        let name = match self.next().map(|spanned| spanned.token) {
+            Some(Token::Identifier(name)) if is_variable_name(&name) && !self.is_function(&name) => name,
+            Some(Token::Identifier(name)) => {
+                return Err(parse_error(column, format!("'{}' cannot be used as a variable name", name)))
+            }
+            _ => return Err(parse_error(column, "expected a variable name after let")),
+        };
+        let equals_column = self.column();
+        if !matches!(self.next().map(|spanned| spanned.token), Some(Token::Equals)) {
+            return Err(parse_error(equals_column, format!("expected '=' after let {}", name)));
+        }
+        Ok(Expr::Let(name, Box::new(self.expression(1)?)))
+    }
+
+    // Parse the parenthesized arguments of a function such as isqrt(16) or ilog(100, 10)
+    fn call(&mut self, name: &str, column: usize) -> Result<Expr<T>, CalcError<T>> {
+        // Unary functions are built in, binary ones are looked up among the registered operations
+        let unary = function(name).filter(OperationType::is_unary);
+        let registered = self.operations.by_symbol(name).cloned();
+        let ternary = TernaryOperationType::from_name(name);
+        let aggregate = AggregateOperationType::from_name(name);
+        if unary.is_none() && registered.is_none() && ternary.is_none() && aggregate.is_none() {
+            return Err(parse_error(column, format!("unknown identifier '{}'", name)));
+        }
+        let paren_column = self.column();
+        if !matches!(self.next().map(|spanned| spanned.token), Some(Token::LeftParen)) {
+            return Err(parse_error(paren_column, format!("expected '(' after {}", name)));
+        }
+        let mut arguments = vec![self.expression(1)?];
+        while matches!(self.peek().map(|spanned| &spanned.token), Some(Token::Comma)) {
+            self.next();
+            arguments.push(self.expression(1)?);
+        }
+        let close_column = self.column();
+        if !matches!(self.next().map(|spanned| spanned.token), Some(Token::RightParen)) {
+            return Err(parse_error(close_column, "expected ')'"));
+        }
+        if let Some(operation_type) = aggregate {
+            return Ok(Expr::Aggregate(operation_type, arguments));
+        }
+        let expected = match ternary {
+            Some(operation_type) if operation_type.fixed_divisor().is_none() => 3,
+            _ if unary.is_some() => 1,
+            _ => 2,
+        };
+        if arguments.len() != expected {
+            let plural = if expected == 1 { "" } else { "s" };
+            return Err(parse_error(column, format!("{} takes {} argument{}", name, expected, plural)));
+        }
+        if let Some(operation_type) = ternary {
+            return Ok(Expr::Ternary(operation_type, arguments));
+        }
+        let mut arguments = arguments.into_iter();
+        let x = arguments.next().unwrap();
+        Ok(match (unary, registered, arguments.next()) {
+            (Some(operation_type), _, None) => Expr::Unary(operation_type, Box::new(x)),
+            (None, Some(operation), Some(y)) => binary(operation, x, y),
+            _ => unreachable!("the argument count matches the function"),
+        })
+    }
+}
+
+// Parse a complete infix expression or let statement that uses the built-in operations only
+pub fn parse<T: CheckedInt>(input: &str) -> Result<Expr<T>, CalcError<T>> {
+    parse_with(input, &OperationRegistry::default())
+}
+
+// Same as parse, also accepting the operations registered in operations
+pub fn parse_with<T: CheckedInt>(input: &str, operations: &OperationRegistry<T>) -> Result<Expr<T>, CalcError<T>> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        position: 0,
+        end_column: input.chars().count() + 1,
+        operations,
+    };
+    let expr = parser.statement()?;
+    if parser.peek().is_some() {
+        return Err(parse_error(parser.column(), "unexpected token"));
+    }
+    Ok(expr)
+}
+
+impl<T: CheckedInt> Calculator<T> {
+    // Parse and evaluate an infix expression, recording each binary step and assignment in history
+    pub fn evaluate(&mut self, input: &str) -> Result<T, CalcError<T>> {
+        let expr = parse_with(input, self.operations())?;
+        self.evaluate_expr(&expr)
+    }
+
+    // Evaluate an already parsed expression tree. Like a transaction, the steps are kept as
+    // one undoable step only if every one succeeds, so a failing expression such as
+    // "2 * 3 + 1 / 0" leaves history and variables as they were.
+    pub fn evaluate_expr(&mut self, expr: &Expr<T>) -> Result<T, CalcError<T>> {
+        // References see the history as it was before the first step
+        let visible = self.history.len();
+        self.atomically(|calculator| calculator.evaluate_in(expr, visible))
+            .map_err(|(_, error)| error)
+    }
+
+    fn evaluate_in(&mut self, expr: &Expr<T>, visible: usize) -> Result<T, CalcError<T>> {
+        match expr {
+            Expr::Number(value) => Ok(value.clone()),
+            Expr::HistoryRef(_) | Expr::Ans | Expr::Variable(_) => {
+                let operand = self.operand(expr, visible)?;
+                self.resolve(&operand)
+            }
+            Expr::Let(name, inner) => {
+                let value = self.operand(inner, visible)?;
+                self.assign(name, value)
+            }
+            // Negation is a step like neg(x), so ans and history show the negated result
+            Expr::Negate(inner) => {
+                let x = self.operand(inner, visible)?;
+                self.calculate_operands(OperationType::Neg, x, Operand::Value(T::zero()))
+            }
+            Expr::Unary(operation_type, inner) => {
+                let x = self.operand(inner, visible)?;
+                self.calculate_operands(*operation_type, x, Operand::Value(T::zero()))
+            }
+            Expr::Binary(operation_type, lhs, rhs) => {
+                let x = self.operand(lhs, visible)?;
+                let y = self.operand(rhs, visible)?;
+                self.calculate_operands(*operation_type, x, y)
+            }
+            Expr::Registered(operation, lhs, rhs) => {
+                let x = self.operand(lhs, visible)?;
+                let y = self.operand(rhs, visible)?;
+                self.calculate_registered(operation, x, y)
+            }
+            Expr::Ternary(operation_type, arguments) => {
+                let mut operands = self.operands(arguments, visible)?.into_iter();
+                let (x, y) = (operands.next().unwrap(), operands.next().unwrap());
+                match operands.next() {
+                    Some(z) => self.calculate_ternary(*operation_type, x, y, z),
+                    None => self.calculate_scaled(*operation_type, x, y),
+                }
+            }
+            Expr::Aggregate(operation_type, arguments) => {
+                let operands = self.operands(arguments, visible)?;
+                self.calculate_aggregate(*operation_type, &operands)
+            }
+        }
+    }
+
+    // An operand for a step: references stay references, anything else is evaluated.
+    // Only the first visible entries can be referenced, not steps of this expression.
+    fn operand(&mut self, expr: &Expr<T>, visible: usize) -> Result<Operand<T>, CalcError<T>> {
+        match expr {
+            Expr::HistoryRef(index) if *index < visible => Ok(Operand::HistoryRef(*index)),
+            Expr::HistoryRef(index) => Err(CalcError::InvalidHistoryIndex { index: *index, len: visible }),
+            Expr::Ans => visible
+                .checked_sub(1)
+                .map(Operand::HistoryRef)
+                .ok_or(CalcError::InvalidHistoryIndex { index: 0, len: 0 }),
+            Expr::Variable(name) => self.variable(name).map(|_| Operand::Variable(name.clone())),
+            _ => self.evaluate_in(expr, visible).map(Operand::Value),
+        }
+    }
+
+    // The operands for the arguments of a call, evaluated from left to right
+    fn operands(&mut self, arguments: &[Expr<T>], visible: usize) -> Result<Vec<Operand<T>>, CalcError<T>> {
+        arguments.iter().map(|argument| self.operand(argument, visible)).collect()
+    }
+}
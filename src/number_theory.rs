@@ -0,0 +1,180 @@
+//!-------------------------------------------------------------------------------
+//!
+//! Number theory helpers for checking cryptographic parameters.
+//!
+//! Everything works on u64 with u128 intermediates, so nothing here can
+//! overflow: lcm reports a result that does not fit as None. is_prime is
+//! a deterministic Miller-Rabin test that is exact for every u64, and
+//! factorize splits composites with Pollard's rho.
+//!
+//! The Calculator offers gcd, lcm and mod_inverse for all of its integer
+//! types as OperationType variants; types of up to 64 bits use the ones
+//! here and wider types BigInt. Its modular mode uses mod_add, mod_sub,
+//! mod_mul, mod_pow and mod_inverse for types of up to 64 bits, and
+//! Rational reduces its fractions with gcd_u128.
+//!
+//!-------------------------------------------------------------------------------
+
+// Greatest common divisor; gcd(0, 0) is 0
+pub fn gcd(a: u64, b: u64) -> u64 {
+    gcd_u128(a as u128, b as u128) as u64
+}
+
+// gcd of 128-bit magnitudes, such as those of a Rational's numerator and denominator
+pub fn gcd_u128(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// Least common multiple, or None if it does not fit in u64; lcm(0, n) is 0
+pub fn lcm(a: u64, b: u64) -> Option<u64> {
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+// Returns (g, x, y) with g = gcd(a, b) = a * x + b * y
+pub fn extended_gcd(a: u64, b: u64) -> (u64, i128, i128) {
+    let (mut old_r, mut r) = (a as i128, b as i128);
+    let (mut old_x, mut x) = (1i128, 0i128);
+    let (mut old_y, mut y) = (0i128, 1i128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_x, x) = (x, old_x - quotient * x);
+        (old_y, y) = (y, old_y - quotient * y);
+    }
+    (old_r as u64, old_x, old_y)
+}
+
+// (a + b) % modulus without overflowing; modulus must not be 0
+pub fn mod_add(a: u64, b: u64, modulus: u64) -> u64 {
+    ((a as u128 + b as u128) % modulus as u128) as u64
+}
+
+// (a - b) mod modulus in 0..modulus; modulus must not be 0
+pub fn mod_sub(a: u64, b: u64, modulus: u64) -> u64 {
+    let (a, b) = (a % modulus, b % modulus);
+    if a >= b {
+        a - b
+    } else {
+        modulus - (b - a)
+    }
+}
+
+// a * b % modulus without overflowing; modulus must not be 0
+pub fn mod_mul(a: u64, b: u64, modulus: u64) -> u64 {
+    (a as u128 * b as u128 % modulus as u128) as u64
+}
+
+// base^exponent % modulus by square-and-multiply; None when modulus is 0
+pub fn mod_pow(base: u64, mut exponent: u64, modulus: u64) -> Option<u64> {
+    if modulus == 0 {
+        return None;
+    }
+    let (mut result, mut base) = (1 % modulus, base % modulus);
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mod_mul(result, base, modulus);
+        }
+        base = mod_mul(base, base, modulus);
+        exponent >>= 1;
+    }
+    Some(result)
+}
+
+// The x in [0, modulus) with a * x % modulus == 1.
+// Returns None when modulus is 0 or a and modulus are not coprime.
+pub fn mod_inverse(a: u64, modulus: u64) -> Option<u64> {
+    if modulus == 0 {
+        return None;
+    }
+    let (g, x, _) = extended_gcd(a % modulus, modulus);
+    if g != 1 {
+        return None;
+    }
+    Some(x.rem_euclid(modulus as i128) as u64)
+}
+
+// These bases make Miller-Rabin exact for every n below 2^64
+const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+// Deterministic Miller-Rabin primality test
+pub fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for p in WITNESSES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    // n - 1 = d * 2^s with d odd
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    WITNESSES.iter().all(|&witness| {
+        let mut x = mod_pow(witness, d, n).unwrap();
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = mod_mul(x, x, n);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+// A non-trivial divisor of an odd composite n, by Pollard's rho with Floyd cycle detection
+fn pollard_rho(n: u64) -> u64 {
+    for increment in 1.. {
+        let step = |x: u64| mod_add(mod_mul(x, x, n), increment, n);
+        let (mut slow, mut fast, mut divisor) = (2, 2, 1);
+        while divisor == 1 {
+            slow = step(slow);
+            fast = step(step(fast));
+            divisor = gcd(slow.abs_diff(fast), n);
+        }
+        // A cycle without a divisor; try another polynomial
+        if divisor != n {
+            return divisor;
+        }
+    }
+    unreachable!("every odd composite has a divisor")
+}
+
+// Prime factors in ascending order, repeated by multiplicity; empty for 0 and 1
+pub fn factorize(n: u64) -> Vec<u64> {
+    let mut factors = Vec::new();
+    if n < 2 {
+        return factors;
+    }
+    // Trial division by the small primes leaves pollard_rho only odd composites
+    let mut rest = n;
+    for p in WITNESSES {
+        while rest.is_multiple_of(p) {
+            factors.push(p);
+            rest /= p;
+        }
+    }
+    let mut pending = vec![rest];
+    while let Some(m) = pending.pop() {
+        if m == 1 {
+            continue;
+        }
+        if is_prime(m) {
+            factors.push(m);
+        } else {
+            let divisor = pollard_rho(m);
+            pending.push(divisor);
+            pending.push(m / divisor);
+        }
+    }
+    factors.sort_unstable();
+    factors
+}
@@ -0,0 +1,191 @@
+//!-------------------------------------------------------------------------------
+//!
+//! An open set of binary operations for the Calculator.
+//!
+//! BinaryOperation describes an operation by its symbol, stable name,
+//! checked perform and optional infix precedence. Every Calculator owns an
+//! OperationRegistry that starts out with the binary OperationType
+//! variants; other operations such as avg or absdiff can be registered
+//! next to them and are then recorded in history, repeated, parsed in
+//! expressions and RPN lines, saved and displayed like the built-in ones.
+//!
+//!-------------------------------------------------------------------------------
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::calculator::*;
+use crate::expression::{is_variable_name, precedence};
+
+pub trait BinaryOperation<T>: Send + Sync {
+    // Written in expressions and shown in history, e.g. "+" or "avg"
+    fn symbol(&self) -> &str;
+
+    // Stable name used when history is saved to a file, e.g. "addition"
+    fn name(&self) -> &str;
+
+    // The result, or None when there is none or it does not fit in T
+    fn perform(&self, x: &T, y: &T) -> Option<T>;
+
+    // Binding power when written between the operands, from 1 like | to 7 like **.
+    // None for operations written as calls, e.g. avg(x, y).
+    fn precedence(&self) -> Option<u8> {
+        None
+    }
+
+    // The built-in operation this is; the Calculator computes those under its policy and modulus
+    fn operation_type(&self) -> Option<OperationType> {
+        None
+    }
+}
+
+// Every OperationType with two operands
+pub const BUILT_IN: [OperationType; 19] = [
+    OperationType::Addition,
+    OperationType::Subtraction,
+    OperationType::Multiplication,
+    OperationType::Division,
+    OperationType::Remainder,
+    OperationType::EuclidMod,
+    OperationType::And,
+    OperationType::Or,
+    OperationType::Xor,
+    OperationType::Shl,
+    OperationType::Shr,
+    OperationType::LogicalShr,
+    OperationType::RotateLeft,
+    OperationType::RotateRight,
+    OperationType::Power,
+    OperationType::Ilog,
+    OperationType::Gcd,
+    OperationType::Lcm,
+    OperationType::ModInverse,
+];
+
+impl<T: CheckedInt> BinaryOperation<T> for OperationType {
+    fn symbol(&self) -> &str {
+        self.get_sign()
+    }
+
+    fn name(&self) -> &str {
+        OperationType::name(self)
+    }
+
+    fn perform(&self, x: &T, y: &T) -> Option<T> {
+        self.checked_perform(x.clone(), y.clone())
+    }
+
+    fn precedence(&self) -> Option<u8> {
+        (!self.is_function()).then(|| precedence(*self))
+    }
+
+    fn operation_type(&self) -> Option<OperationType> {
+        Some(*self)
+    }
+}
+
+// A registered operation, shared by the registry and the history entries that use it.
+// Two of them are equal when they have the same name.
+pub struct RegisteredOperation<T>(Arc<dyn BinaryOperation<T>>);
+
+impl<T> RegisteredOperation<T> {
+    pub fn new(operation: impl BinaryOperation<T> + 'static) -> Self {
+        RegisteredOperation(Arc::new(operation))
+    }
+
+    // Return the operation without its result, e.g. "avg(3, 5)" or "3 absdiff 5"
+    pub fn describe(&self, x: &impl fmt::Display, y: &impl fmt::Display) -> String {
+        match self.precedence() {
+            Some(_) => format!("{} {} {}", x, self.symbol(), y),
+            None => format!("{}({}, {})", self.symbol(), x, y),
+        }
+    }
+}
+
+impl<T> std::ops::Deref for RegisteredOperation<T> {
+    type Target = dyn BinaryOperation<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl<T> Clone for RegisteredOperation<T> {
+    fn clone(&self) -> Self {
+        RegisteredOperation(Arc::clone(&self.0))
+    }
+}
+
+impl<T> PartialEq for RegisteredOperation<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl<T> Eq for RegisteredOperation<T> {}
+
+impl<T> fmt::Debug for RegisteredOperation<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RegisteredOperation({})", self.name())
+    }
+}
+
+// The operations a Calculator knows, looked up by symbol or name
+pub struct OperationRegistry<T> {
+    operations: Vec<RegisteredOperation<T>>,
+}
+
+// Only the built-in operations
+impl<T: CheckedInt> Default for OperationRegistry<T> {
+    fn default() -> Self {
+        OperationRegistry { operations: BUILT_IN.into_iter().map(RegisteredOperation::new).collect() }
+    }
+}
+
+impl<T> Clone for OperationRegistry<T> {
+    fn clone(&self) -> Self {
+        OperationRegistry { operations: self.operations.clone() }
+    }
+}
+
+impl<T: CheckedInt> OperationRegistry<T> {
+    // Add an operation. Its symbol and name must be identifiers that are not keywords,
+    // built-in functions or taken by another operation.
+    pub fn register(&mut self, operation: impl BinaryOperation<T> + 'static) -> Result<(), CalcError<T>> {
+        let (symbol, name) = (operation.symbol(), operation.name());
+        let invalid = |reason: &str| CalcError::InvalidRegistration { name: name.to_string(), reason: reason.to_string() };
+        if !is_variable_name(symbol) || !is_variable_name(name) {
+            return Err(invalid("the symbol and name must be identifiers that are not keywords or built-in functions"));
+        }
+        if [symbol, name].iter().any(|word| self.by_symbol(word).is_some() || self.by_name(word).is_some()) {
+            return Err(invalid("the symbol or name is already registered"));
+        }
+        if operation.precedence().is_some_and(|level| !(1..=7).contains(&level)) {
+            return Err(invalid("the precedence must be between 1 and 7"));
+        }
+        self.operations.push(RegisteredOperation::new(operation));
+        Ok(())
+    }
+
+    pub fn by_symbol(&self, symbol: &str) -> Option<&RegisteredOperation<T>> {
+        self.operations.iter().find(|operation| operation.symbol() == symbol)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&RegisteredOperation<T>> {
+        self.operations.iter().find(|operation| operation.name() == name)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    // Forget the operations registered after the first len
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.operations.truncate(len);
+    }
+
+    // Built-in operations first, then the registered ones in registration order
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredOperation<T>> {
+        self.operations.iter()
+    }
+}
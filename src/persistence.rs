@@ -0,0 +1,716 @@
+//!-------------------------------------------------------------------------------
+//!
+//! Saving and loading Calculator history.
+//!
+//! History can be written as JSON (one entry per line inside an array) or
+//! as CSV with a header row. Every entry stores its operands (three for
+//! ternary operations such as mul_div, and a list of any length for
+//! aggregates such as sum), operation name, overflow policy,
+//! modulus if it was computed in modular mode, assigned variable, and
+//! computed result. An operand taken from an earlier entry is stored as
+//! "$n" and one read from a variable as its name instead of the value.
+//! When a file is loaded each entry is recomputed, and a stored result
+//! that does not match is rejected. Operations registered on the
+//! Calculator are stored under their name and found again in its registry.
+//!
+//!-------------------------------------------------------------------------------
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::calculator::*;
+use crate::expression::is_variable_name;
+use crate::operations::*;
+
+const CSV_HEADER: &str = "first_num,second_num,third_num,operation,policy,result";
+// Only written when some entry was computed in modular mode, so other files stay readable by older versions
+const MODULUS_CSV_HEADER: &str = "first_num,second_num,third_num,operation,policy,modulus,result";
+// Only written when some entry assigns a variable
+const VARIABLE_CSV_HEADER: &str = "first_num,second_num,third_num,operation,policy,modulus,variable,result";
+// Only written when some entry is an aggregate, whose operands are separated by spaces
+const OPERANDS_CSV_HEADER: &str = "first_num,second_num,third_num,operation,policy,modulus,variable,operands,result";
+// Only written when some entry was rounded other than half to even
+const ROUNDING_CSV_HEADER: &str =
+    "first_num,second_num,third_num,operation,policy,modulus,variable,operands,rounding,result";
+// Files written before ternary operations existed
+const POLICY_CSV_HEADER: &str = "first_num,second_num,operation,policy,result";
+// Files written before overflow policies existed; every entry is checked
+const LEGACY_CSV_HEADER: &str = "first_num,second_num,operation,result";
+// Every header the reader understands, newest first
+const CSV_HEADERS: [&str; 7] = [
+    ROUNDING_CSV_HEADER,
+    OPERANDS_CSV_HEADER,
+    VARIABLE_CSV_HEADER,
+    MODULUS_CSV_HEADER,
+    CSV_HEADER,
+    POLICY_CSV_HEADER,
+    LEGACY_CSV_HEADER,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    // Guess the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HistoryFileError<T = i64> {
+    Io(io::Error),
+    // The file is not valid JSON/CSV history; line is 1-based
+    Malformed { line: usize, message: String },
+    // The stored result differs from what the operation actually computes
+    // The operation is boxed to keep the error small
+    ResultMismatch { line: usize, operation: Box<Operation<T>>, stored: T, recomputed: Result<T, CalcError<T>> },
+}
+
+impl<T: CheckedInt> fmt::Display for HistoryFileError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryFileError::Io(error) => write!(f, "i/o error: {}", error),
+            HistoryFileError::Malformed { line, message } => write!(f, "line {}: {}", line, message),
+            HistoryFileError::ResultMismatch { line, operation, stored, recomputed } => {
+                write!(f, "line {}: stored result {} for {} ", line, stored, operation.describe())?;
+                match recomputed {
+                    Ok(value) => write!(f, "does not match the computed result {}", value),
+                    Err(error) => write!(f, "cannot be computed: {}", error),
+                }
+            }
+        }
+    }
+}
+
+impl<T: CheckedInt> std::error::Error for HistoryFileError<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HistoryFileError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl<T> From<io::Error> for HistoryFileError<T> {
+    fn from(error: io::Error) -> Self {
+        HistoryFileError::Io(error)
+    }
+}
+
+fn malformed<T>(line: usize, message: impl Into<String>) -> HistoryFileError<T> {
+    HistoryFileError::Malformed { line, message: message.into() }
+}
+
+// A history entry as read from a file, before it has been verified
+struct Entry<T> {
+    line: usize,
+    // Holds the stored result until it is checked against recomputation
+    operation: Operation<T>,
+}
+
+// JSON text for a value: a plain number for integers, a string such as "7/2" otherwise
+fn json_value<T: CheckedInt>(value: &T) -> String {
+    let text = value.to_string();
+    let digits = text.strip_prefix('-').unwrap_or(&text);
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        text
+    } else {
+        format!("\"{}\"", text)
+    }
+}
+
+// The text of an operand: the value, "$n" for a reference to entry n or a variable name
+fn operand_text<T: CheckedInt>(argument: &Argument<T>) -> String {
+    match &argument.reference {
+        Some(reference) => reference.to_string(),
+        None => argument.value.to_string(),
+    }
+}
+
+// JSON for an operand; references are strings such as "$1" or "fee"
+fn json_operand<T: CheckedInt>(argument: &Argument<T>) -> String {
+    match &argument.reference {
+        Some(reference) => format!("\"{}\"", reference),
+        None => json_value(&argument.value),
+    }
+}
+
+// The first and second operands as stored in a file. Unary entries store a zero second
+// operand and aggregates zeros for both, keeping their operands in a list of their own.
+fn first_and_second<T: CheckedInt>(kind: &OperationKind<T>) -> [Argument<T>; 2] {
+    let zero = || Argument::from(T::zero());
+    match kind {
+        OperationKind::Aggregate { .. } => [zero(), zero()],
+        kind => {
+            let arguments = kind.arguments();
+            [arguments[0].clone(), arguments.get(1).cloned().unwrap_or_else(zero)]
+        }
+    }
+}
+
+// Render history in the given format using the stored results
+pub fn format_history<T: CheckedInt>(history: &[Operation<T>], format: Format) -> String {
+    let mut output = String::new();
+    match format {
+        Format::Json => {
+            output.push('[');
+            for (index, operation) in history.iter().enumerate() {
+                output.push_str(if index == 0 { "\n" } else { ",\n" });
+                // Only ternary entries have a third operand, and only aggregates a list
+                let third_num = match &operation.kind {
+                    OperationKind::Ternary { operands: [_, _, z], .. } => format!(", \"third_num\": {}", json_operand(z)),
+                    OperationKind::Aggregate { operands, .. } => {
+                        let values: Vec<String> = operands.iter().map(json_operand).collect();
+                        format!(", \"operands\": [{}]", values.join(", "))
+                    }
+                    _ => String::new(),
+                };
+                let [first_num, second_num] = first_and_second(&operation.kind);
+                let modulus = match &operation.modulus {
+                    Some(modulus) => format!(", \"modulus\": {}", json_value(modulus)),
+                    None => String::new(),
+                };
+                let variable = match &operation.variable {
+                    Some(name) => format!(", \"variable\": \"{}\"", name),
+                    None => String::new(),
+                };
+                let rounding = match operation.rounding {
+                    Rounding::HalfEven => String::new(),
+                    rounding => format!(", \"rounding\": \"{}\"", rounding.name()),
+                };
+                output.push_str(&format!(
+                    "  {{\"first_num\": {}, \"second_num\": {}{}, \"operation\": \"{}\", \"policy\": \"{}\"{}{}{}, \"result\": {}}}",
+                    json_operand(&first_num),
+                    json_operand(&second_num),
+                    third_num,
+                    operation.kind.name(),
+                    operation.policy.name(),
+                    modulus,
+                    variable,
+                    rounding,
+                    json_value(&operation.result)
+                ));
+            }
+            output.push_str(if history.is_empty() { "]\n" } else { "\n]\n" });
+        }
+        Format::Csv => {
+            // Each optional column brings the ones before it
+            let rounded = history.iter().any(|operation| operation.rounding != Rounding::HalfEven);
+            let aggregates =
+                rounded || history.iter().any(|operation| matches!(operation.kind, OperationKind::Aggregate { .. }));
+            let assigns = aggregates || history.iter().any(|operation| operation.variable.is_some());
+            let modular = assigns || history.iter().any(|operation| operation.modulus.is_some());
+            output.push_str(match (rounded, aggregates, assigns, modular) {
+                (true, _, _, _) => ROUNDING_CSV_HEADER,
+                (false, true, _, _) => OPERANDS_CSV_HEADER,
+                (false, false, true, _) => VARIABLE_CSV_HEADER,
+                (false, false, false, true) => MODULUS_CSV_HEADER,
+                (false, false, false, false) => CSV_HEADER,
+            });
+            output.push('\n');
+            for operation in history {
+                let third_num = match &operation.kind {
+                    OperationKind::Ternary { operands: [_, _, z], .. } => operand_text(z),
+                    _ => String::new(),
+                };
+                let modulus = match &operation.modulus {
+                    Some(modulus) => format!("{},", modulus),
+                    None if modular => ",".to_string(),
+                    None => String::new(),
+                };
+                let variable = match &operation.variable {
+                    Some(name) => format!("{},", name),
+                    None if assigns => ",".to_string(),
+                    None => String::new(),
+                };
+                let operands = match &operation.kind {
+                    OperationKind::Aggregate { operands, .. } => {
+                        let values: Vec<String> = operands.iter().map(operand_text).collect();
+                        format!("{},", values.join(" "))
+                    }
+                    _ if aggregates => ",".to_string(),
+                    _ => String::new(),
+                };
+                let rounding = match operation.rounding {
+                    Rounding::HalfEven if rounded => ",".to_string(),
+                    Rounding::HalfEven => String::new(),
+                    rounding => format!("{},", rounding.name()),
+                };
+                let [first_num, second_num] = first_and_second(&operation.kind);
+                output.push_str(&format!(
+                    "{},{},{},{},{},{}{}{}{}{}\n",
+                    operand_text(&first_num),
+                    operand_text(&second_num),
+                    third_num,
+                    operation.kind.name(),
+                    operation.policy.name(),
+                    modulus,
+                    variable,
+                    operands,
+                    rounding,
+                    operation.result
+                ));
+            }
+        }
+    }
+    output
+}
+
+// Parse history in the given format, verifying every stored result
+pub fn parse_history<T: CheckedInt>(text: &str, format: Format) -> Result<Vec<Operation<T>>, HistoryFileError<T>> {
+    parse_history_with(text, format, &OperationRegistry::default())
+}
+
+// Same as parse_history, also accepting the names of the operations registered in operations
+pub fn parse_history_with<T: CheckedInt>(
+    text: &str,
+    format: Format,
+    operations: &OperationRegistry<T>,
+) -> Result<Vec<Operation<T>>, HistoryFileError<T>> {
+    let entries = match format {
+        Format::Json => parse_json_entries(text, operations)?,
+        Format::Csv => parse_csv_entries(text, operations)?,
+    };
+
+    let mut history = Vec::with_capacity(entries.len());
+    for Entry { line, mut operation } in entries {
+        // References can only point to entries earlier in the file
+        operation.resolve(&history).map_err(|error| malformed(line, error.to_string()))?;
+        let recomputed = operation.evaluate().map(|(value, overflowed)| {
+            operation.overflowed = overflowed;
+            value
+        });
+        if recomputed.as_ref() != Ok(&operation.result) {
+            let stored = operation.result.clone();
+            let operation = Box::new(operation);
+            return Err(HistoryFileError::ResultMismatch { line, operation, stored, recomputed });
+        }
+        history.push(operation);
+    }
+    Ok(history)
+}
+
+// What the operation name of an entry stands for
+enum Named<T> {
+    BuiltIn(OperationType),
+    Ternary(TernaryOperationType),
+    Aggregate(AggregateOperationType),
+    Registered(RegisteredOperation<T>),
+}
+
+impl<T> Named<T> {
+    fn name(&self) -> String {
+        match self {
+            Named::BuiltIn(operation_type) => operation_type.name().to_string(),
+            Named::Ternary(operation_type) => operation_type.name(),
+            Named::Aggregate(operation_type) => operation_type.name(),
+            Named::Registered(operation) => operation.name().to_string(),
+        }
+    }
+}
+
+fn named_operation<T: CheckedInt>(
+    line: usize,
+    name: &str,
+    operations: &OperationRegistry<T>,
+) -> Result<Named<T>, HistoryFileError<T>> {
+    if let Some(operation_type) = OperationType::from_name(name) {
+        return Ok(Named::BuiltIn(operation_type));
+    }
+    if let Some(operation_type) = TernaryOperationType::from_name(name) {
+        return Ok(Named::Ternary(operation_type));
+    }
+    if let Some(operation_type) = AggregateOperationType::from_name(name) {
+        return Ok(Named::Aggregate(operation_type));
+    }
+    match operations.by_name(name) {
+        Some(operation) => Ok(Named::Registered(operation.clone())),
+        None => Err(malformed(line, format!("unknown operation '{}'", name))),
+    }
+}
+
+// The operands of an entry: first_num, second_num, third_num and the list of operands
+type Operands<T> = (Operand<T>, Operand<T>, Option<Operand<T>>, Option<Vec<Operand<T>>>);
+
+// Build an entry from its fields; ternary operations need a third operand and binary ones must not have one.
+// Only aggregates have a list of operands, which is empty when it is missing.
+fn entry<T: CheckedInt>(
+    line: usize,
+    named: Named<T>,
+    operands: Operands<T>,
+    (policy, rounding): (OverflowPolicy, Rounding),
+    modulus: Option<T>,
+    variable: Option<String>,
+    result: T,
+) -> Result<Entry<T>, HistoryFileError<T>> {
+    let (first_num, second_num, third_num, list) = operands;
+    let name = named.name();
+    let mut operation = match (named, third_num, list) {
+        (Named::BuiltIn(operation_type), None, None) => Operation::from_operands(first_num, second_num, operation_type),
+        (Named::Registered(operation), None, None) => Operation::from_registered(first_num, second_num, &operation),
+        (Named::Ternary(operation_type), Some(third_num), None) => {
+            let operands = [first_num, second_num, third_num].map(Argument::from_operand);
+            Operation::from_kind(OperationKind::Ternary { operation_type, operands }, T::zero())
+        }
+        (Named::Aggregate(operation_type), None, list) => {
+            let operands = list.unwrap_or_default().into_iter().map(Argument::from_operand).collect();
+            Operation::from_kind(OperationKind::Aggregate { operation_type, operands }, T::zero())
+        }
+        (Named::Ternary(_), None, _) => return Err(malformed(line, format!("operation '{}' needs a third_num", name))),
+        (_, Some(_), _) => return Err(malformed(line, format!("operation '{}' takes no third_num", name))),
+        (_, None, Some(_)) => return Err(malformed(line, format!("operation '{}' takes no operands", name))),
+    };
+    operation.policy = policy;
+    operation.rounding = rounding;
+    operation.modulus = modulus;
+    operation.variable = variable;
+    operation.result = result;
+    Ok(Entry { line, operation })
+}
+
+fn parse_policy_name<T>(line: usize, name: &str) -> Result<OverflowPolicy, HistoryFileError<T>> {
+    OverflowPolicy::from_name(name).ok_or_else(|| malformed(line, format!("unknown overflow policy '{}'", name)))
+}
+
+fn parse_rounding_name<T>(line: usize, name: &str) -> Result<Rounding, HistoryFileError<T>> {
+    Rounding::from_name(name).ok_or_else(|| malformed(line, format!("unknown rounding '{}'", name)))
+}
+
+fn parse_number<T: CheckedInt>(line: usize, field: &str, text: &str) -> Result<T, HistoryFileError<T>> {
+    text.parse::<T>()
+        .map_err(|_| malformed(line, format!("{} '{}' is not a valid {}", field, text, T::NAME)))
+}
+
+// Values never start with a letter, "_" or "$", so such operands are references
+fn is_reference(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$')
+}
+
+// An operand: a value, "$n" for the result of entry n, or a variable name
+fn parse_operand<T: CheckedInt>(line: usize, field: &str, text: &str) -> Result<Operand<T>, HistoryFileError<T>> {
+    match text.strip_prefix('$') {
+        Some(index) => index
+            .parse()
+            .map(Operand::HistoryRef)
+            .map_err(|_| malformed(line, format!("{} '{}' is not a valid history reference", field, text))),
+        None if is_reference(text) => parse_variable(line, text).map(Operand::Variable),
+        None => parse_number(line, field, text).map(Operand::Value),
+    }
+}
+
+fn parse_variable<T>(line: usize, name: &str) -> Result<String, HistoryFileError<T>> {
+    if !is_variable_name(name) {
+        return Err(malformed(line, format!("'{}' is not a valid variable name", name)));
+    }
+    Ok(name.to_string())
+}
+
+fn parse_csv_entries<T: CheckedInt>(text: &str, operations: &OperationRegistry<T>) -> Result<Vec<Entry<T>>, HistoryFileError<T>> {
+    let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+
+    // Older headers lack some columns, so fields are looked up by column name
+    let columns: Vec<&str> = match lines.next() {
+        Some((_, header)) if CSV_HEADERS.contains(&header) => header.split(',').collect(),
+        found => {
+            let expected: Vec<String> = CSV_HEADERS.iter().map(|header| format!("'{}'", header)).collect();
+            let found = match found {
+                Some((_, header)) => format!("found the header '{}'", header),
+                None => "the file is empty".to_string(),
+            };
+            return Err(malformed(1, format!("{}, expected one of {}", found, expected.join(", "))));
+        }
+    };
+
+    let mut entries = Vec::new();
+    for (line, record) in lines {
+        if record.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = record.split(',').map(str::trim).collect();
+        if fields.len() != columns.len() {
+            return Err(malformed(line, format!("expected {} fields, found {}", columns.len(), fields.len())));
+        }
+        let field = |name: &str| columns.iter().position(|column| *column == name).map(|index| fields[index]);
+
+        let number = |name: &str| parse_number(line, name, field(name).unwrap_or_default());
+        let third_num = match field("third_num") {
+            None | Some("") => None,
+            Some(text) => Some(parse_operand(line, "third_num", text)?),
+        };
+        let policy = match field("policy") {
+            Some(name) => parse_policy_name(line, name)?,
+            None => OverflowPolicy::Checked,
+        };
+        let modulus = match field("modulus") {
+            None | Some("") => None,
+            Some(text) => Some(parse_number(line, "modulus", text)?),
+        };
+        let variable = match field("variable") {
+            None | Some("") => None,
+            Some(name) => Some(parse_variable(line, name)?),
+        };
+        let rounding = match field("rounding") {
+            None | Some("") => Rounding::HalfEven,
+            Some(name) => parse_rounding_name(line, name)?,
+        };
+        let list = match field("operands") {
+            None | Some("") => None,
+            Some(text) => Some(text.split_whitespace().map(|value| parse_operand(line, "operands", value)).collect::<Result<_, _>>()?),
+        };
+        let operand = |name: &str| parse_operand(line, name, field(name).unwrap_or_default());
+        let operands = (operand("first_num")?, operand("second_num")?, third_num, list);
+        let named = named_operation(line, field("operation").unwrap_or_default(), operations)?;
+        entries.push(entry(line, named, operands, (policy, rounding), modulus, variable, number("result")?)?);
+    }
+    Ok(entries)
+}
+
+fn parse_json_entries<T: CheckedInt>(text: &str, operations: &OperationRegistry<T>) -> Result<Vec<Entry<T>>, HistoryFileError<T>> {
+    let mut reader = JsonReader { chars: text.chars().collect(), position: 0, line: 1 };
+    let document = reader.value()?;
+    reader.skip_whitespace();
+    if reader.position < reader.chars.len() {
+        return Err(malformed(reader.line, "unexpected data after the history array"));
+    }
+
+    let Json::Array(items) = document.value else {
+        return Err(malformed(document.line, "expected an array of history entries"));
+    };
+
+    let mut entries = Vec::with_capacity(items.len());
+    for item in items {
+        let line = item.line;
+        let Json::Object(fields) = item.value else {
+            return Err(malformed(line, "expected a history entry object"));
+        };
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| &value.value)
+                .ok_or_else(|| malformed(line, format!("missing field '{}'", name)))
+        };
+        let number = |name: &str| match field(name)? {
+            // Values that are not plain integers, such as rationals, are written as strings
+            Json::Number(text) | Json::String(text) => parse_number(line, name, text),
+            _ => Err(malformed(line, format!("field '{}' must be a number", name))),
+        };
+        let operand = |name: &str| match field(name)? {
+            Json::String(text) if is_reference(text) => parse_operand(line, name, text),
+            _ => number(name).map(Operand::Value),
+        };
+        let named = match field("operation")? {
+            Json::String(name) => named_operation(line, name, operations)?,
+            _ => return Err(malformed(line, "field 'operation' must be a string")),
+        };
+        // Files written before overflow policies existed have no "policy" field
+        let policy = match field("policy") {
+            Err(_) => OverflowPolicy::Checked,
+            Ok(Json::String(name)) => parse_policy_name(line, name)?,
+            Ok(_) => return Err(malformed(line, "field 'policy' must be a string")),
+        };
+        let third_num = match field("third_num") {
+            Err(_) => None,
+            Ok(_) => Some(operand("third_num")?),
+        };
+        let modulus = match field("modulus") {
+            Err(_) => None,
+            Ok(_) => Some(number("modulus")?),
+        };
+        let variable = match field("variable") {
+            Err(_) => None,
+            Ok(Json::String(name)) => Some(parse_variable(line, name)?),
+            Ok(_) => return Err(malformed(line, "field 'variable' must be a string")),
+        };
+        let rounding = match field("rounding") {
+            Err(_) => Rounding::HalfEven,
+            Ok(Json::String(name)) => parse_rounding_name(line, name)?,
+            Ok(_) => return Err(malformed(line, "field 'rounding' must be a string")),
+        };
+        let list = match field("operands") {
+            Err(_) => None,
+            Ok(Json::Array(values)) => Some(
+                values
+                    .iter()
+                    .map(|value| match &value.value {
+                        Json::String(text) if is_reference(text) => parse_operand(line, "operands", text),
+                        Json::Number(text) | Json::String(text) => parse_number(line, "operands", text).map(Operand::Value),
+                        _ => Err(malformed(line, "field 'operands' must hold numbers or references")),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            Ok(_) => return Err(malformed(line, "field 'operands' must be an array")),
+        };
+        let operands = (operand("first_num")?, operand("second_num")?, third_num, list);
+        entries.push(entry(line, named, operands, (policy, rounding), modulus, variable, number("result")?)?);
+    }
+    Ok(entries)
+}
+
+// A JSON syntax error, reported as HistoryFileError::Malformed
+struct SyntaxError {
+    line: usize,
+    message: String,
+}
+
+impl<T> From<SyntaxError> for HistoryFileError<T> {
+    fn from(error: SyntaxError) -> Self {
+        HistoryFileError::Malformed { line: error.line, message: error.message }
+    }
+}
+
+fn syntax_error(line: usize, message: impl Into<String>) -> SyntaxError {
+    SyntaxError { line, message: message.into() }
+}
+
+// The subset of JSON that history files use
+enum Json {
+    Number(String),
+    String(String),
+    Array(Vec<Located>),
+    Object(Vec<(String, Located)>),
+}
+
+// A JSON value together with the line it starts on
+struct Located {
+    line: usize,
+    value: Json,
+}
+
+struct JsonReader {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl JsonReader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SyntaxError> {
+        self.skip_whitespace();
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(syntax_error(self.line, format!("expected '{}', found '{}'", expected, c))),
+            None => Err(syntax_error(self.line, format!("expected '{}', found end of file", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Located, SyntaxError> {
+        self.skip_whitespace();
+        let line = self.line;
+        let value = match self.peek() {
+            Some('[') => Json::Array(self.sequence('[', ']', |reader| reader.value())?),
+            Some('{') => Json::Object(self.sequence('{', '}', |reader| {
+                let key = reader.string()?;
+                reader.expect(':')?;
+                Ok((key, reader.value()?))
+            })?),
+            Some('"') => Json::String(self.string()?),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let mut text = String::new();
+                while let Some(c) = self.peek().filter(|c| *c == '-' || c.is_ascii_alphanumeric() || *c == '.') {
+                    text.push(c);
+                    self.bump();
+                }
+                Json::Number(text)
+            }
+            Some(c) => return Err(syntax_error(line, format!("unexpected character '{}'", c))),
+            None => return Err(syntax_error(line, "unexpected end of file")),
+        };
+        Ok(Located { line, value })
+    }
+
+    // Parse a comma separated list between open and close
+    fn sequence<T>(
+        &mut self,
+        open: char,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<T, SyntaxError>,
+    ) -> Result<Vec<T>, SyntaxError> {
+        self.expect(open)?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(close) {
+            self.bump();
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some(c) if c == close => return Ok(items),
+                _ => return Err(syntax_error(self.line, format!("expected ',' or '{}'", close))),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SyntaxError> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.bump() {
+                    Some(c @ ('"' | '\\' | '/')) => text.push(c),
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    _ => return Err(syntax_error(self.line, "unsupported escape sequence")),
+                },
+                Some('\n') | None => return Err(syntax_error(self.line, "unterminated string")),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+}
+
+impl<T: CheckedInt> Calculator<T> {
+    // Write the whole history to a file in the given format
+    pub fn save_history(&self, path: impl AsRef<Path>, format: Format) -> Result<(), HistoryFileError<T>> {
+        fs::write(path, format_history(&self.history, format))?;
+        Ok(())
+    }
+
+    // Replace the history with the entries stored in a file.
+    // The format is taken from the extension, falling back to the file contents.
+    // On any error the current history is left untouched; a successful load can be undone.
+    pub fn load_history(&mut self, path: impl AsRef<Path>) -> Result<(), HistoryFileError<T>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let format = Format::from_path(path).unwrap_or(if text.trim_start().starts_with('[') {
+            Format::Json
+        } else {
+            Format::Csv
+        });
+        let history = parse_history_with(&text, format, self.operations())?;
+        self.replace_history(history);
+        Ok(())
+    }
+}
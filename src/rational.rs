@@ -0,0 +1,289 @@
+//!-------------------------------------------------------------------------------
+//!
+//! Exact rational numbers for the Calculator.
+//!
+//! A Rational is an i128 numerator over a positive i128 denominator in
+//! lowest terms, so 7 / 2 is kept as 7/2 instead of being truncated to 3.
+//! All arithmetic is checked: a result whose reduced form does not fit
+//! comes back as None (CalcError::Overflow in a Calculator), never a panic.
+//!
+//!-------------------------------------------------------------------------------
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bigint::BigInt;
+use crate::calculator::*;
+use crate::number_theory::gcd_u128;
+
+// Invariants: denominator > 0 and gcd(numerator, denominator) == 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRationalError;
+
+impl fmt::Display for ParseRationalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid rational literal")
+    }
+}
+
+impl std::error::Error for ParseRationalError {}
+
+// Greatest common divisor of the magnitudes; gcd(0, 0) is 0
+fn gcd(a: i128, b: i128) -> u128 {
+    gcd_u128(a.unsigned_abs(), b.unsigned_abs())
+}
+
+// Divide by a common divisor. The divisor is 2^127 only when the value is 0 or i128::MIN.
+fn exact_div(value: i128, divisor: u128) -> i128 {
+    match i128::try_from(divisor) {
+        Ok(divisor) => value / divisor,
+        Err(_) => value.signum(),
+    }
+}
+
+impl Rational {
+    // Build numerator / denominator in lowest terms.
+    // Returns None for a zero denominator or when the sign cannot be normalized.
+    pub fn new(numerator: i128, denominator: i128) -> Option<Rational> {
+        if denominator == 0 {
+            return None;
+        }
+        let divisor = gcd(numerator, denominator);
+        let (mut numerator, mut denominator) = (exact_div(numerator, divisor), exact_div(denominator, divisor));
+        if denominator < 0 {
+            numerator = numerator.checked_neg()?;
+            denominator = denominator.checked_neg()?;
+        }
+        Some(Rational { numerator, denominator })
+    }
+
+    pub fn from_integer(value: i128) -> Rational {
+        Rational { numerator: value, denominator: 1 }
+    }
+
+    pub fn zero() -> Rational {
+        Rational::from_integer(0)
+    }
+
+    pub fn numerator(&self) -> i128 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i128 {
+        self.denominator
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator == 1
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.numerator < 0
+    }
+
+    pub fn checked_neg(&self) -> Option<Rational> {
+        Some(Rational { numerator: self.numerator.checked_neg()?, denominator: self.denominator })
+    }
+
+    pub fn checked_abs(&self) -> Option<Rational> {
+        if self.is_negative() {
+            self.checked_neg()
+        } else {
+            Some(*self)
+        }
+    }
+
+    // The integer part, rounded toward zero
+    pub fn trunc(&self) -> Rational {
+        Rational::from_integer(self.numerator / self.denominator)
+    }
+
+    // a/b + c/d, dividing out gcd(b, d) first so intermediates stay small
+    pub fn checked_add(&self, other: &Rational) -> Option<Rational> {
+        let divisor = gcd(self.denominator, other.denominator) as i128;
+        let numerator = self
+            .numerator
+            .checked_mul(other.denominator / divisor)?
+            .checked_add(other.numerator.checked_mul(self.denominator / divisor)?)?;
+        let denominator = (self.denominator / divisor).checked_mul(other.denominator)?;
+        Rational::new(numerator, denominator)
+    }
+
+    pub fn checked_sub(&self, other: &Rational) -> Option<Rational> {
+        self.checked_add(&other.checked_neg()?)
+    }
+
+    // a/b * c/d, cross-reducing a with d and c with b before multiplying.
+    // Denominators are positive, so neither divisor can be zero.
+    pub fn checked_mul(&self, other: &Rational) -> Option<Rational> {
+        let left = gcd(self.numerator, other.denominator) as i128;
+        let right = gcd(other.numerator, self.denominator) as i128;
+        let numerator = (self.numerator / left).checked_mul(other.numerator / right)?;
+        let denominator = (self.denominator / right).checked_mul(other.denominator / left)?;
+        Rational::new(numerator, denominator)
+    }
+
+    // Returns None when dividing by zero
+    pub fn checked_div(&self, other: &Rational) -> Option<Rational> {
+        let reciprocal = Rational::new(other.denominator, other.numerator)?;
+        self.checked_mul(&reciprocal)
+    }
+
+    // x - y * trunc(x / y), so the result has the sign of x like i64::rem
+    pub fn checked_rem(&self, other: &Rational) -> Option<Rational> {
+        let quotient = self.checked_div(other)?.trunc();
+        self.checked_sub(&other.checked_mul(&quotient)?)
+    }
+
+    // Both parts raised to exponent; a reduced fraction stays reduced
+    pub fn checked_pow(&self, exponent: u32) -> Option<Rational> {
+        Some(Rational {
+            numerator: self.numerator.checked_pow(exponent)?,
+            denominator: self.denominator.checked_pow(exponent)?,
+        })
+    }
+
+    // Remainder that is never negative, matching i64::rem_euclid
+    pub fn checked_rem_euclid(&self, other: &Rational) -> Option<Rational> {
+        let remainder = self.checked_rem(other)?;
+        if remainder.is_negative() {
+            remainder.checked_add(&other.checked_abs()?)
+        } else {
+            Some(remainder)
+        }
+    }
+}
+
+impl Ord for Rational {
+    // a/b < c/d exactly when a*d < c*b; the products can need 254 bits
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.denominator == other.denominator {
+            return self.numerator.cmp(&other.numerator);
+        }
+        let left = BigInt::from(self.numerator) * BigInt::from(other.denominator);
+        let right = BigInt::from(other.numerator) * BigInt::from(self.denominator);
+        left.cmp(&right)
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+macro_rules! from_integer {
+    ($($t:ty),*) => {$(
+        impl From<$t> for Rational {
+            fn from(value: $t) -> Self {
+                Rational::from_integer(value as i128)
+            }
+        }
+    )*};
+}
+
+from_integer!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64);
+
+impl FromStr for Rational {
+    type Err = ParseRationalError;
+
+    // Parse "n" or "n/d" with decimal integers n and d
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (numerator, denominator) = match text.split_once('/') {
+            Some((numerator, denominator)) => (numerator, denominator),
+            None => (text, "1"),
+        };
+        let numerator = numerator.parse::<i128>().map_err(|_| ParseRationalError)?;
+        let denominator = denominator.parse::<i128>().map_err(|_| ParseRationalError)?;
+        Rational::new(numerator, denominator).ok_or(ParseRationalError)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+// Rationals never wrap around, so every overflow policy reports overflow as an error
+impl CheckedInt for Rational {
+    const NAME: &'static str = "Rational";
+
+    fn zero() -> Self {
+        Rational::zero()
+    }
+
+    fn apply(operation_type: OperationType, x: &Self, y: &Self, _policy: OverflowPolicy) -> Option<(Self, bool)> {
+        let value = match operation_type {
+            OperationType::Addition => x.checked_add(y),
+            OperationType::Subtraction => x.checked_sub(y),
+            OperationType::Multiplication => x.checked_mul(y),
+            OperationType::Division => x.checked_div(y),
+            OperationType::Remainder => x.checked_rem(y),
+            OperationType::EuclidMod => x.checked_rem_euclid(y),
+            OperationType::Assign => Some(*x),
+            // Built from other operations by OperationType::compute
+            OperationType::Neg
+            | OperationType::Abs
+            | OperationType::Signum
+            | OperationType::Square
+            | OperationType::Factorial => None,
+            OperationType::Power => x.checked_pow(y.to_u32()?),
+            // Bit operations need a fixed width, and roots and logarithms integers;
+            // supports rejects both
+            OperationType::Isqrt
+            | OperationType::Icbrt
+            | OperationType::Ilog2
+            | OperationType::Ilog10
+            | OperationType::Ilog
+            | OperationType::Gcd
+            | OperationType::Lcm
+            | OperationType::ModInverse
+            | OperationType::And
+            | OperationType::Or
+            | OperationType::Xor
+            | OperationType::Not
+            | OperationType::Shl
+            | OperationType::Shr
+            | OperationType::LogicalShr
+            | OperationType::RotateLeft
+            | OperationType::RotateRight => None,
+        };
+        value.map(|value| (value, false))
+    }
+
+    // The quotient is exact, so there is nothing to round
+    fn mul_div(x: &Self, y: &Self, z: &Self, _rounding: Rounding) -> Option<Self> {
+        x.checked_div(z)?.checked_mul(y)
+    }
+
+    // The sum is kept as a BigInt fraction in lowest terms, so only a mean whose
+    // reduced form does not fit fails
+    fn mean(values: &[Self], _rounding: Rounding) -> Option<Self> {
+        let reduced = |numerator: BigInt, denominator: BigInt| {
+            let divisor = numerator.gcd(&denominator);
+            (&numerator / &divisor, &denominator / &divisor)
+        };
+        let (numerator, denominator) = values.iter().fold((BigInt::zero(), BigInt::from(1)), |(numerator, denominator), value| {
+            let (value_numerator, value_denominator) = (BigInt::from(value.numerator), BigInt::from(value.denominator));
+            reduced(&numerator * &value_denominator + &value_numerator * &denominator, &denominator * &value_denominator)
+        });
+        let (numerator, denominator) = reduced(numerator, denominator * BigInt::from(values.len()));
+        Rational::new(i128::try_from(&numerator).ok()?, i128::try_from(&denominator).ok()?)
+    }
+
+    fn supports(operation_type: OperationType) -> bool {
+        !operation_type.is_bitwise() && !operation_type.is_integer_only()
+    }
+}
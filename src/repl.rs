@@ -0,0 +1,163 @@
+//!-------------------------------------------------------------------------------
+//!
+//! Line-oriented REPL for the Calculator.
+//!
+//! Each input line is either a built-in command or an infix expression
+//! that is evaluated with Calculator::evaluate. Errors are reported
+//! inline and the session keeps going until "quit" or end of input.
+//!
+//!-------------------------------------------------------------------------------
+
+use std::io::{self, BufRead, Write};
+
+use crate::calculator::*;
+
+const PROMPT: &str = "> ";
+
+const HELP: &str = "\
+Enter an expression such as (-4 + 9) * 3 - 2, abs(-4) + 5!, sum(1, 2, 3),
+mul_div(fee, 3, 7) or $1 * ans, where $n is the result of history entry n and ans the last
+result, an assignment such as let fee = 25, or one of the commands:
+  vars        list the variables and their values
+  M+ E, M- E  add the value of E to or subtract it from the memory register M
+  MR, MC      show the memory register, or set it back to 0
+  history     show the operation history
+  history R   show the history in dec, hex, oct or bin
+  radix R     show the history in radix R from now on
+  twos N      show negative numbers as N-bit two's complement (8, 16, 32, 64 or off)
+  modulus N   compute + - * / ** modulo N from now on (off to stop)
+  repeat N    repeat history entry N
+  clear       clear the history, variables included
+  policy P    use the checked, wrapping, saturating or overflowing policy
+  rounding R  round decimal * / ** results floor, ceil or half_even
+  undo        take back the last change to the history
+  redo        re-apply the last undone change
+  help        show this message
+  quit        leave the calculator
+";
+
+// Outcome of handling a single input line
+enum Step {
+    Continue,
+    Quit,
+}
+
+// Run an i64 REPL until "quit" or the input is exhausted
+pub fn run<R: BufRead, W: Write>(input: R, output: W) -> io::Result<()> {
+    run_with(Calculator::new(), input, output)
+}
+
+// Run the REPL on a Calculator of any integer backend
+pub fn run_with<T: CheckedInt, R: BufRead, W: Write>(
+    mut calculator: Calculator<T>,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    write!(output, "{}", PROMPT)?;
+    output.flush()?;
+    for line in input.lines() {
+        if let Step::Quit = handle_line(&mut calculator, line?.trim(), &mut output)? {
+            return Ok(());
+        }
+        write!(output, "{}", PROMPT)?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
+
+// Execute one command or expression and write its outcome
+fn handle_line<T: CheckedInt, W: Write>(
+    calculator: &mut Calculator<T>,
+    line: &str,
+    output: &mut W,
+) -> io::Result<Step> {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (None, _, _) => {}
+        (Some("quit" | "exit"), None, _) => return Ok(Step::Quit),
+        (Some("help"), None, _) => write!(output, "{}", HELP)?,
+        (Some("history"), None, _) => write!(output, "{}", calculator.show_history())?,
+        (Some("history"), Some(name), None) => match Radix::from_name(name) {
+            Some(radix) => write!(output, "{}", calculator.show_history_in(radix))?,
+            None => writeln!(output, "error: unknown radix '{}'", name)?,
+        },
+        (Some("clear"), None, _) => calculator.clear_history(),
+        (Some("policy"), None, _) => writeln!(output, "{}", calculator.overflow_policy().name())?,
+        (Some("policy"), Some(name), None) => match OverflowPolicy::from_name(name) {
+            Some(policy) => calculator.set_overflow_policy(policy),
+            None => writeln!(output, "error: unknown overflow policy '{}'", name)?,
+        },
+        (Some("rounding"), None, _) => writeln!(output, "{}", calculator.rounding().name())?,
+        (Some("rounding"), Some(name), None) => match Rounding::from_name(name) {
+            Some(rounding) => calculator.set_rounding(rounding),
+            None => writeln!(output, "error: unknown rounding '{}'", name)?,
+        },
+        (Some("radix"), None, _) => writeln!(output, "{}", calculator.number_format().radix.name())?,
+        (Some("radix"), Some(name), None) => match Radix::from_name(name) {
+            Some(radix) => calculator.set_radix(radix),
+            None => writeln!(output, "error: unknown radix '{}'", name)?,
+        },
+        (Some("twos"), Some("off"), None) => calculator.set_twos_complement(None),
+        (Some("twos"), Some(bits), None) => match bits.parse().ok().and_then(BitWidth::from_bits) {
+            Some(width) => calculator.set_twos_complement(Some(width)),
+            None => writeln!(output, "error: bit width must be 8, 16, 32 or 64")?,
+        },
+        (Some("modulus"), None, _) => match calculator.modulus() {
+            Some(modulus) => writeln!(output, "{}", modulus)?,
+            None => writeln!(output, "off")?,
+        },
+        (Some("modulus"), Some("off"), None) => report_edit(calculator.set_modulus(None), output)?,
+        (Some("modulus"), Some(text), None) => match parse_literal::<T>(text) {
+            Some(modulus) => report_edit(calculator.set_modulus(Some(modulus)), output)?,
+            None => writeln!(output, "error: '{}' is not a valid {} modulus", text, T::NAME)?,
+        },
+        (Some("vars"), None, _) => {
+            for (name, value) in calculator.variables() {
+                writeln!(output, "{} = {}", name, value)?;
+            }
+        }
+        (Some("M+" | "M-"), Some(_), _) => {
+            // The rest of the line is an expression whose steps are recorded before the update,
+            // all in one transaction so that a failure leaves nothing behind and undo reverts both
+            let result = calculator.transaction(|tx| {
+                let value = tx.evaluate(line[2..].trim())?;
+                if line.starts_with("M+") {
+                    tx.memory_add(Operand::Value(value))
+                } else {
+                    tx.memory_subtract(Operand::Value(value))
+                }
+            });
+            // The command is a single step, so report the error of the step that failed
+            let result = result.map_err(|error| match error {
+                CalcError::TransactionFailed { error, .. } => *error,
+                error => error,
+            });
+            report(result, output)?
+        }
+        (Some("MR"), None, _) => writeln!(output, "{}", calculator.memory_recall())?,
+        (Some("MC"), None, _) => report_edit(calculator.memory_clear().map(|_| ()), output)?,
+        (Some("undo"), None, _) => report_edit(calculator.undo(), output)?,
+        (Some("redo"), None, _) => report_edit(calculator.redo(), output)?,
+        (Some("repeat"), Some(index), None) => match index.parse::<usize>() {
+            Ok(index) => report(calculator.repeat(index), output)?,
+            Err(_) => writeln!(output, "error: '{}' is not a history index", index)?,
+        },
+        (Some("repeat"), _, _) => writeln!(output, "error: usage: repeat N")?,
+        _ => report(calculator.evaluate(line), output)?,
+    }
+    Ok(Step::Continue)
+}
+
+fn report<T: CheckedInt, W: Write>(result: Result<T, CalcError<T>>, output: &mut W) -> io::Result<()> {
+    match result {
+        Ok(value) => writeln!(output, "{}", value),
+        Err(error) => writeln!(output, "error: {}", error),
+    }
+}
+
+fn report_edit<T: CheckedInt, W: Write>(result: Result<(), CalcError<T>>, output: &mut W) -> io::Result<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(error) => writeln!(output, "error: {}", error),
+    }
+}
@@ -0,0 +1,216 @@
+//!-------------------------------------------------------------------------------
+//!
+//! Reverse Polish notation on top of the Calculator.
+//!
+//! An RpnCalculator keeps a stack of values. Operations take their
+//! operands from the top of the stack, with the top value as the second
+//! operand, and push the result; each one is performed by the wrapped
+//! Calculator, so it follows its policy and modulus and is recorded in
+//! its history. A line such as "4 9 + 3 *" is evaluated word by word,
+//! and the symbols of registered operations such as "avg" are words too.
+//!
+//!-------------------------------------------------------------------------------
+
+use crate::calculator::*;
+use crate::operations::*;
+
+pub struct RpnCalculator<T = i64> {
+    calculator: Calculator<T>,
+    // The top of the stack is the last element
+    stack: Vec<T>,
+}
+
+impl RpnCalculator {
+    // Create an i64 RpnCalculator with an empty stack and history
+    pub fn new() -> Self {
+        RpnCalculator::default()
+    }
+}
+
+impl<T: CheckedInt> Default for RpnCalculator<T> {
+    fn default() -> Self {
+        RpnCalculator::with_calculator(Calculator::default())
+    }
+}
+
+impl<T: CheckedInt> RpnCalculator<T> {
+    // Wrap an existing Calculator, keeping its history and settings
+    pub fn with_calculator(calculator: Calculator<T>) -> Self {
+        RpnCalculator { calculator, stack: Vec::new() }
+    }
+
+    pub fn calculator(&self) -> &Calculator<T> {
+        &self.calculator
+    }
+
+    pub fn calculator_mut(&mut self) -> &mut Calculator<T> {
+        &mut self.calculator
+    }
+
+    pub fn into_calculator(self) -> Calculator<T> {
+        self.calculator
+    }
+
+    // The stack from bottom to top
+    pub fn stack(&self) -> &[T] {
+        &self.stack
+    }
+
+    pub fn top(&self) -> Option<&T> {
+        self.stack.last()
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.stack.push(value);
+    }
+
+    pub fn pop(&mut self) -> Result<T, CalcError<T>> {
+        self.require("pop", 1)?;
+        Ok(self.stack.pop().expect("the stack holds a value"))
+    }
+
+    // Fail unless the stack holds at least needed values
+    fn require(&self, command: &str, needed: usize) -> Result<(), CalcError<T>> {
+        if self.stack.len() < needed {
+            return Err(CalcError::StackUnderflow {
+                command: command.to_string(),
+                needed,
+                available: self.stack.len(),
+            });
+        }
+        Ok(())
+    }
+
+    // Replace the top one (unary) or two values with the result of the operation.
+    // If the operation fails the stack is left unchanged.
+    pub fn apply(&mut self, operation_type: OperationType) -> Result<T, CalcError<T>> {
+        if operation_type == OperationType::Assign {
+            return Err(CalcError::Unsupported { operation: "assign in RPN mode".to_string() });
+        }
+        let arity = if operation_type.is_unary() { 1 } else { 2 };
+        self.replace_top(operation_type.get_sign(), arity, |calculator, operands| match operands {
+            [x] => calculator.calculate_unary(operation_type, x.clone()),
+            [x, y] => calculator.calculate(operation_type, x.clone(), y.clone()),
+            _ => unreachable!("arity is 1 or 2"),
+        })
+    }
+
+    // Replace the top two values with the result of a registered operation
+    pub fn apply_registered(&mut self, operation: &RegisteredOperation<T>) -> Result<T, CalcError<T>> {
+        self.replace_top(operation.symbol(), 2, |calculator, operands| {
+            let [x, y] = operands else { unreachable!("arity is 2") };
+            calculator.calculate_registered(operation, Operand::Value(x.clone()), Operand::Value(y.clone()))
+        })
+    }
+
+    // Pop arity values, perform on them and push the result, or put them back if it fails
+    fn replace_top(
+        &mut self,
+        command: &str,
+        arity: usize,
+        perform: impl FnOnce(&mut Calculator<T>, &[T]) -> Result<T, CalcError<T>>,
+    ) -> Result<T, CalcError<T>> {
+        self.require(command, arity)?;
+        let operands = self.stack.split_off(self.stack.len() - arity);
+        let result = perform(&mut self.calculator, &operands);
+        match &result {
+            Ok(value) => self.stack.push(value.clone()),
+            Err(_) => self.stack.extend(operands),
+        }
+        result
+    }
+
+    // a -- a a
+    pub fn dup(&mut self) -> Result<(), CalcError<T>> {
+        self.require("dup", 1)?;
+        self.stack.push(self.stack[self.stack.len() - 1].clone());
+        Ok(())
+    }
+
+    // a b -- b a
+    pub fn swap(&mut self) -> Result<(), CalcError<T>> {
+        self.require("swap", 2)?;
+        let len = self.stack.len();
+        self.stack.swap(len - 2, len - 1);
+        Ok(())
+    }
+
+    // a --
+    pub fn drop(&mut self) -> Result<(), CalcError<T>> {
+        self.require("drop", 1)?;
+        self.stack.pop();
+        Ok(())
+    }
+
+    // a b c -- b c a
+    pub fn rot(&mut self) -> Result<(), CalcError<T>> {
+        self.require("rot", 3)?;
+        let len = self.stack.len();
+        self.stack[len - 3..].rotate_left(1);
+        Ok(())
+    }
+
+    // Empty the stack; history is kept
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    // Evaluate a line of whitespace-separated numbers, operations and stack commands,
+    // e.g. "4 9 + 3 *", and return the top of the stack afterwards.
+    // Words before a failing one keep their effect.
+    pub fn evaluate(&mut self, line: &str) -> Result<Option<T>, CalcError<T>> {
+        for (column, word) in words(line) {
+            self.word(word, column)?;
+        }
+        Ok(self.top().cloned())
+    }
+
+    fn word(&mut self, word: &str, column: usize) -> Result<(), CalcError<T>> {
+        match word {
+            "dup" => self.dup(),
+            "swap" => self.swap(),
+            "drop" => self.drop(),
+            "rot" => self.rot(),
+            "clear" => {
+                self.clear();
+                Ok(())
+            }
+            // Factorial has no sign of its own in RPN, "!" is the complement
+            "factorial" => self.apply(OperationType::Factorial).map(|_| ()),
+            _ => match OperationType::from_sign(word).filter(|operation_type| *operation_type != OperationType::Assign) {
+                Some(operation_type) => self.apply(operation_type).map(|_| ()),
+                None => match self.calculator.operations().by_symbol(word).cloned() {
+                    Some(operation) => self.apply_registered(&operation).map(|_| ()),
+                    None => match parse_literal(word) {
+                        Some(value) => {
+                            self.push(value);
+                            Ok(())
+                        }
+                        None => Err(CalcError::Parse { column, message: format!("unknown word '{}'", word) }),
+                    },
+                },
+            },
+        }
+    }
+}
+
+// The whitespace-separated words of a line with the 1-based columns they start at
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    // Column and byte offset of the word being read
+    let mut start = None;
+    for (index, (offset, c)) in line.char_indices().enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some((index + 1, offset)),
+            (true, Some((column, begin))) => {
+                words.push((column, &line[begin..offset]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((column, begin)) = start {
+        words.push((column, &line[begin..]));
+    }
+    words
+}
@@ -9,12 +9,12 @@ mod calculator_tests {
         let y_in: i64 = 5;
         let mut calculator = Calculator::new();
 
-        assert_eq!(calculator.addition(x_in, y_in), x_in.checked_add(y_in));
+        assert_eq!(calculator.addition(x_in, y_in).ok(), x_in.checked_add(y_in));
 
         let new_x_in: i64 = 8;
         let new_y_in: i64 = 57;
 
-        assert_eq!(calculator.addition(new_x_in, new_y_in), new_x_in.checked_add(new_y_in));
+        assert_eq!(calculator.addition(new_x_in, new_y_in).ok(), new_x_in.checked_add(new_y_in));
     }
 
     #[test]
@@ -23,12 +23,12 @@ mod calculator_tests {
         let y_in: i64 = 5;
         let mut calculator = Calculator::new();
 
-        assert_eq!(calculator.subtraction(x_in, y_in), x_in.checked_sub(y_in));
+        assert_eq!(calculator.subtraction(x_in, y_in).ok(), x_in.checked_sub(y_in));
 
         let new_x_in: i64 = 13;
         let new_y_in: i64 = 21;
 
-        assert_eq!(calculator.subtraction(new_x_in, new_y_in), new_x_in.checked_sub(new_y_in));
+        assert_eq!(calculator.subtraction(new_x_in, new_y_in).ok(), new_x_in.checked_sub(new_y_in));
     }
 
     #[test]
@@ -37,12 +37,12 @@ mod calculator_tests {
         let y_in: i64 = 5;
         let mut calculator = Calculator::new();
 
-        assert_eq!(calculator.multiplication(x_in, y_in), x_in.checked_mul(y_in));
+        assert_eq!(calculator.multiplication(x_in, y_in).ok(), x_in.checked_mul(y_in));
 
         let new_x_in: i64 = 2;
         let new_y_in: i64 = 473;
 
-        assert_eq!(calculator.multiplication(new_x_in, new_y_in), new_x_in.checked_mul(new_y_in));
+        assert_eq!(calculator.multiplication(new_x_in, new_y_in).ok(), new_x_in.checked_mul(new_y_in));
     }
 
     #[test]
@@ -51,7 +51,10 @@ mod calculator_tests {
         let y_in: i64 = 1;
         let mut calculator = Calculator::new();
 
-        assert_eq!(calculator.addition(x_in, y_in), None);
+        assert_eq!(
+            calculator.addition(x_in, y_in),
+            Err(CalcError::Overflow { lhs: x_in, rhs: y_in, operation: OperationType::Addition })
+        );
     }
 
     #[test]
@@ -60,7 +63,10 @@ mod calculator_tests {
         let y_in: i64 = 1;
         let mut calculator = Calculator::new();
 
-        assert_eq!(calculator.subtraction(x_in, y_in), None);
+        assert_eq!(
+            calculator.subtraction(x_in, y_in),
+            Err(CalcError::Underflow { lhs: x_in, rhs: y_in, operation: OperationType::Subtraction })
+        );
     }
 
     #[test]
@@ -69,7 +75,10 @@ mod calculator_tests {
         let y_in: i64 = 2;
         let mut calculator = Calculator::new();
 
-        assert_eq!(calculator.multiplication(x_in, y_in), None);
+        assert_eq!(
+            calculator.multiplication(x_in, y_in),
+            Err(CalcError::Overflow { lhs: x_in, rhs: y_in, operation: OperationType::Multiplication })
+        );
     }
 
     #[test]
@@ -80,48 +89,173 @@ mod calculator_tests {
             let y_in = rng.gen::<i64>();
 
             let mut calculator = Calculator::new();
-            assert_eq!(calculator.addition(x_in, y_in), x_in.checked_add(y_in));
-            assert_eq!(calculator.subtraction(x_in, y_in), x_in.checked_sub(y_in));
-            assert_eq!(calculator.multiplication(x_in, y_in), x_in.checked_mul(y_in));
+            assert_eq!(calculator.addition(x_in, y_in).ok(), x_in.checked_add(y_in));
+            assert_eq!(calculator.subtraction(x_in, y_in).ok(), x_in.checked_sub(y_in));
+            assert_eq!(calculator.multiplication(x_in, y_in).ok(), x_in.checked_mul(y_in));
         }
     }
 
+    #[test]
+    fn division_remainder_euclid_mod() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(calculator.division(-7, 2), Ok(-3));
+        assert_eq!(calculator.remainder(-7, 2), Ok(-1));
+        assert_eq!(calculator.euclid_mod(-7, 2), Ok(1));
+        assert_eq!(calculator.euclid_mod(-7, -2), Ok(1));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(
+            calculator.division(5, 0),
+            Err(CalcError::DivisionByZero { lhs: 5, operation: OperationType::Division })
+        );
+        assert_eq!(
+            calculator.remainder(5, 0),
+            Err(CalcError::DivisionByZero { lhs: 5, operation: OperationType::Remainder })
+        );
+        assert_eq!(
+            calculator.euclid_mod(5, 0),
+            Err(CalcError::DivisionByZero { lhs: 5, operation: OperationType::EuclidMod })
+        );
+        assert_eq!(calculator.show_history(), "");
+    }
+
+    #[test]
+    fn overflow_div() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(
+            calculator.division(i64::MIN, -1),
+            Err(CalcError::Overflow { lhs: i64::MIN, rhs: -1, operation: OperationType::Division })
+        );
+        assert!(calculator.remainder(i64::MIN, -1).is_err());
+        assert!(calculator.euclid_mod(i64::MIN, -1).is_err());
+        assert_eq!(calculator.show_history(), "");
+    }
+
+    #[test]
+    fn random_inputs_division() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50_000 {
+            let x_in = rng.gen::<i64>();
+            let y_in = rng.gen::<i64>() >> rng.gen_range(0..64);
+
+            let mut calculator = Calculator::new();
+            assert_eq!(calculator.division(x_in, y_in).ok(), x_in.checked_div(y_in));
+            assert_eq!(calculator.remainder(x_in, y_in).ok(), x_in.checked_rem(y_in));
+            assert_eq!(calculator.euclid_mod(x_in, y_in).ok(), x_in.checked_rem_euclid(y_in));
+        }
+    }
+
+    #[test]
+    fn show_history_and_repeat_division() {
+        let mut calculator = Calculator::new();
+        calculator.division(17, 5).unwrap();
+        calculator.remainder(-17, 5).unwrap();
+        calculator.euclid_mod(-17, 5).unwrap();
+
+        assert_eq!(calculator.repeat(2), Ok(3));
+
+        let history = calculator.show_history();
+        let expected = "0: 17 / 5 = 3\n1: -17 % 5 = -2\n2: -17 mod 5 = 3\n3: -17 mod 5 = 3\n";
+        assert_eq!(history, expected);
+    }
+
+    #[test]
+    fn error_kinds() {
+        assert_eq!(
+            OperationType::Multiplication.perform(i64::MIN, 2),
+            Err(CalcError::Underflow { lhs: i64::MIN, rhs: 2, operation: OperationType::Multiplication })
+        );
+        assert_eq!(
+            OperationType::Multiplication.perform(i64::MIN, -1),
+            Err(CalcError::Overflow { lhs: i64::MIN, rhs: -1, operation: OperationType::Multiplication })
+        );
+        assert_eq!(
+            OperationType::Addition.perform(i64::MIN, -1),
+            Err(CalcError::Underflow { lhs: i64::MIN, rhs: -1, operation: OperationType::Addition })
+        );
+        assert_eq!(
+            OperationType::Subtraction.perform(i64::MAX, -1),
+            Err(CalcError::Overflow { lhs: i64::MAX, rhs: -1, operation: OperationType::Subtraction })
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        let overflow = CalcError::Overflow { lhs: i64::MAX, rhs: 1, operation: OperationType::Addition };
+        assert_eq!(overflow.to_string(), "overflow: 9223372036854775807 + 1 exceeds i64::MAX");
+
+        let division = CalcError::DivisionByZero { lhs: 5, operation: OperationType::Division };
+        assert_eq!(division.to_string(), "division by zero: 5 / 0");
+
+        let index: CalcError = CalcError::InvalidHistoryIndex { index: 3, len: 1 };
+        assert_eq!(index.to_string(), "invalid history index 3 (history has 1 entries)");
+
+        let boxed: Box<dyn std::error::Error> = Box::new(overflow);
+        assert!(boxed.to_string().starts_with("overflow"));
+    }
+
+    #[test]
+    fn option_wrappers() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(calculator.checked_addition(1, 2), Some(3));
+        assert_eq!(calculator.checked_subtraction(i64::MIN, 1), None);
+        assert_eq!(calculator.checked_multiplication(3, 4), Some(12));
+        assert_eq!(calculator.checked_division(1, 0), None);
+        assert_eq!(calculator.checked_remainder(7, 4), Some(3));
+        assert_eq!(calculator.checked_euclid_mod(-7, 4), Some(1));
+        assert_eq!(calculator.checked_repeat(0), Some(3));
+        assert_eq!(calculator.checked_repeat(99), None);
+    }
+
     #[test]
     fn operation_type_get_sign() {
         assert_eq!(OperationType::Addition.get_sign(), "+");
         assert_eq!(OperationType::Subtraction.get_sign(), "-");
         assert_eq!(OperationType::Multiplication.get_sign(), "*");
+        assert_eq!(OperationType::Division.get_sign(), "/");
+        assert_eq!(OperationType::Remainder.get_sign(), "%");
+        assert_eq!(OperationType::EuclidMod.get_sign(), "mod");
     }
 
     #[test]
     fn operation_type_perform() {
         // Test normal operations
-        assert_eq!(OperationType::Addition.perform(5, 3), Some(8));
-        assert_eq!(OperationType::Subtraction.perform(5, 3), Some(2));
-        assert_eq!(OperationType::Multiplication.perform(5, 3), Some(15));
+        assert_eq!(OperationType::Addition.perform(5, 3), Ok(8));
+        assert_eq!(OperationType::Subtraction.perform(5, 3), Ok(2));
+        assert_eq!(OperationType::Multiplication.perform(5, 3), Ok(15));
 
         // Test with negative numbers
-        assert_eq!(OperationType::Addition.perform(-5, 3), Some(-2));
-        assert_eq!(OperationType::Subtraction.perform(-5, 3), Some(-8));
-        assert_eq!(OperationType::Multiplication.perform(-5, 3), Some(-15));
+        assert_eq!(OperationType::Addition.perform(-5, 3), Ok(-2));
+        assert_eq!(OperationType::Subtraction.perform(-5, 3), Ok(-8));
+        assert_eq!(OperationType::Multiplication.perform(-5, 3), Ok(-15));
 
         // Test with zero
-        assert_eq!(OperationType::Addition.perform(0, 5), Some(5));
-        assert_eq!(OperationType::Subtraction.perform(0, 5), Some(-5));
-        assert_eq!(OperationType::Multiplication.perform(0, 5), Some(0));
+        assert_eq!(OperationType::Addition.perform(0, 5), Ok(5));
+        assert_eq!(OperationType::Subtraction.perform(0, 5), Ok(-5));
+        assert_eq!(OperationType::Multiplication.perform(0, 5), Ok(0));
 
         // Test overflow cases
-        assert_eq!(OperationType::Addition.perform(i64::MAX, 1), None);
-        assert_eq!(OperationType::Subtraction.perform(i64::MIN, 1), None);
-        assert_eq!(OperationType::Multiplication.perform(i64::MAX / 2 + 1, 2), None);
+        assert!(OperationType::Addition.perform(i64::MAX, 1).is_err());
+        assert!(OperationType::Subtraction.perform(i64::MIN, 1).is_err());
+        assert!(OperationType::Multiplication.perform(i64::MAX / 2 + 1, 2).is_err());
+        assert_eq!(OperationType::Addition.checked_perform(i64::MAX, 1), None);
     }
 
     #[test]
     fn operation_creation() {
-        let op = Operation::new(10, 5, OperationType::Addition);
-        assert_eq!(op.first_num, 10);
-        assert_eq!(op.second_num, 5);
-        assert_eq!(op.operation_type.get_sign(), "+");
+        let op = Operation::new(10, 5, OperationType::Addition, 15);
+        assert_eq!(op.kind.arguments(), [Argument::from(10), Argument::from(5)]);
+        assert_eq!(op.operation_type().unwrap().get_sign(), "+");
+        assert_eq!(op.result, 15);
+        assert_eq!(op.id, None);
+        assert_eq!(op.recompute(), Ok(15));
     }
 
     #[test]
@@ -133,7 +267,7 @@ mod calculator_tests {
     #[test]
     fn show_history_single_operation() {
         let mut calculator = Calculator::new();
-        calculator.addition(5, 3);
+        calculator.addition(5, 3).unwrap();
         
         let history = calculator.show_history();
         assert_eq!(history, "0: 5 + 3 = 8\n");
@@ -142,9 +276,9 @@ mod calculator_tests {
     #[test]
     fn show_history_multiple_operations() {
         let mut calculator = Calculator::new();
-        calculator.addition(10, 5);
-        calculator.subtraction(20, 8);
-        calculator.multiplication(3, 4);
+        calculator.addition(10, 5).unwrap();
+        calculator.subtraction(20, 8).unwrap();
+        calculator.multiplication(3, 4).unwrap();
         
         let history = calculator.show_history();
         let expected = "0: 10 + 5 = 15\n1: 20 - 8 = 12\n2: 3 * 4 = 12\n";
@@ -154,12 +288,12 @@ mod calculator_tests {
     #[test]
     fn repeat_valid_operation() {
         let mut calculator = Calculator::new();
-        calculator.addition(7, 3);
-        calculator.subtraction(15, 5);
+        calculator.addition(7, 3).unwrap();
+        calculator.subtraction(15, 5).unwrap();
         
         // Repeat the first operation (addition)
         let result = calculator.repeat(0);
-        assert_eq!(result, Some(10));
+        assert_eq!(result, Ok(10));
         
         // Check that it was added to history
         let history = calculator.show_history();
@@ -170,11 +304,11 @@ mod calculator_tests {
     #[test]
     fn repeat_invalid_index() {
         let mut calculator = Calculator::new();
-        calculator.addition(5, 2);
+        calculator.addition(5, 2).unwrap();
         
         // Try to repeat operation at index 5 (doesn't exist)
         let result = calculator.repeat(5);
-        assert_eq!(result, None);
+        assert_eq!(result, Err(CalcError::InvalidHistoryIndex { index: 5, len: 1 }));
         
         // History should remain unchanged
         let history = calculator.show_history();
@@ -187,7 +321,7 @@ mod calculator_tests {
         
         // Try to repeat when no operations exist
         let result = calculator.repeat(0);
-        assert_eq!(result, None);
+        assert_eq!(result, Err(CalcError::InvalidHistoryIndex { index: 0, len: 0 }));
         
         // History should still be empty
         assert_eq!(calculator.show_history(), "");
@@ -203,9 +337,9 @@ mod calculator_tests {
     #[test]
     fn clear_history_with_operations() {
         let mut calculator = Calculator::new();
-        calculator.addition(1, 2);
-        calculator.subtraction(10, 5);
-        calculator.multiplication(3, 3);
+        calculator.addition(1, 2).unwrap();
+        calculator.subtraction(10, 5).unwrap();
+        calculator.multiplication(3, 3).unwrap();
         
         // Verify history exists
         assert!(!calculator.show_history().is_empty());
@@ -215,7 +349,236 @@ mod calculator_tests {
         assert_eq!(calculator.show_history(), "");
         
         // Verify repeat doesn't work after clearing
-        assert_eq!(calculator.repeat(0), None);
+        assert!(calculator.repeat(0).is_err());
+    }
+
+    #[test]
+    fn undo_redo_operations() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.undo(), Err(CalcError::NothingToUndo));
+        assert_eq!(calculator.redo(), Err(CalcError::NothingToRedo));
+
+        calculator.addition(1, 2).unwrap();
+        calculator.multiplication(3, 4).unwrap();
+        calculator.repeat(0).unwrap();
+
+        calculator.undo().unwrap();
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n1: 3 * 4 = 12\n");
+        calculator.undo().unwrap();
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n");
+        assert!(calculator.can_redo());
+
+        calculator.redo().unwrap();
+        calculator.redo().unwrap();
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n1: 3 * 4 = 12\n2: 1 + 2 = 3\n");
+        assert_eq!(calculator.redo(), Err(CalcError::NothingToRedo));
+    }
+
+    #[test]
+    fn new_operation_invalidates_redo() {
+        let mut calculator = Calculator::new();
+        calculator.addition(1, 2).unwrap();
+        calculator.subtraction(5, 3).unwrap();
+
+        calculator.undo().unwrap();
+        calculator.division(9, 3).unwrap();
+        assert!(!calculator.can_redo());
+        assert_eq!(calculator.redo(), Err(CalcError::NothingToRedo));
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n1: 9 / 3 = 3\n");
+
+        // A failed operation changes nothing, so redo stays available
+        calculator.undo().unwrap();
+        assert!(calculator.division(1, 0).is_err());
+        assert!(calculator.can_redo());
+    }
+
+    #[test]
+    fn undo_clear_history() {
+        let mut calculator = Calculator::new();
+        calculator.addition(1, 2).unwrap();
+        calculator.subtraction(10, 5).unwrap();
+        let before = calculator.show_history();
+
+        calculator.clear_history();
+        assert_eq!(calculator.show_history(), "");
+
+        calculator.undo().unwrap();
+        assert_eq!(calculator.show_history(), before);
+
+        calculator.redo().unwrap();
+        assert_eq!(calculator.show_history(), "");
+
+        // Undo walks back through the clear and then the individual operations
+        calculator.undo().unwrap();
+        calculator.undo().unwrap();
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n");
+    }
+
+    #[test]
+    fn history_stores_results_and_ids() {
+        let mut calculator = Calculator::new();
+        calculator.addition(1, 2).unwrap();
+        calculator.multiplication(3, 4).unwrap();
+        calculator.undo().unwrap();
+        calculator.subtraction(9, 4).unwrap();
+        calculator.repeat(0).unwrap();
+
+        let results: Vec<i64> = calculator.history.iter().map(|operation| operation.result).collect();
+        let ids: Vec<Option<u64>> = calculator.history.iter().map(|operation| operation.id).collect();
+        assert_eq!(results, vec![3, 5, 3]);
+        assert_eq!(ids, vec![Some(0), Some(2), Some(3)]);
+
+        // Redo restores an entry with the id it was recorded under
+        calculator.undo().unwrap();
+        calculator.redo().unwrap();
+        assert_eq!(calculator.history[2].id, Some(3));
+    }
+
+    #[test]
+    fn verify_history_reports_mismatches() {
+        let mut calculator = Calculator::new();
+        calculator.addition(1, 2).unwrap();
+        calculator.division(10, 5).unwrap();
+        calculator.subtraction(7, 7).unwrap();
+        assert!(calculator.verify_history().is_empty());
+
+        calculator.history[1].result = 3;
+        calculator.history[2].kind = OperationKind::Binary {
+            operation_type: OperationType::Division,
+            operands: [Argument::from(7), Argument::from(0)],
+        };
+
+        // show_history prints what was stored instead of recomputing
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n1: 10 / 5 = 3\n2: 7 / 0 = 0\n");
+
+        let mismatches = calculator.verify_history();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].index, 1);
+        assert_eq!(mismatches[0].recomputed, Ok(2));
+        assert_eq!(mismatches[1].index, 2);
+        assert_eq!(
+            mismatches[1].recomputed,
+            Err(CalcError::DivisionByZero { lhs: 7, operation: OperationType::Division })
+        );
+    }
+
+    #[test]
+    fn overflow_policies() {
+        let cases = [
+            (OverflowPolicy::Wrapping, OperationType::Addition, i64::MAX, 1, i64::MIN),
+            (OverflowPolicy::Saturating, OperationType::Addition, i64::MAX, 1, i64::MAX),
+            (OverflowPolicy::Saturating, OperationType::Subtraction, i64::MIN, 1, i64::MIN),
+            (OverflowPolicy::Saturating, OperationType::Multiplication, i64::MIN, 2, i64::MIN),
+            (OverflowPolicy::Wrapping, OperationType::Division, i64::MIN, -1, i64::MIN),
+            (OverflowPolicy::Saturating, OperationType::Division, i64::MIN, -1, i64::MAX),
+            (OverflowPolicy::Saturating, OperationType::Remainder, i64::MIN, -1, 0),
+            (OverflowPolicy::Overflowing, OperationType::EuclidMod, i64::MIN, -1, 0),
+        ];
+        for (policy, operation_type, x, y, expected) in cases {
+            let mut calculator = Calculator::with_overflow_policy(policy);
+            assert_eq!(calculator.calculate(operation_type, x, y), Ok(expected), "{:?} {:?}", policy, operation_type);
+        }
+
+        // Division by zero fails under every policy
+        for policy in [OverflowPolicy::Wrapping, OverflowPolicy::Saturating, OverflowPolicy::Overflowing] {
+            assert_eq!(
+                OperationType::Division.perform_with_policy(1, 0, policy),
+                Err(CalcError::DivisionByZero { lhs: 1, operation: OperationType::Division })
+            );
+        }
+    }
+
+    #[test]
+    fn overflow_policy_in_history() {
+        let mut calculator = Calculator::with_overflow_policy(OverflowPolicy::Saturating);
+        calculator.addition(i64::MAX, 1).unwrap();
+        calculator.calculate_with_policy(OperationType::Multiplication, i64::MAX, 2, OverflowPolicy::Wrapping).unwrap();
+        calculator.calculate_with_policy(OperationType::Addition, i64::MAX, 1, OverflowPolicy::Overflowing).unwrap();
+        calculator.calculate_with_policy(OperationType::EuclidMod, -7, 3, OverflowPolicy::Saturating).unwrap();
+        calculator.calculate_with_policy(OperationType::Addition, 1, 1, OverflowPolicy::Checked).unwrap();
+
+        let expected = "0: 9223372036854775807 +sat 1 = 9223372036854775807\n\
+                        1: 9223372036854775807 *wrap 2 = -2\n\
+                        2: 9223372036854775807 +ovf 1 = -9223372036854775808 (overflowed)\n\
+                        3: -7 mod_sat 3 = 2\n\
+                        4: 1 + 1 = 2\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert!(calculator.verify_history().is_empty());
+
+        // Repeat uses the policy the entry was recorded with, not the current one
+        calculator.set_overflow_policy(OverflowPolicy::Checked);
+        assert_eq!(calculator.repeat(1), Ok(-2));
+        assert_eq!(calculator.history[5].policy, OverflowPolicy::Wrapping);
+        assert!(calculator.addition(i64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn unsigned_backends() {
+        let mut calculator = Calculator::<u64>::default();
+        assert_eq!(calculator.addition(u64::MAX - 1, 1), Ok(u64::MAX));
+        assert_eq!(
+            calculator.subtraction(3, 5),
+            Err(CalcError::Underflow { lhs: 3, rhs: 5, operation: OperationType::Subtraction })
+        );
+        assert_eq!(
+            calculator.multiplication(u64::MAX, 2),
+            Err(CalcError::Overflow { lhs: u64::MAX, rhs: 2, operation: OperationType::Multiplication })
+        );
+        assert_eq!(calculator.euclid_mod(17, 5), Ok(2));
+        assert_eq!(calculator.evaluate("18446744073709551615 / 5"), Ok(3689348814741910323));
+        assert_eq!(
+            calculator.evaluate("-1"),
+            Err(CalcError::Parse { column: 2, message: "integer literal out of range for u64".to_string() })
+        );
+
+        let mut calculator = Calculator::<u128>::with_overflow_policy(OverflowPolicy::Saturating);
+        assert_eq!(calculator.subtraction(1, 2), Ok(0));
+        assert_eq!(calculator.multiplication(u128::MAX, 3), Ok(u128::MAX));
+        assert_eq!(calculator.show_history(), format!("0: 1 -sat 2 = 0\n1: {} *sat 3 = {}\n", u128::MAX, u128::MAX));
+        assert_eq!(
+            format!("{}", CalcError::<u128>::Underflow { lhs: 0, rhs: 1, operation: OperationType::Subtraction }),
+            "underflow: 0 - 1 is below u128::MIN"
+        );
+    }
+
+    #[test]
+    fn narrow_backends() {
+        assert_eq!(OperationType::Addition.perform(100i8, 27), Ok(127));
+        assert_eq!(
+            OperationType::Addition.perform(100i8, 28),
+            Err(CalcError::Overflow { lhs: 100, rhs: 28, operation: OperationType::Addition })
+        );
+        assert_eq!(OperationType::Division.perform_with_policy(i8::MIN, -1, OverflowPolicy::Overflowing), Ok((i8::MIN, true)));
+        assert_eq!(OperationType::Multiplication.perform_with_policy(16u8, 16, OverflowPolicy::Wrapping), Ok((0, false)));
+        assert_eq!(OperationType::Subtraction.checked_perform(0usize, 1), None);
+
+        let mut calculator = Calculator::<i32>::default();
+        assert_eq!(calculator.evaluate("(-4 + 9) * 3 - 2"), Ok(13));
+        assert!(calculator.evaluate("2147483647 + 1").is_err());
+        assert_eq!(calculator.repeat(1), Ok(15));
+
+        // A narrow history widens into a larger backend unchanged
+        let mut calculator = calculator.into_backend::<i64>().unwrap();
+        assert_eq!(calculator.addition(i32::MAX as i64, 1), Ok(2147483648));
+        assert_eq!(calculator.show_history(), "0: -4 + 9 = 5\n1: 5 * 3 = 15\n2: 15 - 2 = 13\n3: 5 * 3 = 15\n4: 2147483647 + 1 = 2147483648\n");
+    }
+
+    #[test]
+    fn random_inputs_narrow_backends() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..1_000 {
+            let (x, y) = (rng.gen::<u8>(), rng.gen::<u8>());
+            let mut calculator = Calculator::<u8>::default();
+            assert_eq!(calculator.addition(x, y).ok(), x.checked_add(y));
+            assert_eq!(calculator.subtraction(x, y).ok(), x.checked_sub(y));
+            assert_eq!(calculator.multiplication(x, y).ok(), x.checked_mul(y));
+            assert_eq!(calculator.division(x, y).ok(), x.checked_div(y));
+
+            let (x, y) = (rng.gen::<i16>(), rng.gen::<i16>());
+            let mut calculator = Calculator::<i16>::with_overflow_policy(OverflowPolicy::Wrapping);
+            assert_eq!(calculator.multiplication(x, y), Ok(x.wrapping_mul(y)));
+            assert_eq!(calculator.euclid_mod(x, y).ok(), x.checked_rem_euclid(y).or(if y == 0 { None } else { Some(0) }));
+        }
     }
 
     #[test]
@@ -223,11 +586,11 @@ mod calculator_tests {
         let mut calculator = Calculator::new();
         
         // Perform some operations
-        calculator.addition(10, 5);
-        calculator.multiplication(3, 4);
+        calculator.addition(10, 5).unwrap();
+        calculator.multiplication(3, 4).unwrap();
         
         // Repeat first operation
-        calculator.repeat(0);
+        calculator.repeat(0).unwrap();
         
         // Check complete history
         let history = calculator.show_history();
@@ -239,11 +602,3100 @@ mod calculator_tests {
         assert_eq!(calculator.show_history(), "");
         
         // Add new operation after clearing
-        calculator.subtraction(20, 7);
+        calculator.subtraction(20, 7).unwrap();
         assert_eq!(calculator.show_history(), "0: 20 - 7 = 13\n");
     }
 }
 
+#[cfg(test)]
+mod ternary_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::decimal::*;
+    use crate::rational::*;
+    use rand::Rng;
+
+    #[test]
+    fn mul_div_widens_the_product() {
+        let mut calculator = Calculator::<u64>::default();
+        // u64::MAX * 3 overflows u64, but the quotient fits
+        assert_eq!(calculator.mul_div(u64::MAX, 3, 7), Ok(7905747460161236406));
+        assert_eq!(calculator.mul_div_ceil(u64::MAX, 3, 7), Ok(7905747460161236407));
+        assert_eq!(calculator.mul_div(10, 3, 5), Ok(6));
+        assert_eq!(calculator.mul_div_ceil(10, 3, 5), Ok(6));
+        assert_eq!(
+            calculator.mul_div(u64::MAX, 3, 2),
+            Err(CalcError::TernaryOverflow {
+                operands: [u64::MAX, 3, 2],
+                operation: TernaryOperationType::MulDiv(Rounding::Floor),
+            })
+        );
+        assert_eq!(
+            calculator.mul_div(1, 2, 0),
+            Err(CalcError::TernaryDivisionByZero {
+                operands: [1, 2, 0],
+                operation: TernaryOperationType::MulDiv(Rounding::Floor),
+            })
+        );
+
+        // i128 needs a 256-bit intermediate
+        let mut calculator = Calculator::<i128>::default();
+        assert_eq!(calculator.mul_div(i128::MAX, i128::MAX, i128::MAX), Ok(i128::MAX));
+        assert_eq!(calculator.mul_div(i128::MIN, 2, 4), Ok(i128::MIN / 2));
+    }
+
+    #[test]
+    fn signed_rounding() {
+        let cases = [
+            (Rounding::Floor, 7, 2, 3),
+            (Rounding::Ceil, 7, 2, 4),
+            (Rounding::Floor, -7, 2, -4),
+            (Rounding::Ceil, -7, 2, -3),
+            (Rounding::HalfEven, 5, 2, 2),
+            (Rounding::HalfEven, 7, 2, 4),
+            (Rounding::HalfEven, -5, 2, -2),
+            (Rounding::HalfEven, -7, 2, -4),
+            (Rounding::HalfEven, -8, 3, -3),
+            (Rounding::Floor, 6, -3, -2),
+        ];
+        for (rounding, x, z, expected) in cases {
+            let operation = TernaryOperationType::MulDiv(rounding);
+            assert_eq!(operation.compute(&x, &1i64, &z), Ok(expected), "{:?} {} / {}", rounding, x, z);
+        }
+    }
+
+    #[test]
+    fn random_inputs_match_i128() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10_000 {
+            let (x, y, z) = (rng.gen::<i64>(), rng.gen::<i32>() as i64, rng.gen_range(1..=i64::MAX));
+            let z = if rng.gen() { z } else { -z };
+            let (exact, divisor) = (x as i128 * y as i128, z as i128);
+            let inexact = exact % divisor != 0;
+            let floor = exact / divisor - (inexact && (exact < 0) != (divisor < 0)) as i128;
+            let ceil = floor + inexact as i128;
+
+            let mut calculator = Calculator::new();
+            assert_eq!(calculator.mul_div(x, y, z).ok(), i64::try_from(floor).ok());
+            assert_eq!(calculator.mul_div_ceil(x, y, z).ok(), i64::try_from(ceil).ok());
+        }
+    }
+
+    #[test]
+    fn basis_points_and_percentages() {
+        let mut calculator = Calculator::<u64>::default();
+        assert_eq!(calculator.apply_bps(1_000_000, 30), Ok(3_000));
+        assert_eq!(calculator.apply_bps(12_345, 30), Ok(37));
+        assert_eq!(calculator.apply_bps_ceil(12_345, 30), Ok(38));
+        assert_eq!(calculator.apply_bps(u64::MAX, 10_000), Ok(u64::MAX));
+        assert_eq!(calculator.percent(250, 15), Ok(37));
+        assert_eq!(calculator.percent_ceil(250, 15), Ok(38));
+
+        // i8 cannot hold the 10000 basis-point divisor
+        let mut calculator = Calculator::<i8>::default();
+        assert_eq!(
+            calculator.apply_bps(100, 50),
+            Err(CalcError::Unsupported { operation: "apply_bps".to_string() })
+        );
+        assert_eq!(calculator.apply_bps(100, 50).unwrap_err().to_string(), "apply_bps is not supported for i8");
+        assert_eq!(calculator.percent(100, 50), Ok(50));
+    }
+
+    #[test]
+    fn ternary_history() {
+        let mut calculator = Calculator::new();
+        calculator.mul_div(1000, 3, 7).unwrap();
+        calculator.mul_div_ceil(1000, 3, 7).unwrap();
+        calculator.apply_bps(1000, 30).unwrap();
+        calculator.percent_ceil(-250, 15).unwrap();
+        calculator.addition(1, 2).unwrap();
+
+        let expected = "0: mul_div(1000, 3, 7) = 428\n\
+                        1: mul_div_ceil(1000, 3, 7) = 429\n\
+                        2: apply_bps(1000, 30) = 3\n\
+                        3: percent_ceil(-250, 15) = -37\n\
+                        4: 1 + 2 = 3\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.history[2].kind, OperationKind::Ternary {
+            operation_type: TernaryOperationType::ApplyBps(Rounding::Floor),
+            operands: [Argument::from(1000), Argument::from(30), Argument::from(10_000)],
+        });
+
+        assert_eq!(calculator.repeat(1), Ok(429));
+        assert_eq!(calculator.history[5].describe(), "mul_div_ceil(1000, 3, 7)");
+        assert!(calculator.verify_history().is_empty());
+        calculator.undo().unwrap();
+        assert_eq!(calculator.history.len(), 5);
+
+        assert_eq!(
+            CalcError::TernaryOverflow { operands: [i64::MAX, 2, 1], operation: TernaryOperationType::MulDiv(Rounding::Ceil) }
+                .to_string(),
+            "overflow: mul_div_ceil(9223372036854775807, 2, 1) does not fit in i64"
+        );
+        assert_eq!(TernaryOperationType::from_name("percent_half_even"), Some(TernaryOperationType::Percent(Rounding::HalfEven)));
+        assert_eq!(TernaryOperationType::from_name("mul_div_floor"), None);
+    }
+
+    #[test]
+    fn other_backends() {
+        let mut calculator = Calculator::<BigInt>::default();
+        let big: BigInt = "123456789012345678901234567890".parse().unwrap();
+        assert_eq!(calculator.mul_div(big.clone(), big.clone(), big.clone()), Ok(big));
+
+        let mut calculator = Calculator::<Rational>::default();
+        assert_eq!(calculator.mul_div(Rational::from(10), Rational::from(3), Rational::from(4)), Ok("15/2".parse().unwrap()));
+
+        let mut calculator = Calculator::<FixedDecimal>::default();
+        let amount: FixedDecimal = "1.500000000".parse().unwrap();
+        assert_eq!(calculator.apply_bps(amount, FixedDecimal::from(30u64)).unwrap().to_string(), "0.004500000");
+        let third = calculator.mul_div("1.00".parse::<FixedDecimal>().unwrap(), FixedDecimal::from(1u64), FixedDecimal::from(3u64));
+        assert_eq!(third.unwrap().to_string(), "0.33");
+    }
+}
+
+#[cfg(test)]
+mod bitwise_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::persistence::*;
+    use crate::rational::*;
+
+    #[test]
+    fn logic_operations() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.bit_and(0xf0, 0x3c), Ok(0x30));
+        assert_eq!(calculator.bit_or(0xf0, 0x0f), Ok(0xff));
+        assert_eq!(calculator.bit_xor(0xff, 0x0f), Ok(0xf0));
+        assert_eq!(calculator.bit_not(0), Ok(-1));
+        assert_eq!(calculator.history.len(), 4);
+        assert!(matches!(calculator.history[3].kind, OperationKind::Unary { operation_type: OperationType::Not, .. }));
+        assert!(calculator.verify_history().is_empty());
+    }
+
+    #[test]
+    fn shift_amount_out_of_range() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.shl(1, 63), Ok(i64::MIN));
+        let expected = CalcError::ShiftOutOfRange { lhs: 1, rhs: 64, operation: OperationType::Shl };
+        assert_eq!(calculator.shl(1, 64), Err(expected.clone()));
+        assert_eq!(expected.to_string(), "shift amount out of range: 1 << 64 for i64");
+        assert!(matches!(calculator.shr(1, -1), Err(CalcError::ShiftOutOfRange { .. })));
+        assert!(matches!(calculator.rotate_left(1, -1), Err(CalcError::ShiftOutOfRange { .. })));
+
+        // Wrapping masks the amount like i64::wrapping_shl
+        let mut calculator = Calculator::with_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(calculator.shl(1, 65), Ok(2));
+        let mut calculator = Calculator::with_overflow_policy(OverflowPolicy::Overflowing);
+        assert_eq!(calculator.shl(1, 65), Ok(2));
+        assert!(calculator.history[0].overflowed);
+    }
+
+    #[test]
+    fn arithmetic_and_logical_shifts() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.shr(-16, 2), Ok(-4));
+        assert_eq!(calculator.logical_shr(-16, 60), Ok(0xf));
+        assert_eq!(calculator.rotate_left(i64::MIN + 1, 1), Ok(3));
+        assert_eq!(calculator.rotate_right(3, 1), Ok(i64::MIN + 1));
+
+        let mut calculator = Calculator::<u8>::default();
+        assert_eq!(calculator.rotate_left(0b1000_0001, 1), Ok(0b0000_0011));
+        assert_eq!(calculator.shr(0x80, 7), Ok(1));
+        assert_eq!(calculator.bit_not(0x0f), Ok(0xf0));
+        assert!(matches!(calculator.shl(1, 8), Err(CalcError::ShiftOutOfRange { .. })));
+
+        let mut calculator = Calculator::<i8>::default();
+        assert_eq!(calculator.shr(-128, 7), Ok(-1));
+        assert_eq!(calculator.logical_shr(-128, 7), Ok(1));
+    }
+
+    #[test]
+    fn history_in_hex_and_binary() {
+        let mut calculator = Calculator::new();
+        calculator.bit_and(0xf0, 0x3c).unwrap();
+        calculator.bit_not(0).unwrap();
+        calculator.shl(5, 2).unwrap();
+        assert_eq!(
+            calculator.show_history_in(Radix::Hex),
+            "0: 0xf0 & 0x3c = 0x30\n1: !0x0 = -0x1\n2: 0x5 << 0x2 = 0x14\n"
+        );
+        assert_eq!(calculator.show_history(), "0: 240 & 60 = 48\n1: !0 = -1\n2: 5 << 2 = 20\n");
+
+        let mut calculator = Calculator::<u8>::default();
+        calculator.bit_xor(0b1010, 0b0110).unwrap();
+        assert_eq!(calculator.show_history_in(Radix::Binary), "0: 0b1010 ^ 0b110 = 0b1100\n");
+    }
+
+    #[test]
+    fn unsupported_without_fixed_width() {
+        let mut calculator = Calculator::<Rational>::default();
+        assert_eq!(
+            calculator.bit_and(Rational::from(6), Rational::from(3)),
+            Err(CalcError::Unsupported { operation: "and".to_string() })
+        );
+        assert_eq!(
+            CalcError::<Rational>::Unsupported { operation: "and".to_string() }.to_string(),
+            "and is not supported for Rational"
+        );
+
+        let mut calculator = Calculator::<BigInt>::default();
+        assert!(matches!(calculator.shl(BigInt::from(1), BigInt::from(3)), Err(CalcError::Unsupported { .. })));
+        assert!(calculator.history.is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut calculator = Calculator::new();
+        calculator.bit_or(12, 3).unwrap();
+        calculator.bit_not(7).unwrap();
+        calculator.logical_shr(-1, 60).unwrap();
+        calculator.rotate_right(1, 1).unwrap();
+
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        for format in [Format::Json, Format::Csv] {
+            let loaded = parse_history::<i64>(&format_history(&calculator.history, format), format).unwrap();
+            assert_eq!(loaded, expected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod radix_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::decimal::*;
+    use crate::rational::*;
+
+    #[test]
+    fn prefixed_literals() {
+        assert_eq!(parse_literal::<i64>("0xFFFF_FFFF"), Some(0xffff_ffff));
+        assert_eq!(parse_literal::<u32>("0xFFFF_FFFF"), Some(u32::MAX));
+        assert_eq!(parse_literal::<i64>("0o755"), Some(0o755));
+        assert_eq!(parse_literal::<i64>("0b1010_0001"), Some(0b1010_0001));
+        assert_eq!(parse_literal::<i64>("1_000_000"), Some(1_000_000));
+        assert_eq!(parse_literal::<i8>("-0x80"), Some(i8::MIN));
+        assert_eq!(parse_literal::<i8>("0x80"), None);
+        assert_eq!(parse_literal::<u8>("-0x1"), None);
+        for text in ["0x", "0x_", "0x-5", "0x+5", "_1", "0b102", "0xg"] {
+            assert_eq!(parse_literal::<i64>(text), None, "{}", text);
+        }
+
+        // Arbitrary-precision integers take any prefix and any length
+        let big: BigInt = "1267650600228229401496703205375".parse().unwrap();
+        assert_eq!(parse_literal::<BigInt>("0x0f_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF"), Some(big.clone()));
+        assert_eq!(parse_literal::<BigInt>(&format!("-0b{}", "1".repeat(100))), Some(-big));
+        assert_eq!(parse_literal::<BigInt>("0o777"), Some(BigInt::from(511)));
+        assert_eq!(parse_literal::<AutoInt>("0xff"), Some(AutoInt::from(255)));
+        assert_eq!(parse_literal::<AutoInt>("0x1_0000_0000_0000_0000").map(|value| value.is_promoted()), Some(true));
+        assert_eq!(parse_literal::<BigInt>("0b102"), None);
+
+        // Fractions and decimals only take decimal literals
+        assert_eq!(parse_literal::<Rational>("7/2"), Rational::new(7, 2));
+        assert_eq!(parse_literal::<Rational>("0x10"), None);
+        assert_eq!(parse_literal::<FixedDecimal>("1_000.50"), FixedDecimal::new(100_050, 2));
+    }
+
+    #[test]
+    fn evaluate_prefixed_literals() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.evaluate("0xFFFF_FFFF + 1"), Ok(0x1_0000_0000));
+        assert_eq!(calculator.evaluate("0b1111 & 0o7"), Ok(7));
+        assert_eq!(calculator.evaluate("-0x8000_0000_0000_0000"), Ok(i64::MIN));
+        assert_eq!(
+            calculator.evaluate("1 + 0x1_0000_0000_0000_0000"),
+            Err(CalcError::Parse { column: 5, message: "integer literal out of range for i64".to_string() })
+        );
+        assert_eq!(
+            calculator.evaluate("0b12"),
+            Err(CalcError::Parse { column: 1, message: "'0b12' is not a valid i64 literal".to_string() })
+        );
+
+        let mut calculator = Calculator::<AutoInt>::default();
+        assert_eq!(calculator.evaluate("0xff + 0xFFFF_FFFF_FFFF_FFFF"), Ok("18446744073709551870".parse().unwrap()));
+        let mut calculator = Calculator::<Rational>::default();
+        assert_eq!(
+            calculator.evaluate("0x10 + 1"),
+            Err(CalcError::Parse { column: 1, message: "hex literals are not supported by Rational".to_string() })
+        );
+    }
+
+    #[test]
+    fn signed_and_twos_complement_display() {
+        let format = |radix, bits| NumberFormat { radix, twos_complement: BitWidth::from_bits(bits) };
+
+        assert_eq!((-16i64).to_radix_string(Radix::Hex.into()), "-0x10");
+        assert_eq!((-16i64).to_radix_string(format(Radix::Hex, 8)), "0xf0");
+        assert_eq!(5i64.to_radix_string(format(Radix::Hex, 8)), "0x05");
+        assert_eq!(200i64.to_radix_string(format(Radix::Hex, 8)), "0xc8");
+        // Values that do not fit the width keep their sign
+        assert_eq!((-200i64).to_radix_string(format(Radix::Hex, 8)), "-0xc8");
+        assert_eq!(256i64.to_radix_string(format(Radix::Hex, 8)), "0x100");
+        assert_eq!((-16i64).to_radix_string(format(Radix::Binary, 8)), "0b11110000");
+        assert_eq!((-16i64).to_radix_string(format(Radix::Octal, 8)), "0o360");
+        assert_eq!((-1i64).to_radix_string(format(Radix::Decimal, 16)), "65535");
+        assert_eq!((-1i32).to_radix_string(format(Radix::Hex, 64)), "0xffffffffffffffff");
+
+        assert_eq!(i8::MIN.to_radix_string(Radix::Hex.into()), "-0x80");
+        assert_eq!(i128::MIN.to_radix_string(Radix::Hex.into()), format!("-0x8{}", "0".repeat(31)));
+        assert_eq!(u128::MAX.to_radix_string(Radix::Hex.into()), format!("0x{}", "f".repeat(32)));
+        assert_eq!(Rational::new(7, 2).unwrap().to_radix_string(format(Radix::Hex, 8)), "7/2");
+    }
+
+    #[test]
+    fn calculator_display_setting() {
+        let mut calculator = Calculator::new();
+        calculator.subtraction(0, 16).unwrap();
+        calculator.bit_and(-16, 0xff).unwrap();
+        assert_eq!(calculator.show_history(), "0: 0 - 16 = -16\n1: -16 & 255 = 240\n");
+
+        calculator.set_radix(Radix::Hex);
+        assert_eq!(calculator.show_history(), "0: 0x0 - 0x10 = -0x10\n1: -0x10 & 0xff = 0xf0\n");
+        calculator.set_twos_complement(Some(BitWidth::Bits8));
+        assert_eq!(calculator.show_history(), "0: 0x00 - 0x10 = 0xf0\n1: 0xf0 & 0xff = 0xf0\n");
+        assert_eq!(calculator.show_history_in(Radix::Decimal), "0: 0 - 16 = -16\n1: -16 & 255 = 240\n");
+        assert_eq!(
+            calculator.number_format(),
+            NumberFormat { radix: Radix::Hex, twos_complement: Some(BitWidth::Bits8) }
+        );
+
+        // Signed hex parses back to the same operation
+        calculator.set_twos_complement(None);
+        let describe = calculator.history[1].describe_in(calculator.number_format());
+        assert_eq!(calculator.evaluate(&describe), Ok(0xf0));
+        assert_eq!(calculator.into_backend::<i128>().unwrap().number_format().radix, Radix::Hex);
+    }
+}
+
+#[cfg(test)]
+mod power_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::decimal::*;
+    use crate::persistence::*;
+    use crate::rational::*;
+    use rand::Rng;
+
+    #[test]
+    fn power() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.power(2, 10), Ok(1024));
+        assert_eq!(calculator.power(10, 18), Ok(1_000_000_000_000_000_000));
+        assert_eq!(calculator.power(7, 0), Ok(1));
+        assert_eq!(calculator.power(-2, 63), Ok(i64::MIN));
+        assert_eq!(
+            calculator.power(10, 19),
+            Err(CalcError::Overflow { lhs: 10, rhs: 19, operation: OperationType::Power })
+        );
+        assert_eq!(
+            calculator.power(-2, 65),
+            Err(CalcError::Underflow { lhs: -2, rhs: 65, operation: OperationType::Power })
+        );
+        assert!(matches!(calculator.power(-2, 64), Err(CalcError::Overflow { .. })));
+
+        let error = calculator.power(2, -1).unwrap_err();
+        assert_eq!(error, CalcError::InvalidArgument { lhs: 2, rhs: -1, operation: OperationType::Power });
+        assert_eq!(error.to_string(), "invalid argument: 2 ** -1");
+        // Exponents above u32::MAX are rejected even when the result would be 1
+        let mut calculator = Calculator::<u64>::default();
+        assert!(matches!(calculator.power(1, 1 << 32), Err(CalcError::InvalidArgument { .. })));
+        assert_eq!(calculator.power(1, u32::MAX as u64), Ok(1));
+
+        let mut calculator = Calculator::with_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(calculator.power(2, 64), Ok(0));
+        calculator.set_overflow_policy(OverflowPolicy::Saturating);
+        assert_eq!(calculator.power(-3, 41), Ok(i64::MIN));
+        calculator.set_overflow_policy(OverflowPolicy::Overflowing);
+        assert_eq!(calculator.power(3, 41), Ok(3i64.wrapping_pow(41)));
+        assert!(calculator.history[2].overflowed);
+        assert_eq!(calculator.show_history().lines().next(), Some("0: 2 **wrap 64 = 0"));
+    }
+
+    #[test]
+    fn roots() {
+        let mut calculator = Calculator::new();
+        for (x, expected) in [(0, 0), (1, 1), (15, 3), (16, 4), (i64::MAX, 3037000499)] {
+            assert_eq!(calculator.isqrt(x), Ok(expected), "isqrt({})", x);
+        }
+        for (x, expected) in [(0, 0), (26, 2), (27, 3), (-26, -2), (-27, -3), (i64::MIN, -2097152)] {
+            assert_eq!(calculator.icbrt(x), Ok(expected), "icbrt({})", x);
+        }
+        let error = calculator.isqrt(-1).unwrap_err();
+        assert_eq!(error.to_string(), "invalid argument: isqrt(-1)");
+
+        assert_eq!(Calculator::<u128>::default().icbrt(u128::MAX), Ok(6981463658331));
+        assert_eq!(Calculator::<i8>::default().icbrt(i8::MIN), Ok(-5));
+        assert_eq!(Calculator::<u8>::default().isqrt(u8::MAX), Ok(15));
+    }
+
+    #[test]
+    fn random_roots_bracket_the_input() {
+        let mut rng = rand::thread_rng();
+        let mut calculator = Calculator::new();
+        for _ in 0..10_000 {
+            let x = rng.gen_range(0..=i64::MAX) >> rng.gen_range(0..63);
+            let root = calculator.isqrt(x).unwrap() as i128;
+            assert!(root * root <= x as i128 && (root + 1) * (root + 1) > x as i128, "isqrt({})", x);
+            let root = calculator.icbrt(x).unwrap() as i128;
+            assert!(root.pow(3) <= x as i128 && (root + 1).pow(3) > x as i128, "icbrt({})", x);
+            calculator.clear_history();
+        }
+    }
+
+    #[test]
+    fn logarithms() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.ilog2(1), Ok(0));
+        assert_eq!(calculator.ilog2(1025), Ok(10));
+        assert_eq!(calculator.ilog10(999), Ok(2));
+        assert_eq!(calculator.ilog10(i64::MAX), Ok(18));
+        assert_eq!(calculator.ilog(81, 3), Ok(4));
+        assert_eq!(calculator.ilog(80, 3), Ok(3));
+        assert_eq!(calculator.ilog(5, i64::MAX), Ok(0));
+        for (x, base) in [(0, 2), (-8, 2), (8, 1), (8, 0), (8, -2)] {
+            assert_eq!(
+                calculator.ilog(x, base),
+                Err(CalcError::InvalidArgument { lhs: x, rhs: base, operation: OperationType::Ilog }),
+                "ilog({}, {})",
+                x,
+                base
+            );
+        }
+        assert!(matches!(calculator.ilog2(0), Err(CalcError::InvalidArgument { .. })));
+        assert_eq!(calculator.ilog10(-5).unwrap_err().to_string(), "invalid argument: ilog10(-5)");
+        assert_eq!(calculator.ilog(8, 1).unwrap_err().to_string(), "invalid argument: ilog(8, 1)");
+        assert_eq!(Calculator::<u64>::default().ilog(u64::MAX, 1 << 40), Ok(1));
+    }
+
+    #[test]
+    fn history_entries() {
+        let mut calculator = Calculator::new();
+        calculator.power(2, 10).unwrap();
+        calculator.isqrt(17).unwrap();
+        calculator.icbrt(-30).unwrap();
+        calculator.ilog2(1024).unwrap();
+        calculator.ilog(100, 10).unwrap();
+        let expected = "0: 2 ** 10 = 1024\n\
+                        1: isqrt(17) = 4\n\
+                        2: icbrt(-30) = -3\n\
+                        3: ilog2(1024) = 10\n\
+                        4: ilog(100, 10) = 2\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.repeat(1), Ok(4));
+        assert!(calculator.verify_history().is_empty());
+
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        for format in [Format::Json, Format::Csv] {
+            let loaded = parse_history::<i64>(&format_history(&calculator.history, format), format).unwrap();
+            assert_eq!(loaded, expected);
+        }
+    }
+
+    #[test]
+    fn bigint_backends() {
+        let big = |text: &str| text.parse::<BigInt>().unwrap();
+        let mut calculator = Calculator::<BigInt>::default();
+        assert_eq!(calculator.power(BigInt::from(2), BigInt::from(100)), Ok(big("1267650600228229401496703205376")));
+        assert_eq!(calculator.power(BigInt::from(-3), BigInt::from(3)), Ok(BigInt::from(-27)));
+        assert_eq!(calculator.isqrt(BigInt::from(10).pow(40)), Ok(BigInt::from(10).pow(20)));
+        assert_eq!(calculator.isqrt(big("99999999999999999999")), Ok(BigInt::from(9999999999u64)));
+        assert_eq!(calculator.icbrt(-(BigInt::from(10).pow(30) + BigInt::from(1))), Ok(BigInt::from(-10_000_000_000i64)));
+        assert_eq!(calculator.ilog2(BigInt::from(2).pow(200)), Ok(BigInt::from(200)));
+        assert_eq!(calculator.ilog10(big("99999999999999999999999")), Ok(BigInt::from(22)));
+        assert_eq!(calculator.ilog(BigInt::from(3).pow(50), BigInt::from(3)), Ok(BigInt::from(50)));
+        assert!(matches!(calculator.isqrt(BigInt::from(-4)), Err(CalcError::InvalidArgument { .. })));
+        assert!(matches!(calculator.power(BigInt::from(2), BigInt::from(1u64 << 32)), Err(CalcError::InvalidArgument { .. })));
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..1_000 {
+            let x = rng.gen::<u64>() >> rng.gen_range(0..64);
+            assert_eq!(calculator.isqrt(BigInt::from(x)), Ok(BigInt::from(x.isqrt())), "isqrt({})", x);
+            if x > 0 {
+                assert_eq!(calculator.ilog10(BigInt::from(x)), Ok(BigInt::from(x.ilog10())), "ilog10({})", x);
+            }
+        }
+
+        let mut calculator = Calculator::<AutoInt>::default();
+        assert_eq!(calculator.power(AutoInt::from(2), AutoInt::from(62)), Ok(AutoInt::from(1i64 << 62)));
+        let promoted = calculator.power(AutoInt::from(2), AutoInt::from(64)).unwrap();
+        assert!(promoted.is_promoted());
+        assert_eq!(calculator.isqrt(promoted), Ok(AutoInt::from(1i64 << 32)));
+    }
+
+    #[test]
+    fn unbounded_results_are_limited() {
+        let mut calculator = Calculator::<BigInt>::default();
+        let too_large = calculator.power(BigInt::from(2), BigInt::from(4_000_000_000u32)).unwrap_err();
+        assert_eq!(
+            too_large,
+            CalcError::TooLarge { lhs: BigInt::from(2), rhs: BigInt::from(4_000_000_000u32), operation: OperationType::Power }
+        );
+        assert_eq!(too_large.to_string(), "too large: 2 ** 4000000000 could need more than 262144 bits as a BigInt");
+        assert_eq!(calculator.power(BigInt::from(-1), BigInt::from(4_000_000_001u32)), Ok(BigInt::from(-1)));
+        assert_eq!(calculator.power(BigInt::from(2), BigInt::from(MAX_RESULT_BITS / 2)).map(|value| value.bits()), Ok(131073));
+        assert!(matches!(calculator.factorial(BigInt::from(1_000_000)), Err(CalcError::TooLarge { .. })));
+        assert!(calculator.factorial(BigInt::from(2_000)).is_ok());
+        assert_eq!(calculator.history.len(), 3);
+
+        let mut calculator = Calculator::<AutoInt>::default();
+        assert!(matches!(calculator.evaluate("2 ** 4000000000"), Err(CalcError::TooLarge { .. })));
+        assert!(matches!(calculator.evaluate("1000000!"), Err(CalcError::TooLarge { .. })));
+        assert_eq!(calculator.evaluate("2 ** 10"), Ok(AutoInt::from(1024)));
+    }
+
+    #[test]
+    fn rational_and_decimal_backends() {
+        let mut calculator = Calculator::<Rational>::default();
+        let two_thirds = Rational::new(2, 3).unwrap();
+        assert_eq!(calculator.power(two_thirds, Rational::from(3)), Ok(Rational::new(8, 27).unwrap()));
+        assert!(matches!(
+            calculator.power(two_thirds, Rational::new(1, 2).unwrap()),
+            Err(CalcError::InvalidArgument { .. })
+        ));
+        assert_eq!(
+            calculator.isqrt(Rational::from(4)),
+            Err(CalcError::Unsupported { operation: "isqrt".to_string() })
+        );
+
+        let decimal = |text: &str| text.parse::<FixedDecimal>().unwrap();
+        let mut calculator = Calculator::<FixedDecimal>::default();
+        // 1.1025 rounds half-even to 1.10
+        assert_eq!(calculator.power(decimal("1.05"), decimal("2")), Ok(decimal("1.10")));
+        assert_eq!(calculator.power(decimal("1.05"), decimal("2.00")), Ok(decimal("1.10")));
+        assert_eq!(calculator.power(decimal("1.05"), decimal("0")).map(|value| value.decimals()), Ok(2));
+        assert_eq!(calculator.power(decimal("10"), decimal("9")), Ok(decimal("1000000000")));
+        assert!(matches!(calculator.power(decimal("2"), decimal("0.5")), Err(CalcError::InvalidArgument { .. })));
+        assert!(matches!(calculator.ilog2(decimal("8")), Err(CalcError::Unsupported { .. })));
+    }
+}
+
+#[cfg(test)]
+mod number_theory_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::number_theory::*;
+    use crate::persistence::*;
+    use crate::rational::*;
+    use rand::Rng;
+
+    #[test]
+    fn gcd_and_lcm() {
+        assert_eq!(gcd(0, 0), 0);
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(gcd(17, 0), 17);
+        assert_eq!(lcm(4, 6), Some(12));
+        assert_eq!(lcm(0, 6), Some(0));
+        assert_eq!(lcm(u64::MAX, u64::MAX - 1), None);
+        assert_eq!(lcm(1 << 32, 1 << 31), Some(1 << 32));
+        assert_eq!(gcd_u128(u128::MAX, u128::MAX - 2), 1);
+        assert_eq!(gcd_u128(1 << 127, 3 << 100), 1 << 100);
+    }
+
+    #[test]
+    fn extended_gcd_identity() {
+        assert_eq!(extended_gcd(240, 46), (2, -9, 47));
+        assert_eq!(extended_gcd(0, 5), (5, 0, 1));
+        let mut rng = rand::thread_rng();
+        for _ in 0..10_000 {
+            let (a, b) = (rng.gen::<u64>(), rng.gen::<u64>() >> rng.gen_range(0..64));
+            let (g, x, y) = extended_gcd(a, b);
+            assert_eq!(g, gcd(a, b));
+            let combination = BigInt::from(a) * BigInt::from(x) + BigInt::from(b) * BigInt::from(y);
+            assert_eq!(combination, BigInt::from(g), "extended_gcd({}, {})", a, b);
+        }
+    }
+
+    #[test]
+    fn modular_arithmetic() {
+        assert_eq!(mod_pow(2, 10, 1000), Some(24));
+        assert_eq!(mod_pow(7, 0, 1), Some(0));
+        assert_eq!(mod_pow(7, 0, 13), Some(1));
+        assert_eq!(mod_pow(2, 5, 0), None);
+        assert_eq!(mod_pow(u64::MAX - 1, u64::MAX, u64::MAX), Some(u64::MAX - 1));
+        assert_eq!(mod_inverse(3, 7), Some(5));
+        assert_eq!(mod_inverse(10, 7), Some(5));
+        assert_eq!(mod_inverse(4, 8), None);
+        assert_eq!(mod_inverse(5, 1), Some(0));
+        assert_eq!(mod_inverse(5, 0), None);
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..10_000 {
+            let (a, modulus) = (rng.gen::<u64>(), rng.gen_range(2..=u64::MAX));
+            match mod_inverse(a, modulus) {
+                Some(inverse) => {
+                    assert!(inverse < modulus);
+                    assert_eq!(a as u128 % modulus as u128 * inverse as u128 % modulus as u128, 1);
+                }
+                None => assert_ne!(gcd(a, modulus), 1),
+            }
+            // Fermat's little theorem for a prime modulus
+            let p = 18446744073709551557;
+            assert_eq!(mod_pow(a % p, p - 1, p), Some(if a % p == 0 { 0 } else { 1 }));
+        }
+    }
+
+    #[test]
+    fn primality() {
+        // Compare with a sieve for small n
+        let mut composite = vec![false; 10_000];
+        for n in 2..composite.len() {
+            if !composite[n] {
+                (n * n..composite.len()).step_by(n).for_each(|multiple| composite[multiple] = true);
+            }
+            assert_eq!(is_prime(n as u64), !composite[n], "is_prime({})", n);
+        }
+        assert!(!is_prime(0) && !is_prime(1));
+        // Carmichael numbers and strong pseudoprimes to several small bases
+        for n in [561, 1_105, 3_215_031_751, 3_825_123_056_546_413_051, u64::MAX] {
+            assert!(!is_prime(n), "{} is composite", n);
+        }
+        for n in [(1 << 61) - 1, 4_294_967_291, 18_446_744_073_709_551_557] {
+            assert!(is_prime(n), "{} is prime", n);
+        }
+    }
+
+    #[test]
+    fn factorization() {
+        assert_eq!(factorize(0), Vec::<u64>::new());
+        assert_eq!(factorize(1), Vec::<u64>::new());
+        assert_eq!(factorize(3072), [vec![2; 10], vec![3]].concat());
+        assert_eq!(factorize(600_851_475_143), vec![71, 839, 1471, 6857]);
+        assert_eq!(factorize(u64::MAX), vec![3, 5, 17, 257, 641, 65537, 6700417]);
+        assert_eq!(factorize(18_446_743_979_220_271_189), vec![4_294_967_279, 4_294_967_291]);
+        assert_eq!(factorize(1681), vec![41, 41]);
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            let n = rng.gen::<u64>() >> rng.gen_range(0..60);
+            let factors = factorize(n);
+            assert!(factors.iter().all(|&factor| is_prime(factor)), "factorize({})", n);
+            assert!(factors.windows(2).all(|pair| pair[0] <= pair[1]));
+            if n > 1 {
+                assert_eq!(factors.iter().product::<u64>(), n);
+            }
+        }
+    }
+
+    #[test]
+    fn calculator_operations() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.gcd(12, -18), Ok(6));
+        assert_eq!(calculator.lcm(-4, 6), Ok(12));
+        assert_eq!(calculator.mod_inverse(3, 7), Ok(5));
+        assert_eq!(calculator.mod_inverse(-3, 7), Ok(2));
+        let expected = "0: gcd(12, -18) = 6\n\
+                        1: lcm(-4, 6) = 12\n\
+                        2: mod_inverse(3, 7) = 5\n\
+                        3: mod_inverse(-3, 7) = 2\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.repeat(0), Ok(6));
+
+        assert_eq!(
+            calculator.lcm(i64::MAX, i64::MAX - 1),
+            Err(CalcError::Overflow { lhs: i64::MAX, rhs: i64::MAX - 1, operation: OperationType::Lcm })
+        );
+        assert!(matches!(calculator.gcd(i64::MIN, 0), Err(CalcError::Overflow { .. })));
+        let error = calculator.mod_inverse(4, 8).unwrap_err();
+        assert_eq!(error, CalcError::NotInvertible { value: 4, modulus: 8 });
+        assert_eq!(error.to_string(), "no inverse: 4 is not invertible modulo 8");
+        for modulus in [0, -7] {
+            assert_eq!(
+                calculator.mod_inverse(3, modulus),
+                Err(CalcError::InvalidArgument { lhs: 3, rhs: modulus, operation: OperationType::ModInverse })
+            );
+        }
+        assert_eq!(calculator.evaluate("gcd(12, 18) + lcm(4, 6)"), Ok(18));
+
+        // 128-bit operands use the BigInt path
+        let prime = i128::MAX;
+        let mut calculator = Calculator::<i128>::default();
+        assert_eq!(calculator.mod_inverse(3, prime), Ok(113427455640312821154458202477256070485));
+        assert_eq!(calculator.gcd(prime - 1, 6), Ok(6));
+        let mut calculator = Calculator::<BigInt>::default();
+        let expected = BigInt::from(u64::MAX as u128 * (u64::MAX - 1) as u128);
+        assert_eq!(calculator.lcm(BigInt::from(u64::MAX), BigInt::from(u64::MAX - 1)), Ok(expected));
+
+        let mut calculator = Calculator::<Rational>::default();
+        assert!(matches!(calculator.gcd(Rational::from(4), Rational::from(6)), Err(CalcError::Unsupported { .. })));
+
+        // The number_theory path for up to 64 bits agrees with BigInt
+        let mut rng = rand::thread_rng();
+        for _ in 0..10_000 {
+            let (x, y) = (rng.gen::<i64>() >> rng.gen_range(0..63), rng.gen::<i64>() >> rng.gen_range(0..63));
+            for operation_type in [OperationType::Gcd, OperationType::Lcm, OperationType::ModInverse] {
+                // Calculator::mod_inverse rejects a modulus below 1 before apply
+                if operation_type == OperationType::ModInverse && y < 1 {
+                    continue;
+                }
+                let expected = BigInt::apply(operation_type, &BigInt::from(x), &BigInt::from(y), OverflowPolicy::Checked)
+                    .and_then(|(value, _)| i64::try_from(&value).ok());
+                let actual = i64::apply(operation_type, &x, &y, OverflowPolicy::Checked).map(|(value, _)| value);
+                assert_eq!(actual, expected, "{:?}({}, {})", operation_type, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut calculator = Calculator::new();
+        calculator.gcd(84, 36).unwrap();
+        calculator.lcm(21, 6).unwrap();
+        calculator.mod_inverse(17, 3120).unwrap();
+
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        for format in [Format::Json, Format::Csv] {
+            let loaded = parse_history::<i64>(&format_history(&calculator.history, format), format).unwrap();
+            assert_eq!(loaded, expected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod unary_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::decimal::*;
+    use crate::persistence::*;
+    use crate::rational::*;
+    use crate::rpn::*;
+    use rand::Rng;
+
+    #[test]
+    fn checked_results() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.neg(-4), Ok(4));
+        assert_eq!(calculator.abs(-7), Ok(7));
+        assert_eq!(calculator.signum(-7), Ok(-1));
+        assert_eq!(calculator.signum(0), Ok(0));
+        assert_eq!(calculator.square(-12), Ok(144));
+        assert_eq!(calculator.factorial(5), Ok(120));
+        assert_eq!(calculator.factorial(0), Ok(1));
+        assert_eq!(calculator.factorial(20), Ok(2_432_902_008_176_640_000));
+        let expected = "0: neg(-4) = 4\n\
+                        1: abs(-7) = 7\n\
+                        2: signum(-7) = -1\n\
+                        3: signum(0) = 0\n\
+                        4: square(-12) = 144\n\
+                        5: 5! = 120\n\
+                        6: 0! = 1\n\
+                        7: 20! = 2432902008176640000\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.repeat(5), Ok(120));
+        assert_eq!(calculator.repeat(0), Ok(4));
+        assert!(calculator.verify_history().is_empty());
+
+        let overflow = |x, operation| CalcError::Overflow { lhs: x, rhs: 0, operation };
+        assert_eq!(calculator.neg(i64::MIN), Err(overflow(i64::MIN, OperationType::Neg)));
+        assert_eq!(calculator.abs(i64::MIN), Err(overflow(i64::MIN, OperationType::Abs)));
+        assert_eq!(calculator.square(3_037_000_500), Err(overflow(3_037_000_500, OperationType::Square)));
+        assert_eq!(calculator.factorial(21), Err(overflow(21, OperationType::Factorial)));
+        assert_eq!(
+            calculator.factorial(-1),
+            Err(CalcError::InvalidArgument { lhs: -1, rhs: 0, operation: OperationType::Factorial })
+        );
+        assert!(calculator.factorial(1 << 40).is_err());
+        assert_eq!(overflow(i64::MIN, OperationType::Neg).to_string(), "overflow: neg(-9223372036854775808) exceeds i64::MAX");
+        assert_eq!(overflow(21, OperationType::Factorial).to_string(), "overflow: 21! exceeds i64::MAX");
+        assert_eq!(calculator.history.len(), 10);
+    }
+
+    #[test]
+    fn policies_and_widths() {
+        let mut wrapping = Calculator::<i64>::with_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(wrapping.neg(i64::MIN), Ok(i64::MIN));
+        assert_eq!(wrapping.abs(i64::MIN), Ok(i64::MIN));
+        assert_eq!(wrapping.show_history(), "0: neg_wrap(-9223372036854775808) = -9223372036854775808\n1: abs_wrap(-9223372036854775808) = -9223372036854775808\n");
+        let mut saturating = Calculator::<i64>::with_overflow_policy(OverflowPolicy::Saturating);
+        assert_eq!(saturating.neg(i64::MIN), Ok(i64::MAX));
+        assert_eq!(saturating.factorial(25), Ok(i64::MAX));
+
+        // 10! is a multiple of 256, and wrapping products stay at zero once they get there
+        let mut wrapping = Calculator::<u8>::with_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(wrapping.factorial(10), Ok(0));
+        assert_eq!(wrapping.factorial(255), Ok(0));
+        let mut wrapping = Calculator::<u32>::with_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(wrapping.factorial(u32::MAX), Ok(0));
+        let mut overflowing = Calculator::<u8>::with_overflow_policy(OverflowPolicy::Overflowing);
+        assert_eq!(overflowing.factorial(6), Ok(208));
+        assert!(overflowing.history[0].overflowed);
+        assert_eq!(overflowing.show_history(), "0: 6!ovf = 208 (overflowed)\n");
+
+        let mut unsigned = Calculator::<u8>::default();
+        assert_eq!(unsigned.neg(0), Ok(0));
+        assert_eq!(unsigned.signum(200), Ok(1));
+        assert_eq!(unsigned.abs(200), Ok(200));
+        let underflow = unsigned.neg(5).unwrap_err();
+        assert_eq!(underflow, CalcError::Underflow { lhs: 5, rhs: 0, operation: OperationType::Neg });
+        assert_eq!(underflow.to_string(), "underflow: neg(5) is below u8::MIN");
+    }
+
+    #[test]
+    fn matches_std() {
+        let mut rng = rand::thread_rng();
+        let mut calculator = Calculator::<i32>::default();
+        for _ in 0..1000 {
+            let x: i32 = rng.gen();
+            assert_eq!(calculator.neg(x).ok(), x.checked_neg());
+            assert_eq!(calculator.abs(x).ok(), x.checked_abs());
+            assert_eq!(calculator.signum(x), Ok(x.signum()));
+            assert_eq!(calculator.square(x).ok(), x.checked_mul(x));
+            let n = rng.gen_range(0..15);
+            assert_eq!(calculator.factorial(n).ok(), (1..=n).try_fold(1i32, |product, k| product.checked_mul(k)));
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.evaluate("5!"), Ok(120));
+        assert_eq!(calculator.evaluate("-3!"), Ok(-6));
+        assert_eq!(calculator.evaluate("3!!"), Ok(720));
+        assert_eq!(calculator.evaluate("2 ** 3! - !0"), Ok(65));
+        assert_eq!(calculator.evaluate("abs(-4) + neg(2) * signum(-9)"), Ok(6));
+        assert_eq!(calculator.evaluate("square($0 / 12)"), Ok(100));
+        assert_eq!(
+            calculator.evaluate("ans!"),
+            Err(CalcError::Overflow { lhs: 100, rhs: 0, operation: OperationType::Factorial })
+        );
+        assert_eq!(
+            calculator.evaluate("let abs = 1"),
+            Err(CalcError::Parse { column: 5, message: "'abs' cannot be used as a variable name".to_string() })
+        );
+        let expected = "0: 5! = 120\n\
+                        1: 3! = 6\n\
+                        2: neg(6) = -6\n\
+                        3: 3! = 6\n\
+                        4: 6! = 720\n\
+                        5: 3! = 6\n\
+                        6: 2 ** 6 = 64\n\
+                        7: !0 = -1\n\
+                        8: 64 - -1 = 65\n\
+                        9: abs(-4) = 4\n\
+                        10: neg(2) = -2\n\
+                        11: signum(-9) = -1\n\
+                        12: -2 * -1 = 2\n\
+                        13: 4 + 2 = 6\n\
+                        14: $0 / 12 = 10\n\
+                        15: square(10) = 100\n";
+        assert_eq!(calculator.show_history(), expected);
+    }
+
+    #[test]
+    fn other_backends() {
+        let mut calculator = Calculator::<BigInt>::default();
+        let factorial = calculator.factorial(BigInt::from(30)).unwrap();
+        assert_eq!(factorial.to_string(), "265252859812191058636308480000000");
+        assert_eq!(calculator.neg(BigInt::from(i64::MIN)), Ok(-BigInt::from(i64::MIN)));
+        let mut calculator = Calculator::<AutoInt>::default();
+        assert!(calculator.factorial(AutoInt::from(21)).unwrap().is_promoted());
+        assert_eq!(calculator.abs(AutoInt::from(i64::MIN)).map(|value| value.is_promoted()), Ok(true));
+
+        let mut calculator = Calculator::<Rational>::default();
+        let half = Rational::new(-1, 2).unwrap();
+        assert_eq!(calculator.abs(half), Ok(Rational::new(1, 2).unwrap()));
+        assert_eq!(calculator.square(half), Ok(Rational::new(1, 4).unwrap()));
+        assert_eq!(calculator.signum(half), Ok(Rational::from(-1)));
+        assert_eq!(
+            calculator.factorial(Rational::from(3)),
+            Err(CalcError::Unsupported { operation: "factorial".to_string() })
+        );
+
+        let decimal = |text: &str| text.parse::<FixedDecimal>().unwrap();
+        let mut calculator = Calculator::<FixedDecimal>::default();
+        assert_eq!(calculator.square(decimal("1.5")), Ok(decimal("2.2")));
+        assert_eq!(calculator.neg(decimal("0.00")), Ok(decimal("0")));
+        assert!(matches!(calculator.neg(decimal("1.5")), Err(CalcError::Underflow { .. })));
+
+        let mut calculator = Calculator::<i64>::with_modulus(7).unwrap();
+        assert_eq!(calculator.neg(3), Ok(4));
+        assert_eq!(calculator.square(5), Ok(4));
+        assert_eq!(calculator.show_history(), "0: neg(3) = 4 (mod 7)\n1: square(5) = 4 (mod 7)\n");
+        assert_eq!(
+            calculator.factorial(3),
+            Err(CalcError::Unsupported { operation: "factorial in modular mode".to_string() })
+        );
+    }
+
+    #[test]
+    fn round_trip_and_rpn() {
+        let mut rpn = RpnCalculator::new();
+        assert_eq!(rpn.evaluate("5 factorial neg abs signum 9 square"), Ok(Some(81)));
+        assert_eq!(rpn.stack(), &[1, 81]);
+        let calculator = rpn.into_calculator();
+        assert_eq!(calculator.show_history(), "0: 5! = 120\n1: neg(120) = -120\n2: abs(-120) = 120\n3: signum(120) = 1\n4: square(9) = 81\n");
+
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        for format in [Format::Json, Format::Csv] {
+            let text = format_history(&calculator.history, format);
+            assert!(text.contains("factorial"));
+            assert_eq!(parse_history::<i64>(&text, format).unwrap(), expected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod aggregate_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::decimal::*;
+    use crate::persistence::*;
+    use crate::rational::*;
+    use rand::Rng;
+
+    #[test]
+    fn checked_results() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.sum(&[4, -9, 12]), Ok(7));
+        assert_eq!(calculator.product(&[2, 3, -7]), Ok(-42));
+        assert_eq!(calculator.min(&[5, -3, 8]), Ok(-3));
+        assert_eq!(calculator.max(&[5, -3, 8]), Ok(8));
+        assert_eq!(calculator.mean(&[1, 2, 4]), Ok(2));
+        assert_eq!(calculator.median(&[9, 1, 5]), Ok(5));
+        assert_eq!(calculator.sum(&[] as &[i64]), Ok(0));
+        assert_eq!(calculator.product(&[] as &[i64]), Ok(1));
+        let expected = "0: sum(4, -9, 12) = 7\n\
+                        1: product(2, 3, -7) = -42\n\
+                        2: min(5, -3, 8) = -3\n\
+                        3: max(5, -3, 8) = 8\n\
+                        4: mean(1, 2, 4) = 2\n\
+                        5: median(9, 1, 5) = 5\n\
+                        6: sum() = 0\n\
+                        7: product() = 1\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.repeat(1), Ok(-42));
+        assert_eq!(calculator.repeat(5), Ok(5));
+        assert!(calculator.verify_history().is_empty());
+
+        // Long lists are shortened in history but kept whole
+        let values: Vec<i64> = (1..=100).collect();
+        assert_eq!(calculator.sum(&values), Ok(5050));
+        assert_eq!(calculator.history[10].describe(), "sum(1, 2, 3, ... 95 more, 99, 100)");
+        assert_eq!(calculator.repeat(10), Ok(5050));
+    }
+
+    #[test]
+    fn overflow_and_empty_lists() {
+        let mut calculator = Calculator::new();
+        let error = calculator.sum(&[i64::MAX, 1, -1]).unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::AggregateOverflow { operands: vec![i64::MAX, 1, -1], position: Some(1), operation: AggregateOperationType::Sum }
+        );
+        assert_eq!(
+            error.to_string(),
+            "overflow: sum(9223372036854775807, 1, -1) does not fit in i64 at operand 1 (1)"
+        );
+        let error = calculator.product(&[1 << 40, 3, 1 << 30, 0]).unwrap_err();
+        assert!(matches!(error, CalcError::AggregateOverflow { position: Some(2), .. }));
+
+        for operation in [AggregateOperationType::Min, AggregateOperationType::Mean(Rounding::Ceil)] {
+            let error = calculator.calculate_aggregate(operation, &[] as &[i64]).unwrap_err();
+            assert_eq!(error, CalcError::NoOperands { operation });
+        }
+        assert_eq!(calculator.median(&[] as &[i64]).unwrap_err().to_string(), "median needs at least one operand");
+        assert!(calculator.history.is_empty());
+    }
+
+    #[test]
+    fn rounding() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.mean(&[1, 2]), Ok(1));
+        assert_eq!(calculator.mean_ceil(&[1, 2]), Ok(2));
+        assert_eq!(calculator.mean(&[-1, -2]), Ok(-2));
+        assert_eq!(calculator.calculate_aggregate(AggregateOperationType::Mean(Rounding::HalfEven), &[1, 2, 3, 4]), Ok(2));
+        assert_eq!(calculator.median(&[10, 1, 3, 2]), Ok(2));
+        assert_eq!(calculator.median_ceil(&[10, 1, 3, 2]), Ok(3));
+        assert_eq!(calculator.history[5].describe(), "median_ceil(10, 1, 3, 2)");
+
+        // A mean always lies between the smallest and largest value, however large their sum
+        assert_eq!(calculator.mean(&[i64::MAX, i64::MAX]), Ok(i64::MAX));
+        assert_eq!(calculator.mean_ceil(&[i64::MIN, i64::MIN + 1]), Ok(i64::MIN + 1));
+        assert_eq!(calculator.mean(&[i64::MIN, i64::MIN, i64::MAX]), Ok(-3074457345618258603));
+
+        // The two middle values of a median can be too large to add
+        assert_eq!(calculator.median(&[i64::MAX, i64::MAX - 1]), Ok(i64::MAX - 1));
+        assert_eq!(calculator.median_ceil(&[i64::MAX, i64::MAX - 1]), Ok(i64::MAX));
+        assert_eq!(calculator.median(&[i64::MIN, i64::MIN + 1]), Ok(i64::MIN));
+        assert_eq!(calculator.median_ceil(&[i64::MIN, i64::MIN + 3]), Ok(i64::MIN + 2));
+        let half_even = AggregateOperationType::Median(Rounding::HalfEven);
+        assert_eq!(calculator.calculate_aggregate(half_even, &[i64::MAX - 2, i64::MAX - 1]), Ok(i64::MAX - 1));
+        assert_eq!(calculator.calculate_aggregate(half_even, &[i64::MAX - 1, i64::MAX]), Ok(i64::MAX - 1));
+    }
+
+    #[test]
+    fn mean_and_median_match_wider_arithmetic() {
+        let mut rng = rand::thread_rng();
+        let mut calculator = Calculator::new();
+        for _ in 0..1000 {
+            let (a, b): (i64, i64) = (rng.gen(), rng.gen());
+            let sum = a as i128 + b as i128;
+            let floor = sum.div_euclid(2) as i64;
+            let ceil = (sum + 1).div_euclid(2) as i64;
+            let half_even = if sum.rem_euclid(2) == 1 && floor % 2 != 0 { ceil } else { floor };
+            assert_eq!(calculator.median(&[a, b]), Ok(floor));
+            assert_eq!(calculator.median_ceil(&[a, b]), Ok(ceil));
+            assert_eq!(calculator.calculate_aggregate(AggregateOperationType::Median(Rounding::HalfEven), &[a, b]), Ok(half_even));
+            let c: i64 = rng.gen();
+            let sum = sum + c as i128;
+            assert_eq!(calculator.mean(&[a, b, c]), Ok(sum.div_euclid(3) as i64));
+            assert_eq!(calculator.mean_ceil(&[a, b, c]), Ok((sum + 2).div_euclid(3) as i64));
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.evaluate("sum(1, 2, 3) * 2"), Ok(12));
+        assert_eq!(calculator.evaluate("max(4, $0, -1) + min(3!, 2)"), Ok(8));
+        assert_eq!(calculator.evaluate("median(ans, 1, 5, 100)"), Ok(6));
+        assert_eq!(
+            calculator.evaluate("let sum = 1"),
+            Err(CalcError::Parse { column: 5, message: "'sum' cannot be used as a variable name".to_string() })
+        );
+        let expected = "0: sum(1, 2, 3) = 6\n\
+                        1: 6 * 2 = 12\n\
+                        2: max(4, $0, -1) = 6\n\
+                        3: 3! = 6\n\
+                        4: min(6, 2) = 2\n\
+                        5: 6 + 2 = 8\n\
+                        6: median($5, 1, 5, 100) = 6\n";
+        assert_eq!(calculator.show_history(), expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut calculator = Calculator::new();
+        calculator.sum(&[4, -9, 12]).unwrap();
+        calculator.addition(1, 2).unwrap();
+        calculator.median_ceil(&[1, 2]).unwrap();
+        calculator.product(&[] as &[i64]).unwrap();
+
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        for format in [Format::Json, Format::Csv] {
+            let text = format_history(&calculator.history, format);
+            assert!(text.contains("median_ceil"));
+            assert_eq!(parse_history::<i64>(&text, format).unwrap(), expected);
+        }
+        let csv = format_history(&calculator.history, Format::Csv);
+        assert!(csv.starts_with("first_num,second_num,third_num,operation,policy,modulus,variable,operands,result\n"));
+        assert!(csv.contains(",4 -9 12,7\n"));
+        assert!(format_history(&calculator.history, Format::Json).contains("\"operands\": [4, -9, 12]"));
+
+        let invalid = "first_num,second_num,third_num,operation,policy,modulus,variable,operands,result\n1,2,,addition,checked,,,1 2,3\n";
+        assert!(parse_history::<i64>(invalid, Format::Csv).unwrap_err().to_string().contains("takes no operands"));
+    }
+
+    #[test]
+    fn other_backends() {
+        let mut calculator = Calculator::<BigInt>::default();
+        let values = [BigInt::from(i64::MAX), BigInt::from(i64::MAX), BigInt::from(4)];
+        assert_eq!(calculator.product(&values).unwrap().to_string(), "340282366920938463389587631136930004996");
+        assert_eq!(calculator.median(&values), Ok(BigInt::from(i64::MAX)));
+
+        let mut calculator = Calculator::<Rational>::default();
+        let values = [Rational::new(1, 2).unwrap(), Rational::new(1, 3).unwrap()];
+        assert_eq!(calculator.mean(&values), Ok(Rational::new(5, 12).unwrap()));
+        assert_eq!(calculator.min(&values), Ok(Rational::new(1, 3).unwrap()));
+        let max = Rational::from_integer(i128::MAX);
+        assert_eq!(calculator.mean(&[max, Rational::from_integer(i128::MAX - 2)]), Ok(Rational::from_integer(i128::MAX - 1)));
+        // The mean of 1/p and 1/q has the denominator p * q, which is too large for i128
+        let values = [Rational::new(1, (1 << 64) + 1).unwrap(), Rational::new(1, (1 << 64) + 3).unwrap()];
+        let error = calculator.mean(&values).unwrap_err();
+        assert!(matches!(error, CalcError::AggregateOverflow { position: None, .. }));
+        assert_eq!(
+            error.to_string(),
+            "overflow: mean(1/18446744073709551617, 1/18446744073709551619) does not fit in Rational"
+        );
+
+        let decimal = |text: &str| text.parse::<FixedDecimal>().unwrap();
+        let mut calculator = Calculator::<FixedDecimal>::default();
+        let max = FixedDecimal::new(u128::MAX, 2).unwrap();
+        assert_eq!(calculator.mean(&[max, max]), Ok(max));
+        assert_eq!(calculator.mean(&[decimal("1.5"), decimal("0.25")]), Ok(decimal("0.87")));
+        assert_eq!(calculator.mean_ceil(&[decimal("1.5"), decimal("0.25")]), Ok(decimal("0.88")));
+
+        let mut calculator = Calculator::<i64>::with_modulus(7).unwrap();
+        assert_eq!(
+            calculator.sum(&[3, 5]),
+            Err(CalcError::Unsupported { operation: "sum in modular mode".to_string() })
+        );
+    }
+}
+
+#[cfg(test)]
+mod transaction_tests {
+    use crate::calculator::*;
+    use crate::operations::*;
+    use crate::persistence::*;
+
+    #[test]
+    fn commits_as_one_undo_step() {
+        let mut calculator = Calculator::new();
+        calculator.assign("balance", Operand::Value(100)).unwrap();
+        let total = calculator.transaction(|tx| {
+            let fee = tx.assign("fee", Operand::Value(3))?;
+            let net = tx.subtraction(tx.variable("balance")?, fee)?;
+            tx.evaluate("let balance = 97 - fee")?;
+            tx.multiplication(net, 2)
+        });
+        assert_eq!(total, Ok(194));
+        let expected = "0: balance = 100\n\
+                        1: fee = 3\n\
+                        2: 100 - 3 = 97\n\
+                        3: 97 - fee = 94\n\
+                        4: balance = 94\n\
+                        5: 97 * 2 = 194\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.variable("balance"), Ok(94));
+
+        calculator.undo().unwrap();
+        assert_eq!(calculator.history.len(), 1);
+        assert_eq!(calculator.variable("balance"), Ok(100));
+        assert_eq!(calculator.variable("fee"), Err(CalcError::UndefinedVariable { name: "fee".to_string() }));
+        calculator.redo().unwrap();
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.repeat(5), Ok(194));
+        assert_eq!(calculator.history[6].id, Some(6));
+        assert!(calculator.verify_history().is_empty());
+    }
+
+    #[test]
+    fn failure_rolls_everything_back() {
+        let mut calculator = Calculator::new();
+        calculator.assign("balance", Operand::Value(100)).unwrap();
+        calculator.undo().unwrap();
+        calculator.assign("balance", Operand::Value(i64::MAX)).unwrap();
+        let result = calculator.transaction(|tx| {
+            tx.assign("balance", Operand::Value(0))?;
+            tx.set_overflow_policy(OverflowPolicy::Wrapping);
+            tx.set_modulus(Some(7))?;
+            tx.addition(1, 2)?;
+            tx.set_modulus(None)?;
+            tx.set_overflow_policy(OverflowPolicy::Checked);
+            tx.addition(i64::MAX, 1)
+        });
+        let overflow = CalcError::Overflow { lhs: i64::MAX, rhs: 1, operation: OperationType::Addition };
+        let error = result.unwrap_err();
+        assert_eq!(error, CalcError::TransactionFailed { step: 2, error: Box::new(overflow) });
+        assert_eq!(error.to_string(), "transaction rolled back at step 2: overflow: 9223372036854775807 + 1 exceeds i64::MAX");
+
+        // Nothing changed, not even what can be undone or the ids of later entries
+        assert_eq!(calculator.show_history(), "0: balance = 9223372036854775807\n");
+        assert_eq!(calculator.variable("balance"), Ok(i64::MAX));
+        assert_eq!(calculator.overflow_policy(), OverflowPolicy::Checked);
+        assert_eq!(calculator.modulus(), None);
+        assert!(!calculator.can_redo());
+        assert_eq!(calculator.addition(1, 2), Ok(3));
+        assert_eq!(calculator.history[1].id, Some(2));
+        calculator.undo().unwrap();
+        calculator.undo().unwrap();
+        assert!(calculator.history.is_empty());
+    }
+
+    #[test]
+    fn steps_and_settings() {
+        let mut calculator = Calculator::new();
+        let step = |result: Result<i64, CalcError>| match result {
+            Err(CalcError::TransactionFailed { step, .. }) => step,
+            result => panic!("the transaction did not fail: {:?}", result),
+        };
+        // A failing expression records none of its steps
+        assert_eq!(step(calculator.transaction(|tx| tx.evaluate("(1 + 2) * 9223372036854775807"))), 0);
+        assert_eq!(step(calculator.transaction(|tx| tx.evaluate("1 + 2").and_then(|_| tx.evaluate("4 / (2 - 2)")))), 1);
+        assert_eq!(step(calculator.transaction(|tx| tx.undo().map(|_| 0))), 0);
+        assert!(calculator.history.is_empty());
+        assert!(!calculator.can_undo());
+
+        // Settings changed by a successful transaction are kept, and a batch with no operations records nothing
+        assert_eq!(calculator.transaction(|tx| tx.set_modulus(Some(7))), Ok(()));
+        assert_eq!(calculator.modulus(), Some(&7));
+        assert!(!calculator.can_undo());
+        assert_eq!(calculator.transaction(|tx| tx.addition(5, 4)), Ok(2));
+        assert_eq!(calculator.show_history(), "0: 5 + 4 = 2 (mod 7)\n");
+    }
+
+    #[test]
+    fn clearing_inside_a_transaction() {
+        let mut calculator = Calculator::new();
+        calculator.addition(1, 2).unwrap();
+        let result = calculator.transaction(|tx| {
+            tx.clear_history();
+            tx.multiplication(6, 7)
+        });
+        assert_eq!(result, Ok(42));
+        assert_eq!(calculator.show_history(), "0: 6 * 7 = 42\n");
+        calculator.undo().unwrap();
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n");
+        calculator.redo().unwrap();
+        assert_eq!(calculator.show_history(), "0: 6 * 7 = 42\n");
+    }
+
+    // Doubles its left operand, for checking that registrations are rolled back
+    struct Twice;
+
+    impl BinaryOperation<i64> for Twice {
+        fn symbol(&self) -> &str {
+            "twice"
+        }
+
+        fn name(&self) -> &str {
+            "twice"
+        }
+
+        fn perform(&self, x: &i64, _y: &i64) -> Option<i64> {
+            x.checked_mul(2)
+        }
+    }
+
+    #[test]
+    fn failure_reverts_every_kind_of_edit() {
+        let mut calculator = Calculator::new();
+        calculator.addition(1, 2).unwrap();
+        calculator.addition(3, 4).unwrap();
+        calculator.undo().unwrap();
+        let result = calculator.transaction(|tx| {
+            tx.register_operation(Twice)?;
+            tx.calculate_named("twice", Operand::Value(5), Operand::Value(0))?;
+            tx.clear_history();
+            tx.evaluate("let fee = 2 * 3")?;
+            tx.undo()?;
+            tx.set_rounding(Rounding::Ceil);
+            tx.division(1, 0)
+        });
+        assert!(matches!(result, Err(CalcError::TransactionFailed { step: 3, .. })));
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n");
+        assert!(calculator.operations().by_symbol("twice").is_none());
+        assert_eq!(calculator.rounding(), Rounding::HalfEven);
+
+        // What could be undone or redone before is still there
+        calculator.redo().unwrap();
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n1: 3 + 4 = 7\n");
+        calculator.undo().unwrap();
+        calculator.undo().unwrap();
+        assert!(calculator.history.is_empty());
+
+        // A batch that undoes its own edits keeps the rest as one step
+        calculator.addition(1, 2).unwrap();
+        let result = calculator.transaction(|tx| {
+            tx.clear_history();
+            tx.addition(5, 6)?;
+            tx.undo()?;
+            tx.multiplication(6, 7)
+        });
+        assert_eq!(result, Ok(42));
+        assert_eq!(calculator.show_history(), "0: 6 * 7 = 42\n");
+        calculator.undo().unwrap();
+        assert_eq!(calculator.show_history(), "0: 1 + 2 = 3\n");
+        calculator.redo().unwrap();
+        assert_eq!(calculator.show_history(), "0: 6 * 7 = 42\n");
+    }
+
+    #[test]
+    fn loading_counts_as_one_step() {
+        let path = std::env::temp_dir().join(format!("calculator_{}_transaction.json", std::process::id()));
+        let mut saved = Calculator::new();
+        for x in 1..=3 {
+            saved.addition(x, x).unwrap();
+        }
+        saved.save_history(&path, Format::Json).unwrap();
+
+        // The loaded entries take fresh ids, but the load is a single step
+        let mut calculator = Calculator::new();
+        let result = calculator.transaction(|tx| {
+            tx.addition(1, 2)?;
+            tx.load_history(&path).unwrap();
+            tx.addition(i64::MAX, 1)
+        });
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(CalcError::TransactionFailed { step: 2, .. })));
+        assert!(calculator.history.is_empty());
+        assert_eq!(calculator.addition(1, 2), Ok(3));
+        assert_eq!(calculator.history[0].id, Some(0));
+    }
+}
+
+#[cfg(test)]
+mod modular_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::number_theory::*;
+    use crate::persistence::*;
+    use crate::rational::*;
+    use rand::Rng;
+
+    // The largest primes below 2^64 and 2^128
+    const P64: u64 = 18446744073709551557;
+    const P128: u128 = u128::MAX - 158;
+
+    #[test]
+    fn small_modulus() {
+        let mut calculator = Calculator::with_modulus(7).unwrap();
+        assert_eq!(calculator.modulus(), Some(&7));
+        assert_eq!(calculator.addition(5, 4), Ok(2));
+        assert_eq!(calculator.subtraction(2, 5), Ok(4));
+        assert_eq!(calculator.multiplication(3, 5), Ok(1));
+        assert_eq!(calculator.division(3, 5), Ok(2));
+        assert_eq!(calculator.power(3, 6), Ok(1));
+        assert_eq!(calculator.addition(-1, 0), Ok(6));
+        let expected = "0: 5 + 4 = 2 (mod 7)\n\
+                        1: 2 - 5 = 4 (mod 7)\n\
+                        2: 3 * 5 = 1 (mod 7)\n\
+                        3: 3 / 5 = 2 (mod 7)\n\
+                        4: 3 ** 6 = 1 (mod 7)\n\
+                        5: -1 + 0 = 6 (mod 7)\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert!(calculator.verify_history().is_empty());
+        assert_eq!(calculator.show_history_in(Radix::Hex).lines().next(), Some("0: 0x5 + 0x4 = 0x2 (mod 0x7)"));
+    }
+
+    #[test]
+    fn moduli_near_the_type_limit() {
+        let mut calculator = Calculator::<u64>::with_modulus(P64).unwrap();
+        assert_eq!(calculator.multiplication(P64 - 1, P64 - 1), Ok(1));
+        assert_eq!(calculator.addition(P64 - 1, P64 - 1), Ok(P64 - 2));
+        assert_eq!(calculator.addition(u64::MAX, u64::MAX), Ok(2 * (u64::MAX - P64) % P64));
+        assert_eq!(calculator.subtraction(0, u64::MAX), Ok(P64 - (u64::MAX - P64)));
+        assert_eq!(calculator.division(1, 2), Ok(P64.div_ceil(2)));
+        assert_eq!(calculator.power(2, P64 - 1), Ok(1));
+
+        let mut calculator = Calculator::<i64>::with_modulus(i64::MAX).unwrap();
+        assert_eq!(calculator.multiplication(i64::MAX - 1, i64::MAX - 1), Ok(1));
+        assert_eq!(calculator.subtraction(i64::MIN, i64::MAX), Ok(i64::MAX - 1));
+
+        // 128-bit types go through BigInt
+        let mut calculator = Calculator::<u128>::with_modulus(P128).unwrap();
+        assert_eq!(calculator.multiplication(P128 - 1, P128 - 1), Ok(1));
+        assert_eq!(calculator.division(1, 3), Ok(226854911280625642308916404954512140865));
+        assert_eq!(calculator.power(3, P128 - 1), Ok(1));
+    }
+
+    #[test]
+    fn random_inputs_match_u128() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10_000 {
+            let modulus = (rng.gen_range(1..=u64::MAX) >> rng.gen_range(0..63)).max(1);
+            let (x, y) = (rng.gen::<u64>(), rng.gen::<u64>());
+            let (a, b, m) = (x as u128 % modulus as u128, y as u128 % modulus as u128, modulus as u128);
+            let mut calculator = Calculator::<u64>::with_modulus(modulus).unwrap();
+            assert_eq!(calculator.addition(x, y), Ok(((a + b) % m) as u64));
+            assert_eq!(calculator.subtraction(x, y), Ok(((a + m - b) % m) as u64));
+            assert_eq!(calculator.multiplication(x, y), Ok((a * b % m) as u64));
+            assert_eq!(calculator.power(x, y), Ok(mod_pow(x, y, modulus).unwrap()));
+            match mod_inverse(y, modulus) {
+                Some(inverse) => assert_eq!(calculator.division(x, y), Ok((a * inverse as u128 % m) as u64)),
+                None => assert!(calculator.division(x, y).is_err()),
+            }
+        }
+    }
+
+    #[test]
+    fn division_without_inverse() {
+        let mut calculator = Calculator::with_modulus(8).unwrap();
+        assert_eq!(calculator.division(1, 4), Err(CalcError::NotInvertible { value: 4, modulus: 8 }));
+        assert_eq!(
+            calculator.division(3, 16),
+            Err(CalcError::DivisionByZero { lhs: 3, operation: OperationType::Division })
+        );
+        assert_eq!(calculator.division(3, 0).unwrap_err().to_string(), "division by zero: 3 / 0");
+        assert_eq!(calculator.division(3, 5), Ok(7));
+        assert_eq!(calculator.history.len(), 1);
+    }
+
+    #[test]
+    fn invalid_setup_and_unsupported_operations() {
+        for modulus in [0, -5] {
+            assert_eq!(Calculator::with_modulus(modulus).err(), Some(CalcError::InvalidModulus { modulus }));
+        }
+        assert_eq!(CalcError::InvalidModulus { modulus: 0 }.to_string(), "invalid modulus 0: it must be positive");
+        let error = Calculator::<Rational>::with_modulus(Rational::from(7)).err().unwrap();
+        assert_eq!(error.to_string(), "modular arithmetic is not supported for Rational");
+
+        let mut calculator = Calculator::with_modulus(7).unwrap();
+        assert_eq!(calculator.bit_and(1, 2), Err(CalcError::Unsupported { operation: "and in modular mode".to_string() }));
+        assert_eq!(
+            calculator.mul_div(1, 2, 3),
+            Err(CalcError::Unsupported { operation: "mul_div in modular mode".to_string() })
+        );
+        assert!(matches!(calculator.power(2, -1), Err(CalcError::InvalidArgument { .. })));
+        // Exponents are not limited to u32 and are not reduced
+        assert_eq!(calculator.power(2, 1 << 40), Ok(mod_pow(2, 1 << 40, 7).unwrap() as i64));
+        let mut calculator = Calculator::<i64>::with_modulus(1_000_000_007).unwrap();
+        assert_eq!(calculator.power(2, 1 << 40), Ok(819855989));
+    }
+
+    #[test]
+    fn leaving_modular_mode() {
+        let mut calculator = Calculator::with_modulus(7).unwrap();
+        calculator.addition(5, 4).unwrap();
+        calculator.set_modulus(None).unwrap();
+        calculator.addition(5, 4).unwrap();
+        assert_eq!(calculator.show_history(), "0: 5 + 4 = 2 (mod 7)\n1: 5 + 4 = 9\n");
+        // Entries keep the modulus they were computed with
+        assert_eq!(calculator.repeat(0), Ok(2));
+        assert_eq!(calculator.history[2].modulus, Some(7));
+        let calculator = calculator.into_backend::<i128>().unwrap();
+        assert_eq!(calculator.modulus(), None);
+        assert_eq!(calculator.history[0].modulus, Some(7));
+    }
+
+    #[test]
+    fn expressions() {
+        let mut calculator = Calculator::with_modulus(7).unwrap();
+        assert_eq!(calculator.evaluate("3 ** 2 * 4 - 1"), Ok(0));
+        assert_eq!(calculator.evaluate("-(2 + 3)"), Ok(2));
+        assert_eq!(calculator.evaluate("1 / 3 + 1"), Ok(6));
+
+        let mut calculator = Calculator::<BigInt>::with_modulus(BigInt::from(P128)).unwrap();
+        assert_eq!(calculator.evaluate("(0 - 1) * (0 - 1)"), Ok(BigInt::from(1)));
+        let mut calculator = Calculator::<AutoInt>::with_modulus(AutoInt::from(13)).unwrap();
+        assert_eq!(calculator.evaluate("2 ** 12"), Ok(AutoInt::from(1)));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut calculator = Calculator::new();
+        calculator.addition(5, 4).unwrap();
+        calculator.set_modulus(Some(7)).unwrap();
+        calculator.addition(5, 4).unwrap();
+        calculator.division(1, 3).unwrap();
+
+        let csv = format_history(&calculator.history, Format::Csv);
+        assert_eq!(
+            csv,
+            "first_num,second_num,third_num,operation,policy,modulus,result\n\
+             5,4,,addition,checked,,9\n\
+             5,4,,addition,checked,7,2\n\
+             1,3,,division,checked,7,5\n"
+        );
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        for format in [Format::Json, Format::Csv] {
+            let loaded = parse_history::<i64>(&format_history(&calculator.history, format), format).unwrap();
+            assert_eq!(loaded, expected);
+        }
+
+        // The stored modulus is what the result is checked against
+        let text = r#"[{"first_num": 5, "second_num": 4, "operation": "addition", "modulus": 6, "result": 2}]"#;
+        assert!(matches!(parse_history::<i64>(text, Format::Json), Err(HistoryFileError::ResultMismatch { .. })));
+    }
+}
+
+#[cfg(test)]
+mod history_ref_tests {
+    use crate::calculator::*;
+    use crate::persistence::*;
+
+    #[test]
+    fn operands_from_history() {
+        let mut calculator = Calculator::new();
+        calculator.addition(2, 3).unwrap();
+        calculator.multiplication(4, 5).unwrap();
+        let result = calculator.calculate_operands(OperationType::Multiplication, Operand::HistoryRef(1), 7.into());
+        assert_eq!(result, Ok(140));
+        let ans = calculator.ans().unwrap();
+        assert_eq!(ans, Operand::HistoryRef(2));
+        assert_eq!(calculator.resolve(&ans), Ok(140));
+        assert_eq!(calculator.calculate_operands(OperationType::Subtraction, ans, Operand::HistoryRef(0)), Ok(135));
+        assert_eq!(calculator.calculate_operands(OperationType::Isqrt, Operand::HistoryRef(0), 0.into()), Ok(2));
+        let expected = "0: 2 + 3 = 5\n\
+                        1: 4 * 5 = 20\n\
+                        2: $1 * 7 = 140\n\
+                        3: $2 - $0 = 135\n\
+                        4: isqrt($0) = 2\n";
+        assert_eq!(calculator.show_history(), expected);
+        let first = &calculator.history[2].kind.arguments()[0];
+        assert_eq!(first.value, 20);
+        assert_eq!(first.reference, Some(Reference::History(1)));
+        assert!(calculator.verify_history().is_empty());
+    }
+
+    #[test]
+    fn invalid_references() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.ans(), Err(CalcError::InvalidHistoryIndex { index: 0, len: 0 }));
+        calculator.addition(2, 3).unwrap();
+        // An entry cannot refer to itself
+        let result = calculator.calculate_operands(OperationType::Addition, Operand::HistoryRef(1), 1.into());
+        assert_eq!(result, Err(CalcError::InvalidHistoryIndex { index: 1, len: 1 }));
+        assert_eq!(calculator.evaluate("$3 + 1"), Err(CalcError::InvalidHistoryIndex { index: 3, len: 1 }));
+        assert_eq!(calculator.history.len(), 1);
+    }
+
+    #[test]
+    fn expressions() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.evaluate("ans + 1"), Err(CalcError::InvalidHistoryIndex { index: 0, len: 0 }));
+        assert_eq!(calculator.evaluate("2 + 3"), Ok(5));
+        assert_eq!(calculator.evaluate("4 * 5"), Ok(20));
+        assert_eq!(calculator.evaluate("$1 * 7"), Ok(140));
+        // ans is the last result before the expression, not the step just computed
+        assert_eq!(calculator.evaluate("(1 + 1) * ans"), Ok(280));
+        assert_eq!(calculator.evaluate("ans - -$0 + gcd($1, 6)"), Ok(287));
+        assert_eq!(calculator.evaluate("$0"), Ok(5));
+        assert_eq!(calculator.evaluate("-$0 * 2"), Ok(-10));
+        // A negation is a step, so ans is the negated result
+        assert_eq!(calculator.evaluate("-2 ** 2"), Ok(-4));
+        assert_eq!(calculator.evaluate("ans"), Ok(-4));
+        assert_eq!(calculator.evaluate("-($0)"), Ok(-5));
+        assert_eq!(calculator.evaluate("ans"), Ok(-5));
+        let expected = "0: 2 + 3 = 5\n\
+                        1: 4 * 5 = 20\n\
+                        2: $1 * 7 = 140\n\
+                        3: 1 + 1 = 2\n\
+                        4: 2 * $2 = 280\n\
+                        5: neg($0) = -5\n\
+                        6: $4 - -5 = 285\n\
+                        7: gcd($1, 6) = 2\n\
+                        8: 285 + 2 = 287\n\
+                        9: neg($0) = -5\n\
+                        10: -5 * 2 = -10\n\
+                        11: 2 ** 2 = 4\n\
+                        12: neg(4) = -4\n\
+                        13: neg($0) = -5\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert!(calculator.verify_history().is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut calculator = Calculator::new();
+        let error = calculator.evaluate("1 + $").unwrap_err();
+        assert_eq!(error, CalcError::Parse { column: 5, message: "expected a history index after '$'".to_string() });
+        let error = calculator.evaluate("$99999999999999999999999").unwrap_err();
+        assert_eq!(error.to_string(), "parse error at column 1: history index $99999999999999999999999 is too large");
+        assert!(matches!(calculator.evaluate("ans(1)"), Err(CalcError::Parse { column: 4, .. })));
+    }
+
+    #[test]
+    fn undo_and_repeat() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("2 + 3").unwrap();
+        calculator.evaluate("$0 * 7").unwrap();
+        calculator.undo().unwrap();
+        calculator.evaluate("$0 - 1").unwrap();
+        calculator.evaluate("$1 * 10").unwrap();
+        assert_eq!(calculator.show_history(), "0: 2 + 3 = 5\n1: $0 - 1 = 4\n2: $1 * 10 = 40\n");
+        assert_eq!(calculator.repeat(2), Ok(40));
+        assert_eq!(calculator.show_history().lines().last(), Some("3: $1 * 10 = 40"));
+
+        calculator.clear_history();
+        calculator.evaluate("6 * 7").unwrap();
+        calculator.undo().unwrap();
+        calculator.undo().unwrap();
+        assert_eq!(calculator.history[2].describe(), "$1 * 10");
+        assert!(calculator.verify_history().is_empty());
+        calculator.redo().unwrap();
+        assert_eq!(calculator.evaluate("ans"), Err(CalcError::InvalidHistoryIndex { index: 0, len: 0 }));
+    }
+
+    #[test]
+    fn verify_follows_references() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("2 + 3").unwrap();
+        calculator.evaluate("$0 * 7").unwrap();
+        calculator.history[0].result = 6;
+        let mismatches = calculator.verify_history();
+        assert_eq!(mismatches.iter().map(|mismatch| mismatch.index).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(mismatches[1].recomputed, Ok(42));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("2 + 3").unwrap();
+        calculator.evaluate("$0 * $0").unwrap();
+        calculator.calculate_operands(OperationType::Subtraction, 1.into(), Operand::HistoryRef(1)).unwrap();
+
+        let csv = format_history(&calculator.history, Format::Csv);
+        assert_eq!(
+            csv,
+            "first_num,second_num,third_num,operation,policy,result\n\
+             2,3,,addition,checked,5\n\
+             $0,$0,,multiplication,checked,25\n\
+             1,$1,,subtraction,checked,-24\n"
+        );
+        let json = format_history(&calculator.history, Format::Json);
+        assert!(json.contains(r#"{"first_num": "$0", "second_num": "$0", "operation": "multiplication""#));
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        for (text, format) in [(csv, Format::Csv), (json, Format::Json)] {
+            assert_eq!(parse_history::<i64>(&text, format).unwrap(), expected);
+        }
+
+        let text = "first_num,second_num,third_num,operation,policy,result\n$1,3,,addition,checked,5\n";
+        let error = parse_history::<i64>(text, Format::Csv).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: invalid history index 1 (history has 0 entries)");
+        let text = "first_num,second_num,third_num,operation,policy,result\n$x,3,,addition,checked,5\n";
+        let error = parse_history::<i64>(text, Format::Csv).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: first_num '$x' is not a valid history reference");
+        // A reference must reproduce the stored result from the referenced entry
+        let text = "first_num,second_num,third_num,operation,policy,result\n2,3,,addition,checked,5\n$0,1,,addition,checked,7\n";
+        assert!(matches!(parse_history::<i64>(text, Format::Csv), Err(HistoryFileError::ResultMismatch { line: 3, .. })));
+    }
+}
+
+#[cfg(test)]
+mod variable_tests {
+    use crate::calculator::*;
+    use crate::expression::parse;
+    use crate::persistence::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn assign_and_use() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.assign("fee", Operand::Value(25)), Ok(25));
+        let fee = || Operand::Variable("fee".to_string());
+        assert_eq!(calculator.calculate_operands(OperationType::Multiplication, fee(), 3.into()), Ok(75));
+        assert_eq!(calculator.assign("total", Operand::HistoryRef(1)), Ok(75));
+        assert_eq!(calculator.calculate_operands(OperationType::Isqrt, fee(), 0.into()), Ok(5));
+        assert_eq!(calculator.assign("fee", Operand::Value(30)), Ok(30));
+        assert_eq!(calculator.variable("fee"), Ok(30));
+        let expected = "0: fee = 25\n\
+                        1: fee * 3 = 75\n\
+                        2: total = $1 = 75\n\
+                        3: isqrt(fee) = 5\n\
+                        4: fee = 30\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.variables(), BTreeMap::from([("fee".to_string(), 30), ("total".to_string(), 75)]));
+        // Entry 1 still reads the fee assigned before it
+        assert!(calculator.verify_history().is_empty());
+        assert_eq!(calculator.repeat(1), Ok(90));
+    }
+
+    #[test]
+    fn errors() {
+        let mut calculator = Calculator::new();
+        let undefined = CalcError::UndefinedVariable { name: "fee".to_string() };
+        assert_eq!(calculator.variable("fee"), Err(undefined.clone()));
+        let result = calculator.calculate_operands(OperationType::Addition, Operand::Variable("fee".to_string()), 1.into());
+        assert_eq!(result, Err(undefined.clone()));
+        assert_eq!(undefined.to_string(), "undefined variable 'fee'");
+        for name in ["", "2x", "a-b", "let", "ans", "mod", "isqrt", "gcd"] {
+            let error = CalcError::InvalidVariableName { name: name.to_string() };
+            assert_eq!(calculator.assign(name, Operand::Value(1)), Err(error));
+        }
+        assert_eq!(
+            CalcError::<i64>::InvalidVariableName { name: "isqrt".to_string() }.to_string(),
+            "'isqrt' cannot be used as a variable name"
+        );
+        assert!(calculator.history.is_empty());
+    }
+
+    #[test]
+    fn expressions() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.evaluate("let fee = 25"), Ok(25));
+        assert_eq!(calculator.evaluate("let total = fee * 3 + 1"), Ok(76));
+        assert_eq!(calculator.evaluate("let _rate2 = $0"), Ok(25));
+        assert_eq!(calculator.evaluate("total - fee"), Ok(51));
+        assert_eq!(calculator.evaluate("fee"), Ok(25));
+        assert_eq!(calculator.evaluate("gcd(fee, 10) ** 2"), Ok(25));
+        let expected = "0: fee = 25\n\
+                        1: fee * 3 = 75\n\
+                        2: 75 + 1 = 76\n\
+                        3: total = 76\n\
+                        4: _rate2 = $0 = 25\n\
+                        5: total - fee = 51\n\
+                        6: gcd(fee, 10) = 5\n\
+                        7: 5 ** 2 = 25\n";
+        assert_eq!(calculator.show_history(), expected);
+
+        assert_eq!(calculator.evaluate("fees + 1"), Err(CalcError::UndefinedVariable { name: "fees".to_string() }));
+        let error = |input: &str| parse::<i64>(input).unwrap_err().to_string();
+        assert_eq!(error("let = 5"), "parse error at column 5: expected a variable name after let");
+        assert_eq!(error("let isqrt = 5"), "parse error at column 5: 'isqrt' cannot be used as a variable name");
+        assert_eq!(error("let x 5"), "parse error at column 7: expected '=' after let x");
+        assert_eq!(error("1 + let x = 5"), "parse error at column 9: unexpected token");
+        assert_eq!(error("x = 5"), "parse error at column 3: unexpected token");
+    }
+
+    #[test]
+    fn ternary_and_aggregate_operands() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("let fee = 1000").unwrap();
+        let fee = || Operand::Variable("fee".to_string());
+        assert_eq!(calculator.mul_div(fee(), 3, 7), Ok(428));
+        assert_eq!(calculator.sum(&[fee(), Operand::HistoryRef(1), 2.into()]), Ok(1430));
+        assert_eq!(calculator.evaluate("mul_div_ceil(fee, 3, 7)"), Ok(429));
+        assert_eq!(calculator.evaluate("apply_bps($3, 30) + max(fee, ans)"), Ok(1001));
+        let expected = "0: fee = 1000\n\
+                        1: mul_div(fee, 3, 7) = 428\n\
+                        2: sum(fee, $1, 2) = 1430\n\
+                        3: mul_div_ceil(fee, 3, 7) = 429\n\
+                        4: apply_bps($3, 30) = 1\n\
+                        5: max(fee, $3) = 1000\n\
+                        6: 1 + 1000 = 1001\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert!(calculator.verify_history().is_empty());
+
+        let text = format_history(&calculator.history, Format::Csv);
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        assert_eq!(parse_history::<i64>(&text, Format::Csv).unwrap(), expected);
+        let text = format_history(&calculator.history, Format::Json);
+        assert_eq!(parse_history::<i64>(&text, Format::Json).unwrap(), expected);
+
+        let undefined = CalcError::UndefinedVariable { name: "rate".to_string() };
+        assert_eq!(calculator.mul_div(Operand::Variable("rate".to_string()), 3, 7), Err(undefined));
+        let error = |input: &str| parse::<i64>(input).unwrap_err().to_string();
+        assert_eq!(error("mul_div(fee, 3)"), "parse error at column 1: mul_div takes 3 arguments");
+        assert_eq!(error("percent(fee, 3, 7)"), "parse error at column 1: percent takes 2 arguments");
+        assert_eq!(error("let mul_div = 1"), "parse error at column 5: 'mul_div' cannot be used as a variable name");
+    }
+
+    #[test]
+    fn memory_register() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.memory_recall(), 0);
+        assert_eq!(calculator.memory_add(Operand::Value(5)), Ok(5));
+        assert_eq!(calculator.memory_add(Operand::Value(7)), Ok(12));
+        assert_eq!(calculator.memory_subtract(Operand::HistoryRef(0)), Ok(7));
+        assert_eq!(calculator.memory_recall(), 7);
+        assert_eq!(calculator.evaluate("M * 2"), Ok(14));
+        assert_eq!(calculator.memory_clear(), Ok(0));
+        assert_eq!(calculator.memory_subtract(Operand::Value(1)), Ok(-1));
+        let expected = "0: M = 0 + 5 = 5\n\
+                        1: M = M + 7 = 12\n\
+                        2: M = M - $0 = 7\n\
+                        3: M * 2 = 14\n\
+                        4: M = 0\n\
+                        5: M = M - 1 = -1\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert!(calculator.verify_history().is_empty());
+
+        // Memory updates follow the overflow policy
+        calculator.set_overflow_policy(OverflowPolicy::Saturating);
+        calculator.memory_subtract(Operand::Value(i64::MAX)).unwrap();
+        assert_eq!(calculator.memory_subtract(Operand::Value(1)), Ok(i64::MIN));
+    }
+
+    #[test]
+    fn undo_reverts_assignments() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("let fee = 25").unwrap();
+        calculator.evaluate("let fee = 30").unwrap();
+        calculator.memory_add(Operand::Value(3)).unwrap();
+        calculator.undo().unwrap();
+        assert_eq!(calculator.memory_recall(), 0);
+        calculator.undo().unwrap();
+        assert_eq!(calculator.variable("fee"), Ok(25));
+        calculator.clear_history();
+        assert!(calculator.variables().is_empty());
+        calculator.undo().unwrap();
+        calculator.redo().unwrap();
+        calculator.undo().unwrap();
+        assert_eq!(calculator.evaluate("fee + 1"), Ok(26));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("let fee = 25").unwrap();
+        calculator.evaluate("fee * 3").unwrap();
+        calculator.memory_add(Operand::Variable("fee".to_string())).unwrap();
+
+        let csv = format_history(&calculator.history, Format::Csv);
+        assert_eq!(
+            csv,
+            "first_num,second_num,third_num,operation,policy,modulus,variable,result\n\
+             25,0,,assign,checked,,fee,25\n\
+             fee,3,,multiplication,checked,,,75\n\
+             0,fee,,addition,checked,,M,25\n"
+        );
+        let json = format_history(&calculator.history, Format::Json);
+        assert!(json.contains(r#"{"first_num": 25, "second_num": 0, "operation": "assign", "policy": "checked", "variable": "fee", "result": 25}"#));
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        for (text, format) in [(csv, Format::Csv), (json, Format::Json)] {
+            assert_eq!(parse_history::<i64>(&text, format).unwrap(), expected);
+        }
+
+        let text = "first_num,second_num,third_num,operation,policy,modulus,variable,result\n\
+                    fee,3,,multiplication,checked,,,75\n";
+        let error = parse_history::<i64>(text, Format::Csv).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: undefined variable 'fee'");
+        let text = r#"[{"first_num": 1, "second_num": 0, "operation": "assign", "variable": "gcd", "result": 1}]"#;
+        let error = parse_history::<i64>(text, Format::Json).unwrap_err();
+        assert_eq!(error.to_string(), "line 1: 'gcd' is not a valid variable name");
+    }
+}
+
+#[cfg(test)]
+mod rpn_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::rpn::*;
+
+    #[test]
+    fn evaluate_line() {
+        let mut rpn = RpnCalculator::new();
+        assert_eq!(rpn.evaluate("4 9 + 3 *"), Ok(Some(39)));
+        assert_eq!(rpn.stack(), &[39]);
+        assert_eq!(rpn.evaluate("-4 9 - isqrt"), Err(CalcError::InvalidArgument {
+            lhs: -13,
+            rhs: 0,
+            operation: OperationType::Isqrt
+        }));
+        // The failing operation leaves its operand on the stack
+        assert_eq!(rpn.stack(), &[39, -13]);
+        assert_eq!(rpn.evaluate("ilog2 drop"), Err(CalcError::InvalidArgument {
+            lhs: -13,
+            rhs: 0,
+            operation: OperationType::Ilog2
+        }));
+        assert_eq!(rpn.evaluate("drop 0x10 ~ 2 ** 100 10 ilog gcd"), Ok(Some(1)));
+        let expected = "0: 4 + 9 = 13\n\
+                        1: 13 * 3 = 39\n\
+                        2: -4 - 9 = -13\n\
+                        3: !16 = -17\n\
+                        4: -17 ** 2 = 289\n\
+                        5: ilog(100, 10) = 2\n\
+                        6: gcd(289, 2) = 1\n";
+        assert_eq!(rpn.calculator().show_history(), expected);
+        assert_eq!(rpn.stack(), &[39, 1]);
+    }
+
+    #[test]
+    fn stack_commands() {
+        let mut rpn = RpnCalculator::new();
+        assert_eq!(rpn.evaluate("1 2 3 rot"), Ok(Some(1)));
+        assert_eq!(rpn.stack(), &[2, 3, 1]);
+        rpn.swap().unwrap();
+        assert_eq!(rpn.stack(), &[2, 1, 3]);
+        rpn.dup().unwrap();
+        assert_eq!(rpn.stack(), &[2, 1, 3, 3]);
+        rpn.drop().unwrap();
+        assert_eq!(rpn.pop(), Ok(3));
+        assert_eq!(rpn.evaluate("clear"), Ok(None));
+        assert!(rpn.stack().is_empty());
+        assert!(rpn.calculator().history.is_empty());
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let mut rpn = RpnCalculator::new();
+        let underflow = |command: &str, needed, available| CalcError::StackUnderflow {
+            command: command.to_string(),
+            needed,
+            available,
+        };
+        assert_eq!(rpn.pop(), Err(underflow("pop", 1, 0)));
+        assert_eq!(rpn.drop(), Err(underflow("drop", 1, 0)));
+        assert_eq!(rpn.dup(), Err(underflow("dup", 1, 0)));
+        rpn.push(1);
+        assert_eq!(rpn.swap(), Err(underflow("swap", 2, 1)));
+        assert_eq!(rpn.evaluate("2 rot"), Err(underflow("rot", 3, 2)));
+        assert_eq!(rpn.evaluate("+ +"), Err(underflow("+", 2, 1)));
+        assert_eq!(rpn.apply(OperationType::Isqrt), Ok(1));
+        assert_eq!(
+            underflow("*", 2, 1).to_string(),
+            "stack underflow: * needs 2 values, the stack has 1"
+        );
+        assert_eq!(rpn.calculator().show_history(), "0: 1 + 2 = 3\n1: isqrt(3) = 1\n");
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut rpn = RpnCalculator::new();
+        assert_eq!(
+            rpn.evaluate("1 2\t+ \u{e9}t\u{e9} 3"),
+            Err(CalcError::Parse { column: 7, message: "unknown word '\u{e9}t\u{e9}'".to_string() })
+        );
+        assert_eq!(rpn.stack(), &[3]);
+        assert_eq!(rpn.evaluate("="), Err(CalcError::Parse { column: 1, message: "unknown word '='".to_string() }));
+        assert_eq!(rpn.apply(OperationType::Assign), Err(CalcError::Unsupported { operation: "assign in RPN mode".to_string() }));
+    }
+
+    #[test]
+    fn wraps_calculator_settings() {
+        let mut calculator = Calculator::<u8>::with_overflow_policy(OverflowPolicy::Wrapping);
+        calculator.addition(1, 1).unwrap();
+        let mut rpn = RpnCalculator::with_calculator(calculator);
+        assert_eq!(rpn.evaluate("200 100 +"), Ok(Some(44)));
+        let calculator = rpn.into_calculator();
+        assert_eq!(calculator.show_history(), "0: 1 +wrap 1 = 2\n1: 200 +wrap 100 = 44\n");
+
+        let mut rpn = RpnCalculator::with_calculator(Calculator::with_modulus(7).unwrap());
+        assert_eq!(rpn.evaluate("3 5 /"), Ok(Some(2)));
+        let mut rpn = RpnCalculator::<BigInt>::default();
+        assert_eq!(rpn.evaluate("2 100 **"), Ok(Some(BigInt::from(2).pow(100))));
+    }
+}
+
+#[cfg(test)]
+mod registry_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::operations::*;
+    use crate::persistence::*;
+    use crate::rpn::*;
+
+    // The mean rounded toward zero; None when the sum overflows
+    struct Average;
+
+    impl BinaryOperation<i64> for Average {
+        fn symbol(&self) -> &str {
+            "avg"
+        }
+
+        fn name(&self) -> &str {
+            "average"
+        }
+
+        fn perform(&self, x: &i64, y: &i64) -> Option<i64> {
+            x.checked_add(*y).map(|sum| sum / 2)
+        }
+    }
+
+    // Written between its operands, binding like + and -
+    struct AbsDiff;
+
+    impl BinaryOperation<i64> for AbsDiff {
+        fn symbol(&self) -> &str {
+            "absdiff"
+        }
+
+        fn name(&self) -> &str {
+            "absdiff"
+        }
+
+        fn perform(&self, x: &i64, y: &i64) -> Option<i64> {
+            x.checked_sub(*y)?.checked_abs()
+        }
+
+        fn precedence(&self) -> Option<u8> {
+            Some(5)
+        }
+    }
+
+    // A symbol, name and precedence, for testing what register accepts
+    struct Named(&'static str, &'static str, Option<u8>);
+
+    impl BinaryOperation<i64> for Named {
+        fn symbol(&self) -> &str {
+            self.0
+        }
+
+        fn name(&self) -> &str {
+            self.1
+        }
+
+        fn perform(&self, x: &i64, _y: &i64) -> Option<i64> {
+            Some(*x)
+        }
+
+        fn precedence(&self) -> Option<u8> {
+            self.2
+        }
+    }
+
+    fn calculator() -> Calculator {
+        let mut calculator = Calculator::new();
+        calculator.register_operation(Average).unwrap();
+        calculator.register_operation(AbsDiff).unwrap();
+        calculator
+    }
+
+    #[test]
+    fn built_in_operations_are_registered() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.operations().iter().count(), BUILT_IN.len());
+        let addition = calculator.operations().by_symbol("+").unwrap();
+        assert_eq!(addition.operation_type(), Some(OperationType::Addition));
+        assert_eq!(addition.precedence(), Some(5));
+        assert_eq!(calculator.operations().by_name("gcd").unwrap().precedence(), None);
+        assert!(calculator.operations().by_symbol("!").is_none());
+
+        calculator.set_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(calculator.calculate_named("addition", i64::MAX.into(), 1.into()), Ok(i64::MIN));
+        assert_eq!(calculator.calculate_named("pow", Operand::HistoryRef(0), 1.into()), Ok(i64::MIN));
+        assert_eq!(
+            calculator.calculate_named("avg", 1.into(), 2.into()),
+            Err(CalcError::UnknownOperation { name: "avg".to_string() })
+        );
+        // Built-in operations are recorded exactly as if they were called directly
+        assert_eq!(calculator.history[0].operation_type(), Some(OperationType::Addition));
+        assert_eq!(calculator.show_history(), "0: 9223372036854775807 +wrap 1 = -9223372036854775808\n1: $0 **wrap 1 = -9223372036854775808\n");
+    }
+
+    #[test]
+    fn custom_operations_in_history() {
+        let mut calculator = calculator();
+        assert_eq!(calculator.calculate_named("average", 3.into(), 8.into()), Ok(5));
+        assert_eq!(calculator.evaluate("3 absdiff 10 * 2"), Ok(17));
+        assert_eq!(calculator.evaluate("avg($0, 9) absdiff 1"), Ok(6));
+        assert_eq!(calculator.evaluate("absdiff(-2, 2)"), Ok(4));
+        assert_eq!(calculator.repeat(0), Ok(5));
+        let expected = "0: avg(3, 8) = 5\n\
+                        1: 10 * 2 = 20\n\
+                        2: 3 absdiff 20 = 17\n\
+                        3: avg($0, 9) = 7\n\
+                        4: 7 absdiff 1 = 6\n\
+                        5: -2 absdiff 2 = 4\n\
+                        6: avg(3, 8) = 5\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert!(matches!(&calculator.history[0].kind, OperationKind::Registered { operation, .. } if operation.name() == "average"));
+        assert!(calculator.verify_history().is_empty());
+
+        // Registered operations take no overflow policy
+        calculator.set_overflow_policy(OverflowPolicy::Saturating);
+        let overflow = calculator.evaluate("avg(9223372036854775807, 1)");
+        let error = overflow.unwrap_err();
+        assert_eq!(error.to_string(), "no result: avg(9223372036854775807, 1) has no i64 value");
+        assert!(matches!(error, CalcError::NoResult { lhs: i64::MAX, rhs: 1, .. }));
+        assert_eq!(calculator.history.len(), 7);
+    }
+
+    #[test]
+    fn registration_is_validated() {
+        let mut calculator = calculator();
+        let mut register = |symbol, name, precedence| match calculator.register_operation(Named(symbol, name, precedence)) {
+            Err(CalcError::InvalidRegistration { reason, .. }) => reason,
+            result => panic!("{} was registered: {:?}", symbol, result),
+        };
+        assert!(register("avg", "middle", None).contains("already registered"));
+        assert!(register("middle", "average", None).contains("already registered"));
+        assert!(register("plus", "addition", Some(5)).contains("already registered"));
+        assert!(register("+", "plus", Some(5)).contains("identifiers"));
+        assert!(register("let", "bind", None).contains("identifiers"));
+        assert!(register("isqrt", "root", None).contains("identifiers"));
+        assert!(register("mean", "average_of", None).contains("identifiers"));
+        assert!(register("near", "near", Some(0)).contains("precedence"));
+        assert!(register("near", "near", Some(8)).contains("precedence"));
+        calculator.register_operation(Named("first", "first", Some(1))).unwrap();
+        assert_eq!(calculator.evaluate("2 first 3 + 4 | 1"), Ok(3));
+
+        // A registered symbol is no longer a variable name
+        assert_eq!(calculator.assign("avg", 1.into()), Err(CalcError::InvalidVariableName { name: "avg".to_string() }));
+        assert_eq!(
+            calculator.evaluate("let absdiff = 1"),
+            Err(CalcError::Parse { column: 5, message: "'absdiff' cannot be used as a variable name".to_string() })
+        );
+        assert_eq!(
+            calculator.evaluate("avg + 1"),
+            Err(CalcError::Parse { column: 5, message: "expected '(' after avg".to_string() })
+        );
+        assert_eq!(
+            calculator.evaluate("avg(1)"),
+            Err(CalcError::Parse { column: 1, message: "avg takes 2 arguments".to_string() })
+        );
+        // Other calculators only know the built-in operations
+        assert_eq!(
+            Calculator::new().evaluate("3 absdiff 5"),
+            Err(CalcError::Parse { column: 3, message: "unexpected token".to_string() })
+        );
+    }
+
+    #[test]
+    fn modular_and_converted_entries() {
+        let mut calculator = Calculator::with_modulus(7).unwrap();
+        calculator.register_operation(Average).unwrap();
+        assert_eq!(
+            calculator.evaluate("avg(3, 5)"),
+            Err(CalcError::Unsupported { operation: "average in modular mode".to_string() })
+        );
+        calculator.set_modulus(None).unwrap();
+        assert_eq!(calculator.evaluate("avg(3, 5)"), Ok(4));
+
+        // Average is defined for i64 only, so its entries cannot move to another backend
+        let error = calculator.into_backend::<BigInt>().err().unwrap();
+        assert_eq!(error, CalcError::Unsupported { operation: "moving average to another backend".to_string() });
+        assert_eq!(error.to_string(), "moving average to another backend is not supported for i64");
+
+        // Not even when only undo can bring the entry back
+        let mut calculator = Calculator::new();
+        calculator.register_operation(Average).unwrap();
+        calculator.evaluate("avg(3, 5)").unwrap();
+        calculator.undo().unwrap();
+        assert!(calculator.into_backend::<BigInt>().is_err());
+
+        // Registering alone does not block the switch
+        let mut calculator = Calculator::new();
+        calculator.register_operation(Average).unwrap();
+        calculator.addition(3, 5).unwrap();
+        let calculator = calculator.into_backend::<BigInt>().unwrap();
+        assert!(calculator.operations().by_name("average").is_none());
+        assert_eq!(calculator.show_history(), "0: 3 + 5 = 8\n");
+    }
+
+    #[test]
+    fn saved_under_their_name() {
+        let mut calculator = calculator();
+        calculator.evaluate("avg(3, 8)").unwrap();
+        calculator.evaluate("$0 absdiff 5").unwrap();
+        let csv = format_history(&calculator.history, Format::Csv);
+        assert_eq!(csv, "first_num,second_num,third_num,operation,policy,result\n3,8,,average,checked,5\n$0,5,,absdiff,checked,0\n");
+        for (text, format) in [(csv, Format::Csv), (format_history(&calculator.history, Format::Json), Format::Json)] {
+            let parsed = parse_history_with(&text, format, calculator.operations()).unwrap();
+            assert_eq!(parsed.iter().map(Operation::describe).collect::<Vec<_>>(), ["avg(3, 8)", "$0 absdiff 5"]);
+            assert_eq!(parsed[1].kind, calculator.history[1].kind);
+            match parse_history::<i64>(&text, format) {
+                Err(HistoryFileError::Malformed { line: 2, message }) => assert_eq!(message, "unknown operation 'average'"),
+                result => panic!("{:?}", result),
+            }
+        }
+        let text = "first_num,second_num,third_num,operation,policy,result\n3,8,,average,checked,6\n";
+        assert!(matches!(
+            parse_history_with(text, Format::Csv, calculator.operations()),
+            Err(HistoryFileError::ResultMismatch { line: 2, stored: 6, recomputed: Ok(5), .. })
+        ));
+    }
+
+    #[test]
+    fn rpn_words() {
+        let mut rpn = RpnCalculator::with_calculator(calculator());
+        assert_eq!(rpn.evaluate("3 8 avg 9 absdiff"), Ok(Some(4)));
+        assert_eq!(rpn.evaluate("avg"), Err(CalcError::StackUnderflow { command: "avg".to_string(), needed: 2, available: 1 }));
+        let average = rpn.calculator().operations().by_name("average").cloned().unwrap();
+        rpn.push(i64::MAX);
+        assert!(matches!(rpn.apply_registered(&average), Err(CalcError::NoResult { .. })));
+        assert_eq!(rpn.stack(), &[4, i64::MAX]);
+        assert_eq!(rpn.calculator().show_history(), "0: avg(3, 8) = 5\n1: 5 absdiff 9 = 4\n");
+    }
+}
+
+#[cfg(test)]
+mod expression_tests {
+    use crate::calculator::*;
+    use crate::expression::*;
+
+    fn parse_column(input: &str) -> usize {
+        match parse::<i64>(input) {
+            Err(CalcError::Parse { column, .. }) => column,
+            other => panic!("expected a parse error for {:?}, got {:?}", input, other),
+        }
+    }
+
+    #[test]
+    fn evaluate_example() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(calculator.evaluate("(-4 + 9) * 3 - 2"), Ok(13));
+        assert_eq!(calculator.show_history(), "0: -4 + 9 = 5\n1: 5 * 3 = 15\n2: 15 - 2 = 13\n");
+    }
+
+    #[test]
+    fn evaluate_precedence_and_associativity() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(calculator.evaluate("2 + 3 * 4"), Ok(14));
+        assert_eq!(calculator.evaluate("10 - 4 - 3"), Ok(3));
+        assert_eq!(calculator.evaluate("100 / 10 / 5"), Ok(2));
+        assert_eq!(calculator.evaluate("-7 mod 3 + 17 % 5"), Ok(4));
+        assert_eq!(calculator.evaluate("2 * -3"), Ok(-6));
+        assert_eq!(calculator.evaluate("-(2 + 3) * 2"), Ok(-10));
+        assert_eq!(calculator.evaluate("--5"), Ok(5));
+    }
+
+    #[test]
+    fn evaluate_bit_operators() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(calculator.evaluate("1 << 4 | 3"), Ok(19));
+        assert_eq!(calculator.evaluate("1 + 1 << 2"), Ok(8));
+        assert_eq!(calculator.evaluate("6 & 3 ^ 1 | 8"), Ok(11));
+        assert_eq!(calculator.evaluate("-16 >> 2"), Ok(-4));
+        assert_eq!(calculator.evaluate("-1 >>> 60"), Ok(15));
+        assert_eq!(calculator.evaluate("1 rotr 1"), Ok(i64::MIN));
+        assert_eq!(calculator.evaluate("!0"), Ok(-1));
+        assert_eq!(calculator.evaluate("~(1 rotl 2) & 15"), Ok(11));
+
+        calculator.clear_history();
+        calculator.evaluate("!5 & 3").unwrap();
+        assert_eq!(calculator.show_history(), "0: !5 = -6\n1: -6 & 3 = 2\n");
+        assert!(matches!(calculator.evaluate("1 < 2"), Err(CalcError::Parse { column: 3, .. })));
+    }
+
+    #[test]
+    fn evaluate_power_and_functions() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(calculator.evaluate("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(calculator.evaluate("2 * 3 ** 2"), Ok(18));
+        assert_eq!(calculator.evaluate("-2 ** 2"), Ok(-4));
+        assert_eq!(calculator.evaluate("(-2) ** 2"), Ok(4));
+        assert_eq!(calculator.evaluate("2 * -3 ** 2"), Ok(-18));
+        assert_eq!(calculator.evaluate("-2 ** 3 ** 2 + 1"), Ok(-511));
+        assert_eq!(calculator.evaluate("- - 2 ** 2"), Ok(4));
+        assert_eq!(calculator.evaluate("isqrt(17) + ilog(100, 10)"), Ok(6));
+        assert_eq!(calculator.evaluate("ilog2(1 << 40) - icbrt(-8)"), Ok(42));
+
+        calculator.clear_history();
+        calculator.evaluate("isqrt(2 ** 10)").unwrap();
+        assert_eq!(calculator.show_history(), "0: 2 ** 10 = 1024\n1: isqrt(1024) = 32\n");
+
+        let cases = [
+            ("isqrt(1, 2)", 1, "isqrt takes 1 argument"),
+            ("1 + ilog(8)", 5, "ilog takes 2 arguments"),
+            ("foo(1)", 1, "unknown identifier 'foo'"),
+            ("isqrt 4", 7, "expected '(' after isqrt"),
+            ("ilog(8, 2", 10, "expected ')'"),
+        ];
+        for (input, column, message) in cases {
+            assert_eq!(
+                calculator.evaluate(input),
+                Err(CalcError::Parse { column, message: message.to_string() }),
+                "{}",
+                input
+            );
+        }
+        assert!(matches!(calculator.evaluate("2 ** -1"), Err(CalcError::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn evaluate_extreme_literals() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(calculator.evaluate("-9223372036854775808"), Ok(i64::MIN));
+        assert_eq!(calculator.evaluate("9223372036854775807"), Ok(i64::MAX));
+        assert_eq!(parse_column("1 + 9223372036854775808"), 5);
+    }
+
+    #[test]
+    fn evaluate_arithmetic_errors() {
+        let mut calculator = Calculator::new();
+
+        assert_eq!(
+            calculator.evaluate("1 + 9223372036854775807"),
+            Err(CalcError::Overflow { lhs: 1, rhs: i64::MAX, operation: OperationType::Addition })
+        );
+        assert_eq!(
+            calculator.evaluate("(2 + 3) / (4 - 4)"),
+            Err(CalcError::DivisionByZero { lhs: 5, operation: OperationType::Division })
+        );
+        assert!(calculator.evaluate("-(-9223372036854775808)").is_err());
+        assert_eq!(
+            calculator.evaluate("let x = 2 * 3 + 1 / 0"),
+            Err(CalcError::DivisionByZero { lhs: 1, operation: OperationType::Division })
+        );
+
+        // Steps completed before the failure are rolled back, variables included
+        assert_eq!(calculator.show_history(), "");
+        assert!(calculator.variables().is_empty());
+        assert!(!calculator.can_undo());
+
+        // A successful expression is a single undo step
+        assert_eq!(calculator.evaluate("let y = (2 + 3) * 4"), Ok(20));
+        assert_eq!(calculator.history.len(), 3);
+        calculator.undo().unwrap();
+        assert!(calculator.history.is_empty());
+    }
+
+    #[test]
+    fn parse_error_columns() {
+        assert_eq!(parse_column(""), 1);
+        assert_eq!(parse_column("1 +"), 4);
+        assert_eq!(parse_column("(1 + 2"), 7);
+        assert_eq!(parse_column("1 + # 2"), 5);
+        assert_eq!(parse_column("1 2"), 3);
+        assert_eq!(parse_column("3 * foo(2)"), 5);
+        assert_eq!(parse_column(")"), 1);
+
+        let mut calculator = Calculator::new();
+        let error = calculator.evaluate("4 @ 4").unwrap_err();
+        assert_eq!(error.to_string(), "parse error at column 3: unexpected character '@'");
+        assert_eq!(calculator.show_history(), "");
+    }
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use crate::repl;
+
+    fn session(input: &str) -> String {
+        let mut output = Vec::new();
+        repl::run(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn evaluates_lines_and_commands() {
+        let output = session("-4 + 9\n3 * 4\nrepeat 0\nhistory\nquit\n");
+        assert_eq!(output, "> 5\n> 12\n> 5\n> 0: -4 + 9 = 5\n1: 3 * 4 = 12\n2: -4 + 9 = 5\n> ");
+    }
+
+    #[test]
+    fn reports_errors_without_exiting() {
+        let output = session("1 / 0\nrepeat 3\nrepeat\n2 +\n1 + 1\n");
+        let expected = "> error: division by zero: 1 / 0\n\
+                        > error: invalid history index 3 (history has 0 entries)\n\
+                        > error: usage: repeat N\n\
+                        > error: parse error at column 4: unexpected end of input\n\
+                        > 2\n\
+                        > \n";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn clear_and_quit() {
+        let output = session("1 + 2\nclear\nhistory\nquit\n5 * 5\n");
+        assert_eq!(output, "> 3\n> > > ");
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let output = session("1 + 2\nclear\nundo\nhistory\nundo\nundo\nredo\nhistory\n");
+        assert_eq!(output, "> 3\n> > > 0: 1 + 2 = 3\n> > error: nothing to undo\n> > 0: 1 + 2 = 3\n> \n");
+    }
+
+    #[test]
+    fn history_in_hex() {
+        let output = session("255 & 15\nhistory hex\nhistory base64\n");
+        assert_eq!(output, "> 15\n> 0: 0xff & 0xf = 0xf\n> error: unknown radix 'base64'\n> \n");
+    }
+
+    #[test]
+    fn history_references() {
+        let output = session("6 * 7\n$0 + 1\nans * 2\nhistory\n");
+        let expected = "> 42\n\
+                        > 43\n\
+                        > 86\n\
+                        > 0: 6 * 7 = 42\n1: $0 + 1 = 43\n2: $1 * 2 = 86\n\
+                        > \n";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn variables_and_memory() {
+        let output = session("let fee = 25\nfee * 2\nM+ fee + 1\nM- 6\nMR\nvars\nMC\nMR\nnope\n");
+        let expected = "> 25\n\
+                        > 50\n\
+                        > 26\n\
+                        > 20\n\
+                        > 20\n\
+                        > M = 20\nfee = 25\n\
+                        > > 0\n\
+                        > error: undefined variable 'nope'\n\
+                        > \n";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn memory_update_is_one_step() {
+        // A failing update leaves no steps of its expression behind
+        let output = session("let M = 9223372036854775807\nM+ 1 + 1\nhistory\n");
+        let expected = "> 9223372036854775807\n\
+                        > error: overflow: 9223372036854775807 + 2 exceeds i64::MAX\n\
+                        > 0: M = 9223372036854775807\n\
+                        > \n";
+        assert_eq!(output, expected);
+
+        // One undo takes back the expression together with the update
+        let output = session("MC\nM+ 2 * 3\nundo\nMR\nhistory\n");
+        assert_eq!(output, "> > 6\n> > 0\n> 0: M = 0\n> \n");
+    }
+
+    #[test]
+    fn modulus_command() {
+        let output = session("modulus\nmodulus 7\n5 * 3\nhistory\nmodulus 0\nmodulus x\nmodulus off\n5 * 3\nmodulus\n");
+        let expected = "> off\n\
+                        > > 1\n\
+                        > 0: 5 * 3 = 1 (mod 7)\n\
+                        > error: invalid modulus 0: it must be positive\n\
+                        > error: 'x' is not a valid i64 modulus\n\
+                        > > 15\n\
+                        > off\n\
+                        > \n";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn radix_and_twos_complement() {
+        let output = session("radix hex\ntwos 8\n-16 + 0x5\nhistory\ntwos 12\ntwos off\nhistory\nradix\n");
+        let expected = "> > > -11\n\
+                        > 0: 0xf0 + 0x05 = 0xf5\n\
+                        > error: bit width must be 8, 16, 32 or 64\n\
+                        > > 0: -0x10 + 0x5 = -0xb\n\
+                        > hex\n\
+                        > \n";
+        assert_eq!(output, expected);
+    }
+}
+
+#[cfg(test)]
+mod persistence_tests {
+    use crate::calculator::*;
+    use crate::persistence::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("calculator_{}_{}", std::process::id(), name))
+    }
+
+    fn sample_calculator() -> Calculator {
+        let mut calculator = Calculator::new();
+        calculator.addition(-4, 9).unwrap();
+        calculator.division(i64::MIN, 2).unwrap();
+        calculator.euclid_mod(-7, 3).unwrap();
+        calculator
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let path = temp_path("round_trip.json");
+        let calculator = sample_calculator();
+        calculator.save_history(&path, Format::Json).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            text.lines().nth(1),
+            Some(r#"  {"first_num": -4, "second_num": 9, "operation": "addition", "policy": "checked", "result": 5},"#)
+        );
+
+        let mut loaded = Calculator::new();
+        loaded.load_history(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.history, calculator.history);
+        assert_eq!(loaded.show_history(), calculator.show_history());
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let path = temp_path("round_trip.csv");
+        let calculator = sample_calculator();
+        calculator.save_history(&path, Format::Csv).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            text,
+            "first_num,second_num,third_num,operation,policy,result\n\
+             -4,9,,addition,checked,5\n\
+             -9223372036854775808,2,,division,checked,-4611686018427387904\n\
+             -7,3,,euclid_mod,checked,2\n"
+        );
+
+        let mut loaded = Calculator::new();
+        loaded.load_history(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.history, calculator.history);
+    }
+
+    #[test]
+    fn ternary_round_trip() {
+        let mut calculator = Calculator::<u64>::default();
+        calculator.mul_div_ceil(u64::MAX, 3, 7).unwrap();
+        calculator.apply_bps(1_000_000, 30).unwrap();
+        calculator.addition(1, 2).unwrap();
+
+        let json = format_history(&calculator.history, Format::Json);
+        assert!(json.contains(
+            "{\"first_num\": 1000000, \"second_num\": 30, \"third_num\": 10000, \"operation\": \"apply_bps\", \"policy\": \"checked\", \"result\": 3000}"
+        ));
+        let csv = format_history(&calculator.history, Format::Csv);
+        assert!(csv.contains("\n18446744073709551615,3,7,mul_div_ceil,checked,7905747460161236407\n"));
+
+        for (text, format) in [(json, Format::Json), (csv, Format::Csv)] {
+            let loaded = parse_history::<u64>(&text, format).unwrap();
+            assert_eq!(loaded.iter().map(Operation::describe).collect::<Vec<_>>(), [
+                "mul_div_ceil(18446744073709551615, 3, 7)",
+                "apply_bps(1000000, 30)",
+                "1 + 2",
+            ]);
+        }
+
+        let tampered = "first_num,second_num,third_num,operation,policy,result\n10,3,4,mul_div,checked,8\n";
+        let error = parse_history::<u64>(tampered, Format::Csv).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: stored result 8 for mul_div(10, 3, 4) does not match the computed result 7");
+
+        let missing = "first_num,second_num,third_num,operation,policy,result\n10,3,,mul_div,checked,7\n";
+        assert_eq!(
+            parse_history::<u64>(missing, Format::Csv).unwrap_err().to_string(),
+            "line 2: operation 'mul_div' needs a third_num"
+        );
+        let extra = "[{\"first_num\": 1, \"second_num\": 2, \"third_num\": 3, \"operation\": \"addition\", \"result\": 3}]";
+        assert_eq!(
+            parse_history::<u64>(extra, Format::Json).unwrap_err().to_string(),
+            "line 1: operation 'addition' takes no third_num"
+        );
+    }
+
+    #[test]
+    fn empty_history_round_trip() {
+        for format in [Format::Json, Format::Csv] {
+            let text = format_history::<i64>(&[], format);
+            assert!(parse_history::<i64>(&text, format).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn tampered_result_is_rejected() {
+        let text = "first_num,second_num,operation,result\n1,2,addition,3\n-4,9,addition,6\n";
+        let error = parse_history::<i64>(text, Format::Csv).unwrap_err();
+        assert!(matches!(error, HistoryFileError::ResultMismatch { line: 3, stored: 6, recomputed: Ok(5), .. }));
+        assert_eq!(error.to_string(), "line 3: stored result 6 for -4 + 9 does not match the computed result 5");
+
+        let text = "[\n  {\"first_num\": 1, \"second_num\": 0, \"operation\": \"division\", \"result\": 0}\n]";
+        let error = parse_history::<i64>(text, Format::Json).unwrap_err();
+        assert!(matches!(error, HistoryFileError::ResultMismatch { line: 2, recomputed: Err(_), .. }));
+        assert_eq!(error.to_string(), "line 2: stored result 0 for 1 / 0 cannot be computed: division by zero: 1 / 0");
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let cases = [
+            ("first,second\n", Format::Csv, 1),
+            ("first_num,second_num,operation,result\n1,2,power,1\n", Format::Csv, 2),
+            ("first_num,second_num,operation,result\n1,2,addition\n", Format::Csv, 2),
+            ("first_num,second_num,operation,result\n1.5,2,addition,3\n", Format::Csv, 2),
+            ("first_num,second_num,operation,policy,result\n1,2,addition,3\n", Format::Csv, 2),
+            ("first_num,second_num,operation,policy,result\n1,2,addition,clamped,3\n", Format::Csv, 2),
+            ("[\n  {\"first_num\": 1, \"operation\": \"addition\", \"result\": 1}\n]", Format::Json, 2),
+            ("[\n  {\"first_num\": 1,\n", Format::Json, 3),
+            ("{}", Format::Json, 1),
+        ];
+        for (text, format, expected_line) in cases {
+            match parse_history::<i64>(text, format) {
+                Err(HistoryFileError::Malformed { line, .. }) => assert_eq!(line, expected_line, "{:?}", text),
+                other => panic!("expected a malformed error for {:?}, got {:?}", text, other),
+            }
+        }
+
+        // The header error names what was found and every header that is accepted
+        let message = parse_history::<i64>("first,second\n", Format::Csv).unwrap_err().to_string();
+        assert!(message.contains("found the header 'first,second'"), "{}", message);
+        assert!(message.contains("'first_num,second_num,operation,result'"), "{}", message);
+        assert!(message.contains("'first_num,second_num,third_num,operation,policy,modulus,variable,operands,result'"), "{}", message);
+        let message = parse_history::<i64>("", Format::Csv).unwrap_err().to_string();
+        assert!(message.contains("the file is empty, expected one of"), "{}", message);
+    }
+
+    #[test]
+    fn policies_round_trip() {
+        let mut calculator = Calculator::with_overflow_policy(OverflowPolicy::Wrapping);
+        calculator.addition(i64::MAX, 1).unwrap();
+        calculator.calculate_with_policy(OperationType::Multiplication, i64::MAX, 2, OverflowPolicy::Saturating).unwrap();
+        calculator.calculate_with_policy(OperationType::Subtraction, i64::MIN, 1, OverflowPolicy::Overflowing).unwrap();
+
+        let mut expected = calculator.history.clone();
+        expected.iter_mut().for_each(|operation| operation.id = None);
+        for format in [Format::Json, Format::Csv] {
+            let loaded = parse_history(&format_history(&calculator.history, format), format).unwrap();
+            assert_eq!(loaded, expected);
+        }
+
+        // A wrapped result is only valid under the policy it was recorded with
+        let text = "first_num,second_num,operation,policy,result\n\
+                    9223372036854775807,1,addition,saturating,-9223372036854775808\n";
+        let error = parse_history::<i64>(text, Format::Csv).unwrap_err();
+        assert!(matches!(error, HistoryFileError::ResultMismatch { line: 2, recomputed: Ok(i64::MAX), .. }));
+    }
+
+    #[test]
+    fn legacy_files_load_as_checked() {
+        let csv = "first_num,second_num,operation,result\n-4,9,addition,5\n";
+        let json = r#"[{"first_num": -4, "second_num": 9, "operation": "addition", "result": 5}]"#;
+        for (text, format) in [(csv, Format::Csv), (json, Format::Json)] {
+            let history = parse_history::<i64>(text, format).unwrap();
+            assert_eq!(history, vec![Operation::new(-4, 9, OperationType::Addition, 5)]);
+        }
+    }
+
+    #[test]
+    fn failed_load_keeps_history() {
+        let path = temp_path("tampered.txt");
+        std::fs::write(&path, "[{\"first_num\": 1, \"second_num\": 1, \"operation\": \"addition\", \"result\": 3}]").unwrap();
+
+        let mut calculator = sample_calculator();
+        let error = calculator.load_history(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(error, HistoryFileError::ResultMismatch { line: 1, .. }));
+        assert_eq!(calculator.history, sample_calculator().history);
+        assert!(matches!(calculator.load_history(temp_path("missing.json")), Err(HistoryFileError::Io(_))));
+    }
+}
+
+#[cfg(test)]
+mod bigint_tests {
+    use crate::bigint::*;
+    use crate::calculator::*;
+    use crate::persistence::*;
+    use rand::Rng;
+    use std::collections::HashSet;
+
+    fn big(text: &str) -> BigInt {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn arithmetic_matches_i128() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20_000 {
+            let x = rng.gen::<i64>() as i128 * rng.gen::<i32>() as i128;
+            let y = rng.gen::<i64>() as i128 >> rng.gen_range(0..64);
+            let (bx, by) = (BigInt::from(x), BigInt::from(y));
+
+            assert_eq!((&bx + &by).to_i128(), x.checked_add(y));
+            assert_eq!((&bx - &by).to_i128(), x.checked_sub(y));
+            assert_eq!(BigInt::from(x >> 64).checked_div(&by).and_then(|v| v.to_i128()), (x >> 64).checked_div(y));
+            assert_eq!(bx.checked_div(&by).and_then(|v| v.to_i128()), x.checked_div(y));
+            assert_eq!(bx.checked_rem(&by).and_then(|v| v.to_i128()), x.checked_rem(y));
+            assert_eq!(bx.checked_rem_euclid(&by).and_then(|v| v.to_i128()), x.checked_rem_euclid(y));
+            assert_eq!(bx.cmp(&by), x.cmp(&y));
+            assert_eq!(bx.to_string(), x.to_string());
+            assert_eq!(big(&x.to_string()), bx);
+
+            let (a, b) = (rng.gen::<i64>() as i128, rng.gen::<i64>() as i128);
+            assert_eq!((BigInt::from(a) * BigInt::from(b)).to_i128(), Some(a * b));
+        }
+    }
+
+    #[test]
+    fn large_values() {
+        let factor = big("340282366920938463463374607431768211457");
+        let product = &factor * &factor;
+        assert_eq!(product.to_string(), "115792089237316195423570985008687907853950549399482440966384333222776666062849");
+        assert_eq!(product.checked_div(&factor), Some(factor.clone()));
+        assert_eq!(product.checked_rem(&factor), Some(BigInt::zero()));
+        assert_eq!((&product + &BigInt::from(5)).checked_rem(&factor), Some(BigInt::from(5)));
+        assert!(-&product < BigInt::from(i128::MIN));
+        assert_eq!(BigInt::from(u128::MAX).to_u128(), Some(u128::MAX));
+        assert_eq!(BigInt::from(i128::MIN).to_i128(), Some(i128::MIN));
+        assert_eq!(i64::try_from(&big("9223372036854775808")), Err(big("9223372036854775808")));
+        assert_eq!(format!("{:>6}", BigInt::from(-42)), "   -42");
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(big("-0"), BigInt::zero());
+        assert_eq!(big("+17"), BigInt::from(17));
+        assert_eq!(big("000123"), BigInt::from(123));
+        assert!("".parse::<BigInt>().is_err());
+        assert!("-".parse::<BigInt>().is_err());
+        assert!("12a".parse::<BigInt>().is_err());
+        assert!("1_000".parse::<BigInt>().is_err());
+    }
+
+    #[test]
+    fn big_backend() {
+        let mut calculator = Calculator::<BigInt>::default();
+
+        assert_eq!(calculator.addition(i64::MAX.into(), 1.into()), Ok(big("9223372036854775808")));
+        assert_eq!(calculator.multiplication(i64::MIN.into(), i64::MIN.into()), Ok(big("85070591730234615865843651857942052864")));
+        assert_eq!(calculator.division(i64::MIN.into(), (-1).into()), Ok(big("9223372036854775808")));
+        assert_eq!(
+            calculator.division(1.into(), 0.into()),
+            Err(CalcError::DivisionByZero { lhs: BigInt::from(1), operation: OperationType::Division })
+        );
+        assert_eq!(calculator.evaluate("-9223372036854775809 - 1"), Ok(big("-9223372036854775810")));
+
+        let expected = "0: 9223372036854775807 + 1 = 9223372036854775808\n\
+                        1: -9223372036854775808 * -9223372036854775808 = 85070591730234615865843651857942052864\n\
+                        2: -9223372036854775808 / -1 = 9223372036854775808\n\
+                        3: -9223372036854775809 - 1 = -9223372036854775810\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.repeat(0), Ok(big("9223372036854775808")));
+        assert!(calculator.verify_history().is_empty());
+    }
+
+    #[test]
+    fn switch_backend_keeps_history() {
+        let mut calculator = Calculator::new();
+        calculator.addition(-4, 9).unwrap();
+        calculator.multiplication(3, 4).unwrap();
+        calculator.undo().unwrap();
+        assert!(calculator.addition(i64::MAX, 1).is_err());
+
+        let mut calculator = calculator.into_backend::<BigInt>().unwrap();
+        assert_eq!(calculator.addition(i64::MAX.into(), 1.into()), Ok(big("9223372036854775808")));
+        assert_eq!(calculator.show_history(), "0: -4 + 9 = 5\n1: 9223372036854775807 + 1 = 9223372036854775808\n");
+
+        calculator.undo().unwrap();
+        calculator.undo().unwrap();
+        assert_eq!(calculator.show_history(), "");
+    }
+
+    #[test]
+    fn auto_backend_promotes_on_overflow() {
+        let mut calculator = Calculator::<AutoInt>::default();
+
+        let small = calculator.addition(AutoInt::from(2), AutoInt::from(3)).unwrap();
+        assert_eq!(small, AutoInt::from(5));
+
+        let promoted = calculator.addition(AutoInt::from(i64::MAX), AutoInt::from(1)).unwrap();
+        assert!(promoted.is_promoted());
+        assert_eq!(promoted.to_string(), "9223372036854775808");
+
+        // Results that fit again are demoted back to i64
+        let demoted = calculator.subtraction(promoted, AutoInt::from(1)).unwrap();
+        assert_eq!(demoted, AutoInt::from(i64::MAX));
+        assert!(!demoted.is_promoted());
+        // A BigInt that fits is stored as an i64, so equal values are equal and hash alike
+        assert_eq!(AutoInt::from(BigInt::from(5)), AutoInt::from(5));
+        let values = HashSet::from([AutoInt::from(BigInt::from(-7)), AutoInt::from(-7)]);
+        assert_eq!(values.len(), 1);
+
+        assert_eq!(calculator.evaluate("-9223372036854775808 / -1 - 1"), Ok(AutoInt::from(i64::MAX)));
+        assert!(calculator.division(AutoInt::from(1), AutoInt::from(0)).is_err());
+        assert_eq!(
+            calculator.show_history(),
+            "0: 2 + 3 = 5\n\
+             1: 9223372036854775807 + 1 = 9223372036854775808\n\
+             2: 9223372036854775808 - 1 = 9223372036854775807\n\
+             3: -9223372036854775808 / -1 = 9223372036854775808\n\
+             4: 9223372036854775808 - 1 = 9223372036854775807\n"
+        );
+    }
+
+    #[test]
+    fn big_history_round_trip() {
+        let mut calculator = Calculator::<BigInt>::default();
+        calculator.evaluate("99999999999999999999 * 99999999999999999999").unwrap();
+
+        for format in [Format::Json, Format::Csv] {
+            let text = format_history(&calculator.history, format);
+            let loaded = parse_history::<BigInt>(&text, format).unwrap();
+            assert_eq!(loaded[0].result, big("9999999999999999999800000000000000000001"));
+            assert!(parse_history::<i64>(&text, format).is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod rational_tests {
+    use crate::calculator::*;
+    use crate::persistence::*;
+    use crate::rational::*;
+    use rand::Rng;
+
+    fn ratio(numerator: i128, denominator: i128) -> Rational {
+        Rational::new(numerator, denominator).unwrap()
+    }
+
+    #[test]
+    fn normalization() {
+        assert_eq!(ratio(6, 4), ratio(3, 2));
+        assert_eq!(ratio(3, -6), ratio(-1, 2));
+        assert_eq!(ratio(-3, -6).numerator(), 1);
+        assert_eq!(ratio(0, -5), Rational::zero());
+        assert_eq!(ratio(0, -5).denominator(), 1);
+        assert_eq!(ratio(i128::MIN, i128::MIN), Rational::from(1));
+        assert_eq!(ratio(i128::MIN, 2).numerator(), i128::MIN / 2);
+        assert_eq!(Rational::new(1, 0), None);
+        // -i128::MIN does not fit, so the sign cannot move to the numerator
+        assert_eq!(Rational::new(1, i128::MIN), None);
+        assert_eq!(Rational::new(i128::MIN, -1), None);
+    }
+
+    #[test]
+    fn display_and_parse() {
+        assert_eq!(ratio(7, 2).to_string(), "7/2");
+        assert_eq!(ratio(-7, 2).to_string(), "-7/2");
+        assert_eq!(ratio(8, 2).to_string(), "4");
+        assert_eq!("7/2".parse(), Ok(ratio(7, 2)));
+        assert_eq!("14/-4".parse(), Ok(ratio(-7, 2)));
+        assert_eq!("-12".parse(), Ok(Rational::from(-12)));
+        assert_eq!("1/0".parse::<Rational>(), Err(ParseRationalError));
+        assert_eq!("1/2/3".parse::<Rational>(), Err(ParseRationalError));
+        assert_eq!("1.5".parse::<Rational>(), Err(ParseRationalError));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let (x, y) = (ratio(7, 2), ratio(-5, 3));
+        assert_eq!(x.checked_add(&y), Some(ratio(11, 6)));
+        assert_eq!(x.checked_sub(&y), Some(ratio(31, 6)));
+        assert_eq!(x.checked_mul(&y), Some(ratio(-35, 6)));
+        assert_eq!(x.checked_div(&y), Some(ratio(-21, 10)));
+        assert_eq!(x.checked_div(&Rational::zero()), None);
+        // 7/2 = -2 * -5/3 + 1/6
+        assert_eq!(x.checked_rem(&y), Some(ratio(1, 6)));
+        assert_eq!(y.checked_rem(&x), Some(ratio(-5, 3)));
+        assert_eq!(y.checked_rem_euclid(&x), Some(ratio(11, 6)));
+        assert_eq!(ratio(-7, 1).checked_rem_euclid(&ratio(-3, 1)), Some(ratio(2, 1)));
+    }
+
+    #[test]
+    fn random_inputs_match_cross_multiplication() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10_000 {
+            let (a, b) = (rng.gen_range(-1000..=1000i128), rng.gen_range(1..=1000i128));
+            let (c, d) = (rng.gen_range(-1000..=1000i128), rng.gen_range(1..=1000i128));
+            let (x, y) = (ratio(a, b), ratio(c, d));
+
+            assert_eq!(x.checked_add(&y), Rational::new(a * d + c * b, b * d));
+            assert_eq!(x.checked_sub(&y), Rational::new(a * d - c * b, b * d));
+            assert_eq!(x.checked_mul(&y), Rational::new(a * c, b * d));
+            assert_eq!(x.checked_div(&y), Rational::new(a * d, b * c));
+            assert_eq!(x.cmp(&y), (a * d).cmp(&(c * b)));
+            if c != 0 {
+                let remainder = x.checked_rem(&y).unwrap();
+                assert!(remainder.checked_abs().unwrap() < y.checked_abs().unwrap());
+                let euclid = x.checked_rem_euclid(&y).unwrap();
+                assert!(!euclid.is_negative());
+                // x - remainder is a whole multiple of y
+                assert!(x.checked_sub(&euclid).unwrap().checked_div(&y).unwrap().is_integer());
+            }
+        }
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let huge = Rational::from(i128::MAX);
+        let tiny = ratio(1, i128::MAX);
+        assert_eq!(huge.checked_add(&Rational::from(1)), None);
+        assert_eq!(huge.checked_mul(&ratio(3, 2)), None);
+        assert_eq!(tiny.checked_add(&ratio(1, i128::MAX - 1)), None);
+        // Cross-reduction keeps this in range even though MAX * MAX does not fit
+        assert_eq!(huge.checked_mul(&tiny), Some(Rational::from(1)));
+
+        let mut calculator = Calculator::<Rational>::default();
+        assert_eq!(
+            calculator.multiplication(huge, Rational::from(2)),
+            Err(CalcError::Overflow { lhs: huge, rhs: Rational::from(2), operation: OperationType::Multiplication })
+        );
+        assert_eq!(
+            calculator.division(huge, ratio(-1, 2)),
+            Err(CalcError::Underflow { lhs: huge, rhs: ratio(-1, 2), operation: OperationType::Division })
+        );
+        assert_eq!(
+            calculator.calculate_with_policy(OperationType::Addition, huge, huge, OverflowPolicy::Wrapping),
+            Err(CalcError::Overflow { lhs: huge, rhs: huge, operation: OperationType::Addition })
+        );
+        assert_eq!(calculator.show_history(), "");
+    }
+
+    #[test]
+    fn rational_mode() {
+        let mut calculator = Calculator::<Rational>::default();
+        assert_eq!(calculator.division(Rational::from(7), Rational::from(2)), Ok(ratio(7, 2)));
+        assert_eq!(calculator.evaluate("1 / 3 + 1 / 6"), Ok(ratio(1, 2)));
+        assert_eq!(calculator.evaluate("(7 / 2) mod 2"), Ok(ratio(3, 2)));
+        assert_eq!(
+            calculator.evaluate("1 / (2 - 2)"),
+            Err(CalcError::DivisionByZero { lhs: Rational::from(1), operation: OperationType::Division })
+        );
+
+        let expected = "0: 7 / 2 = 7/2\n\
+                        1: 1 / 3 = 1/3\n\
+                        2: 1 / 6 = 1/6\n\
+                        3: 1/3 + 1/6 = 1/2\n\
+                        4: 7 / 2 = 7/2\n\
+                        5: 7/2 mod 2 = 3/2\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert_eq!(calculator.repeat(3), Ok(ratio(1, 2)));
+        assert!(calculator.verify_history().is_empty());
+
+        // An integer history carries over into rational mode
+        let mut calculator = Calculator::new();
+        calculator.division(7, 2).unwrap();
+        let mut calculator = calculator.into_backend::<Rational>().unwrap();
+        assert_eq!(calculator.repeat(0), Ok(ratio(7, 2)));
+        assert_eq!(calculator.show_history(), "0: 7 / 2 = 3\n1: 7 / 2 = 7/2\n");
+    }
+
+    #[test]
+    fn rational_history_round_trip() {
+        let mut calculator = Calculator::<Rational>::default();
+        calculator.evaluate("-7 / 2 * 3").unwrap();
+
+        let json = format_history(&calculator.history, Format::Json);
+        assert!(json.contains("\"first_num\": \"-7/2\", \"second_num\": 3"));
+        for format in [Format::Json, Format::Csv] {
+            let text = format_history(&calculator.history, format);
+            let loaded = parse_history::<Rational>(&text, format).unwrap();
+            assert_eq!(loaded[1].result, ratio(-21, 2));
+        }
+    }
+}
+
+#[cfg(test)]
+mod decimal_tests {
+    use crate::calculator::*;
+    use crate::decimal::*;
+    use crate::persistence::*;
+    use rand::Rng;
+
+    fn dec(text: &str) -> FixedDecimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let sol = dec("1.500000000");
+        assert_eq!((sol.raw(), sol.decimals()), (1_500_000_000, 9));
+        assert_eq!(sol.to_string(), "1.500000000");
+        assert_eq!(FixedDecimal::new(5, 6).unwrap().to_string(), "0.000005");
+        assert_eq!(FixedDecimal::from_units(2, 9).unwrap().to_string(), "2.000000000");
+        assert_eq!(dec("42").to_string(), "42");
+        assert_eq!(dec("1.5"), dec("1.500"));
+        assert!(dec("1.5") < dec("1.51"));
+        for text in ["", ".5", "1.", "1.2.3", "-1.5", "1,5", "1e3"] {
+            assert_eq!(text.parse::<FixedDecimal>(), Err(ParseDecimalError), "{:?}", text);
+        }
+        assert_eq!(FixedDecimal::new(1, 39), None);
+        assert_eq!(FixedDecimal::from_units(10, 38), None);
+    }
+
+    #[test]
+    fn rescaling() {
+        let usdc = dec("2.345678");
+        assert_eq!(usdc.rescale(9, Rounding::Floor).unwrap().to_string(), "2.345678000");
+        assert_eq!(usdc.rescale(2, Rounding::Floor).unwrap().to_string(), "2.34");
+        assert_eq!(usdc.rescale(2, Rounding::Ceil).unwrap().to_string(), "2.35");
+        assert_eq!(usdc.rescale(2, Rounding::HalfEven).unwrap().to_string(), "2.35");
+        // Ties go to the even neighbour
+        assert_eq!(dec("2.5").rescale(0, Rounding::HalfEven), Some(dec("2")));
+        assert_eq!(dec("3.5").rescale(0, Rounding::HalfEven), Some(dec("4")));
+        assert_eq!(dec("2.50001").rescale(0, Rounding::HalfEven), Some(dec("3")));
+        assert_eq!(dec("2.000").rescale(0, Rounding::Ceil), Some(dec("2")));
+        assert_eq!(FixedDecimal::from(u128::MAX).rescale(1, Rounding::Floor), None);
+    }
+
+    #[test]
+    fn arithmetic_and_rounding() {
+        let (amount, fee) = (dec("1.500000000"), dec("0.003"));
+        assert_eq!(amount.checked_mul(&fee, Rounding::HalfEven).unwrap().to_string(), "0.004500000");
+        assert_eq!(dec("1.5").checked_mul(&fee, Rounding::HalfEven), Some(dec("0.004")));
+        assert_eq!(dec("1.5").checked_mul(&fee, Rounding::Ceil), Some(dec("0.005")));
+
+        assert_eq!(dec("1.25").checked_add(&dec("0.005")).unwrap().to_string(), "1.255");
+        assert_eq!(dec("1.25").checked_sub(&dec("1.5")), None);
+        assert_eq!(dec("10").checked_div(&dec("3"), Rounding::Floor), Some(dec("3")));
+        assert_eq!(dec("10.00").checked_div(&dec("3"), Rounding::Floor).unwrap().to_string(), "3.33");
+        assert_eq!(dec("10.00").checked_div(&dec("3"), Rounding::Ceil).unwrap().to_string(), "3.34");
+        assert_eq!(dec("1.00").checked_div(&dec("0.5"), Rounding::Floor).unwrap().to_string(), "2.00");
+        assert_eq!(dec("1").checked_div(&dec("0.000"), Rounding::Floor), None);
+        assert_eq!(dec("7.5").checked_rem(&dec("2")), Some(dec("1.5")));
+
+        // Intermediate products wider than u128 are fine as long as the result fits
+        let large = FixedDecimal::new(u128::MAX / 2, 30).unwrap();
+        assert_eq!(large.checked_mul(&dec("2.000"), Rounding::Floor), FixedDecimal::new(u128::MAX - 1, 30));
+        assert_eq!(large.checked_mul(&dec("3"), Rounding::Floor), None);
+    }
+
+    #[test]
+    fn large_exponents() {
+        assert_eq!(dec("0.00").checked_pow(3_000_000_000, Rounding::Floor).unwrap().to_string(), "0.00");
+        assert_eq!(dec("1.000").checked_pow(u32::MAX, Rounding::Floor).unwrap().to_string(), "1.000");
+        assert_eq!(dec("0.5").checked_pow(3, Rounding::Ceil), Some(dec("0.2")));
+        assert_eq!(dec("0.99").checked_pow(3_000_000_000, Rounding::Floor), None);
+        assert_eq!(dec("2").checked_pow(200, Rounding::Floor), None);
+
+        let mut calculator = Calculator::<FixedDecimal>::default();
+        assert_eq!(calculator.evaluate("0.00 ** 3000000000").map(|value| value.to_string()), Ok("0.00".to_string()));
+        let error = calculator.evaluate("0.99 ** 3000000000").unwrap_err();
+        assert!(matches!(error, CalcError::TooLarge { operation: OperationType::Power, .. }));
+        assert!(matches!(calculator.evaluate("2 ** 200"), Err(CalcError::Overflow { .. })));
+    }
+
+    #[test]
+    fn random_inputs_match_integer_arithmetic() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10_000 {
+            let (x, y) = (rng.gen::<u64>() as u128, rng.gen_range(1..=u32::MAX) as u128);
+            let (a, b) = (FixedDecimal::new(x, 6).unwrap(), FixedDecimal::new(y, 6).unwrap());
+            let scale = 1_000_000u128;
+
+            assert_eq!(a.checked_add(&b).unwrap().raw(), x + y);
+            assert_eq!(a.checked_sub(&b).map(|value| value.raw()), x.checked_sub(y));
+            assert_eq!(a.checked_mul(&b, Rounding::Floor).unwrap().raw(), x * y / scale);
+            assert_eq!(a.checked_mul(&b, Rounding::Ceil).unwrap().raw(), (x * y).div_ceil(scale));
+            assert_eq!(a.checked_div(&b, Rounding::Floor).unwrap().raw(), x * scale / y);
+            assert_eq!(a.checked_div(&b, Rounding::Ceil).unwrap().raw(), (x * scale).div_ceil(y));
+
+            let nearest = a.checked_div(&b, Rounding::HalfEven).unwrap().raw();
+            let twice_remainder = 2 * (x * scale % y);
+            let expected = x * scale / y + (twice_remainder > y || (twice_remainder == y && (x * scale / y) % 2 == 1)) as u128;
+            assert_eq!(nearest, expected);
+        }
+    }
+
+    #[test]
+    fn decimal_mode() {
+        let mut calculator = Calculator::<FixedDecimal>::default();
+        assert_eq!(calculator.evaluate("1.500000000 * 0.003"), Ok(dec("0.0045")));
+        assert_eq!(calculator.evaluate("(100.00 - 0.25) / 3"), Ok(dec("33.25")));
+        assert_eq!(
+            calculator.evaluate("1 - 2.5"),
+            Err(CalcError::Underflow { lhs: dec("1"), rhs: dec("2.5"), operation: OperationType::Subtraction })
+        );
+        assert_eq!(
+            calculator.evaluate("1.5.0"),
+            Err(CalcError::Parse { column: 1, message: "'1.5.0' is not a valid FixedDecimal literal".to_string() })
+        );
+
+        let expected = "0: 1.500000000 * 0.003 = 0.004500000\n\
+                        1: 100.00 - 0.25 = 99.75\n\
+                        2: 99.75 / 3 = 33.25\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert!(calculator.verify_history().is_empty());
+
+        // Integer calculators report a decimal point as a malformed literal
+        assert_eq!(
+            Calculator::new().evaluate("1 + 1.5"),
+            Err(CalcError::Parse { column: 5, message: "'1.5' is not a valid i64 literal".to_string() })
+        );
+    }
+
+    #[test]
+    fn rounding_setting() {
+        let mut calculator = Calculator::<FixedDecimal>::default();
+        assert_eq!(calculator.rounding(), Rounding::HalfEven);
+        calculator.set_rounding(Rounding::Floor);
+        assert_eq!(calculator.evaluate("2.00 / 3"), Ok(dec("0.66")));
+        assert_eq!(calculator.evaluate("1.00 + 2"), Ok(dec("3")));
+        calculator.set_rounding(Rounding::Ceil);
+        assert_eq!(calculator.evaluate("2.00 / 3"), Ok(dec("0.67")));
+        assert_eq!(calculator.evaluate("0.5 ** 3"), Ok(dec("0.2")));
+        assert_eq!(calculator.evaluate("1.5 * 0.25"), Ok(dec("0.38")));
+        calculator.set_rounding(Rounding::HalfEven);
+        assert_eq!(calculator.evaluate("0.5 ** 3"), Ok(dec("0.1")));
+
+        // Each entry keeps the rounding it was computed with
+        let expected = "0: 2.00 / 3 = 0.66 (rounded floor)\n\
+                        1: 1.00 + 2 = 3.00\n\
+                        2: 2.00 / 3 = 0.67 (rounded ceil)\n\
+                        3: 0.5 ** 3 = 0.2 (rounded ceil)\n\
+                        4: 1.5 * 0.25 = 0.38 (rounded ceil)\n\
+                        5: 0.5 ** 3 = 0.1\n";
+        assert_eq!(calculator.show_history(), expected);
+        assert!(calculator.verify_history().is_empty());
+        assert_eq!(calculator.repeat(0), Ok(dec("0.66")));
+        assert_eq!(calculator.history[6].rounding, Rounding::Floor);
+
+        for format in [Format::Json, Format::Csv] {
+            let text = format_history(&calculator.history, format);
+            let loaded = parse_history::<FixedDecimal>(&text, format).unwrap();
+            assert_eq!(loaded.iter().map(|operation| operation.rounding).collect::<Vec<_>>(), [
+                Rounding::Floor,
+                Rounding::HalfEven,
+                Rounding::Ceil,
+                Rounding::Ceil,
+                Rounding::Ceil,
+                Rounding::HalfEven,
+                Rounding::Floor,
+            ]);
+        }
+        let csv = format_history(&calculator.history[..2], Format::Csv);
+        assert_eq!(
+            csv,
+            "first_num,second_num,third_num,operation,policy,modulus,variable,operands,rounding,result\n\
+             2.00,3,,division,checked,,,,floor,0.66\n\
+             1.00,2,,addition,checked,,,,,3.00\n"
+        );
+        let json = format_history(&calculator.history[..1], Format::Json);
+        assert!(json.contains(r#""rounding": "floor""#), "{}", json);
+        match parse_history::<FixedDecimal>(&csv.replace("floor", "down"), Format::Csv) {
+            Err(HistoryFileError::Malformed { line: 2, message }) => assert_eq!(message, "unknown rounding 'down'"),
+            result => panic!("{:?}", result),
+        }
+
+        // Integer division truncates as before, whatever the setting
+        let mut calculator = Calculator::new();
+        calculator.set_rounding(Rounding::Ceil);
+        assert_eq!(calculator.division(7, 2), Ok(3));
+        assert_eq!(calculator.show_history(), "0: 7 / 2 = 3\n");
+    }
+}
+
 #[cfg(test)]
 mod shapes_tests {
     use crate::shapes::*;